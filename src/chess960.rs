@@ -0,0 +1,117 @@
+use super::{ChessState, Color, Piece};
+
+//placements of the two knights among the five squares left after the bishops and queen
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1), (0, 2), (0, 3), (0, 4),
+    (1, 2), (1, 3), (1, 4),
+    (2, 3), (2, 4),
+    (3, 4),
+];
+
+pub struct Chess960;
+
+impl Chess960 {
+    pub const COUNT: u32 = 960;
+
+    //the standard starting position is number 518
+    pub const STANDARD: u32 = 518;
+
+    //white's back rank for start position n, from the a file to the h file
+    pub fn back_rank(n: u32) -> [Piece; 8] {
+        assert!(n < Self::COUNT, "Invalid Chess960 position number.");
+
+        let mut rank = [None; 8];
+        let n = n as usize;
+
+        rank[(n % 4) * 2 + 1] = Some(Piece::Bishop);
+        rank[(n / 4 % 4) * 2] = Some(Piece::Bishop);
+
+        let empty = |rank: &[Option<Piece>; 8]| (0..8).filter(|&x| rank[x].is_none()).collect::<Vec<_>>();
+
+        let queen = empty(&rank)[n / 16 % 6];
+        rank[queen] = Some(Piece::Queen);
+
+        let (first, second) = KNIGHTS[n / 96];
+        let free = empty(&rank);
+        rank[free[first]] = Some(Piece::Knight);
+        rank[free[second]] = Some(Piece::Knight);
+
+        let free = empty(&rank);
+        rank[free[0]] = Some(Piece::Rook);
+        rank[free[1]] = Some(Piece::King);
+        rank[free[2]] = Some(Piece::Rook);
+
+        let mut pieces = [Piece::Pawn; 8];
+        for x in 0..8 {
+            pieces[x] = rank[x].unwrap();
+        }
+
+        pieces
+    }
+
+    pub fn position(n: u32) -> ChessState {
+        let rank = Self::back_rank(n).iter()
+            .map(|piece| piece.letter(Color::White))
+            .collect::<String>();
+
+        let mut state = ChessState::from_fen(&format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            rank.to_ascii_lowercase(),
+            rank
+        ));
+
+        state.chess960 = true;
+        state
+    }
+
+    //the start position number of state, if it is one of the 960 initial positions
+    pub fn number(state: &ChessState) -> Option<u32> {
+        let mut rank = [None; 8];
+        for x in 0..8 {
+            rank[x as usize] = state.piece_at(x);
+        }
+
+        let occupied = |rank: &[Option<Piece>; 8]| (0..8).filter(|&x| rank[x].is_some()).collect::<Vec<_>>();
+        let find = |rank: &[Option<Piece>; 8], piece| (0..8).filter(|&x| rank[x] == Some(piece)).collect::<Vec<_>>();
+
+        let bishops = find(&rank, Piece::Bishop);
+        if bishops.len() != 2 || bishops[0] % 2 == bishops[1] % 2 {
+            return None;
+        }
+
+        let (dark, light) = if bishops[0] % 2 == 0 {
+            (bishops[0], bishops[1])
+        } else {
+            (bishops[1], bishops[0])
+        };
+
+        rank[dark] = None;
+        rank[light] = None;
+
+        let remaining = occupied(&rank);
+        let queen = remaining.iter().position(|&x| rank[x] == Some(Piece::Queen))?;
+        rank[remaining[queen]] = None;
+
+        let remaining = occupied(&rank);
+        let knights = remaining.iter()
+            .enumerate()
+            .filter(|&(_, &x)| rank[x] == Some(Piece::Knight))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        if knights.len() != 2 {
+            return None;
+        }
+
+        let knights = KNIGHTS.iter().position(|&pair| pair == (knights[0], knights[1]))?;
+        let n = (((knights * 6 + queen) * 4 + dark / 2) * 4 + (light - 1) / 2) as u32;
+
+        //the rook, king and rook order and everything off the back rank is checked by comparison
+        let start = Self::position(n);
+        if start.piece_bb == state.piece_bb && start.player_bb == state.player_bb && start.active == state.active {
+            Some(n)
+        } else {
+            None
+        }
+    }
+}
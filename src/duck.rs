@@ -0,0 +1,61 @@
+use super::{parse_pos, BitBoard, ChessState, Color, Move, Piece};
+use super::zobrist::zobrist_keys;
+
+//duck chess: after every move the player also moves the duck to an empty square, where it
+//blocks every piece. there is no check, the game is won by capturing the king
+impl ChessState {
+    /// Squares the duck may be placed on after `action`: every empty square except the one
+    /// it stands on, as the duck has to move each turn.
+    pub fn duck_squares (&self, action: Move) -> BitBoard {
+        let mut after = self.clone();
+        after.apply_move(action);
+        after.occupied().invert()
+    }
+
+    /// Plays `action` and then places the duck on `duck`.
+    pub fn apply_duck_move (&mut self, action: Move, duck: u32) {
+        self.apply_move(action);
+
+        if let Some(old) = self.duck {
+            self.key ^= zobrist_keys.duck[old as usize];
+        }
+        self.key ^= zobrist_keys.duck[duck as usize];
+        self.duck = Some(duck);
+    }
+
+    /// Checks a duck chess move, such as one read from a client.
+    pub fn is_duck_move_legal (&self, action: Move, duck: u32) -> bool {
+        self.duck_winner().is_none()
+            && self.unchecked_moves().contains(&action)
+            && !self.duck_squares(action).empty_at(duck)
+    }
+
+    /// Reads a move in coordinates followed by the duck's square, e.g. "e2e4@d5" or "e7e8q@e2".
+    pub fn parse_duck_move (&self, text: &str) -> Option<(Move, u32)> {
+        let mut parts = text.trim().splitn(2, '@');
+        let action = self.find_among(parts.next()?, &self.unchecked_moves())?;
+        let duck = parse_pos(parts.next()?.trim())?;
+
+        if self.duck_squares(action).empty_at(duck) {
+            None
+        } else {
+            Some((action, duck))
+        }
+    }
+
+    /// The winner once the game is over: the side that captured the enemy king, or the side
+    /// to move if it has no moves at all, as being stalemated wins in duck chess.
+    pub fn duck_winner (&self) -> Option<Color> {
+        for &color in &[Color::White, Color::Black] {
+            if self.pieces_of(color, Piece::King).is_empty() {
+                return Some(color.opposite());
+            }
+        }
+
+        if self.unchecked_moves().is_empty() {
+            Some(self.active)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::{BitBoard, ChessState, Color, Piece, PIECE_TYPE_COUNT, PLAYER_COUNT};
+
+use std::cell::RefCell;
+
+pub const ENCODED_SIZE: usize = 32;
+
+//layout: occupancy (8 bytes), a nibble per occupied square from a1 upwards (16 bytes),
+//flags (1), castling rook files (2), en passant file (1), halfmove clock (2), fullmove number (2)
+const FLAG_BLACK: u8 = 1;
+const FLAG_CHESS960: u8 = 2;
+//marks an absent castling right or en passant square, files otherwise take 3 bits
+const NONE: u8 = 8;
+
+fn nibble(color: Color, piece: Piece) -> u8 {
+    color as u8 * PIECE_TYPE_COUNT as u8 + piece as u8
+}
+
+impl ChessState {
+    /// Packs the position into 32 bytes, for datasets and network messages.
+    /// Only positions with at most 32 pieces fit, which includes every position reachable in a game.
+    /// Duck chess positions have no room for the duck and are not encoded.
+    pub fn encode (&self) -> Option<[u8; ENCODED_SIZE]> {
+        let occupied = self.occupied();
+        if occupied.count() > 32 || self.duck.is_some() {
+            return None;
+        }
+
+        let mut bytes = [0; ENCODED_SIZE];
+        bytes[0..8].copy_from_slice(&occupied.0.to_le_bytes());
+
+        for (i, pos) in occupied.get_indices().enumerate() {
+            let code = nibble(self.color_at(pos)?, self.piece_at(pos)?);
+            bytes[8 + i / 2] |= code << (4 * (i % 2));
+        }
+
+        bytes[24] = match self.active {
+            Color::White => 0,
+            Color::Black => FLAG_BLACK,
+        };
+        if self.chess960 {
+            bytes[24] |= FLAG_CHESS960;
+        }
+
+        let file = |file: Option<u32>| file.map_or(NONE, |file| file as u8);
+        bytes[25] = file(self.castle_ks[0]) | file(self.castle_qs[0]) << 4;
+        bytes[26] = file(self.castle_ks[1]) | file(self.castle_qs[1]) << 4;
+        bytes[27] = file(self.en_passant.map(|bb| bb.solo_pos() % 8));
+
+        bytes[28..30].copy_from_slice(&(self.move_rule.min(u16::MAX as u32) as u16).to_le_bytes());
+        bytes[30..32].copy_from_slice(&(self.fullmove.min(u16::MAX as u32) as u16).to_le_bytes());
+
+        Some(bytes)
+    }
+
+    /// Unpacks a position written by `encode`, or None if the bytes cannot be one.
+    pub fn decode (bytes: &[u8; ENCODED_SIZE]) -> Option<Self> {
+        let mut word = [0; 8];
+        word.copy_from_slice(&bytes[0..8]);
+        let occupied = BitBoard(u64::from_le_bytes(word));
+
+        if occupied.count() > 32 {
+            return None;
+        }
+
+        let mut piece_bb = [BitBoard::new(); PIECE_TYPE_COUNT];
+        let mut player_bb = [BitBoard::new(); PLAYER_COUNT];
+
+        for (i, pos) in occupied.get_indices().enumerate() {
+            let code = (bytes[8 + i / 2] >> (4 * (i % 2))) & 0xF;
+            let color = match code as usize / PIECE_TYPE_COUNT {
+                0 => Color::White,
+                1 => Color::Black,
+                _ => return None,
+            };
+            let piece = Piece::kinds()[code as usize % PIECE_TYPE_COUNT];
+
+            piece_bb[piece as usize] = piece_bb[piece as usize].add_pos(pos);
+            player_bb[color as usize] = player_bb[color as usize].add_pos(pos);
+        }
+
+        for &color in &[Color::White, Color::Black] {
+            if (piece_bb[Piece::King as usize] & player_bb[color as usize]).count() != 1 {
+                return None;
+            }
+        }
+
+        let active = if bytes[24] & FLAG_BLACK == 0 { Color::White } else { Color::Black };
+
+        let file = |bits: u8| match bits & 0xF {
+            NONE => Ok(None),
+            file if file < 8 => Ok(Some(file as u32)),
+            _ => Err(()),
+        };
+
+        let castle_ks = [file(bytes[25]).ok()?, file(bytes[26]).ok()?];
+        let castle_qs = [file(bytes[25] >> 4).ok()?, file(bytes[26] >> 4).ok()?];
+
+        //the en passant square is behind the pawn that just moved two squares
+        let en_passant = file(bytes[27]).ok()?.map(|file| match active {
+            Color::White => BitBoard::from_pos(40 + file),
+            Color::Black => BitBoard::from_pos(16 + file),
+        });
+
+        let mut state = Self {
+            active,
+            piece_bb,
+            player_bb,
+            castle_ks,
+            castle_qs,
+            chess960: bytes[24] & FLAG_CHESS960 != 0,
+            en_passant,
+            move_rule: u16::from_le_bytes([bytes[28], bytes[29]]) as u32,
+            fullmove: u16::from_le_bytes([bytes[30], bytes[31]]) as u32,
+            duck: None,
+            pockets: None,
+            promoted: BitBoard::new(),
+            checks: None,
+            key: 0,
+            move_cache: RefCell::new(None),
+            nnue: None,
+        };
+
+        state.key = state.compute_hash();
+        Some(state)
+    }
+}
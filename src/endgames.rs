@@ -0,0 +1,117 @@
+use super::eval::piece_value;
+use super::kpk;
+use super::material::Endgame;
+use super::shelter::{file_bb, relative_rank};
+use super::{BitBoard, ChessState, Color, Piece, Square, SquareColor};
+
+//a won ending, clearly above any material count but far below the mate scores
+const KNOWN_WIN: i32 = 10_000;
+
+//positions with more pieces than this are never one of the endings below
+const MAX_PIECES: u32 = 6;
+
+fn distance(a: u32, b: u32) -> i32 {
+    let files = (a % 8) as i32 - (b % 8) as i32;
+    let ranks = (a / 8) as i32 - (b / 8) as i32;
+    files.abs().max(ranks.abs())
+}
+
+//the square a pawn of color on pos promotes on
+fn promotion_square(color: Color, pos: u32) -> u32 {
+    match color {
+        Color::White => 56 + pos % 8,
+        Color::Black => pos % 8,
+    }
+}
+
+//how far pos is from the four center squares, 0 to 6
+fn center_distance(pos: u32) -> i32 {
+    let (file, rank) = ((pos % 8) as i32, (pos / 8) as i32);
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+//a queen or rook against the bare king: the lone king is driven to the edge. the material is
+//added so that a pawn about to promote still prefers queening to the won pawn ending
+fn mate_drive(state: &ChessState, strong: Color, piece: Piece) -> i32 {
+    let king = state.king_pos(strong);
+    let lone = state.king_pos(strong.opposite());
+
+    KNOWN_WIN + piece_value(piece) + 20 * center_distance(lone) - 10 * distance(king, lone)
+}
+
+//the lone king is driven into a corner of the bishop's color, where it can be mated
+fn kbnk(state: &ChessState, strong: Color) -> i32 {
+    let bishop = state.pieces_of(strong, Piece::Bishop).solo_pos();
+    let corners: &[u32] = match Square::new(bishop).color() {
+        SquareColor::Dark => &[0, 63],
+        SquareColor::Light => &[7, 56],
+    };
+
+    let king = state.king_pos(strong);
+    let lone = state.king_pos(strong.opposite());
+    let corner = corners.iter().map(|&corner| distance(lone, corner)).min().unwrap_or(0);
+
+    KNOWN_WIN - 50 * corner - 10 * distance(king, lone)
+}
+
+//king and pawn against king, decided by the bitbase. a win is worth more the further the
+//pawn has come, so the search pushes it
+fn kpk(state: &ChessState, strong: Color) -> i32 {
+    let pawn = state.pieces_of(strong, Piece::Pawn).solo_pos();
+
+    if kpk::probe(state) == Some(true) {
+        KNOWN_WIN + 100 * relative_rank(strong, pawn) as i32
+    } else {
+        0
+    }
+}
+
+//a bishop and rook pawns on one file against the bare king, where the bishop cannot cover
+//the promotion square: the defending king sits in the corner and cannot be driven out
+fn wrong_bishop(state: &ChessState, strong: Color) -> bool {
+    let pawns = state.pieces_of(strong, Piece::Pawn);
+    let bishops = state.pieces_of(strong, Piece::Bishop);
+    let weak = strong.opposite();
+
+    let pieces = state.player_bb[strong as usize];
+    let others = pieces & (pawns | bishops | BitBoard::from_pos(state.king_pos(strong))).invert();
+    if !others.is_empty() || bishops.count() != 1 || pawns.is_empty() {
+        return false;
+    }
+
+    if state.player_bb[weak as usize].count() != 1 {
+        return false;
+    }
+
+    let file = pawns.get_indices().next().unwrap_or(0) % 8;
+    if (file != 0 && file != 7) || pawns != (pawns & file_bb(file)) {
+        return false;
+    }
+
+    let queening = promotion_square(strong, pawns.get_indices().next().unwrap_or(0));
+    let wrong = Square::new(bishops.solo_pos()).color() != Square::new(queening).color();
+
+    wrong && distance(state.king_pos(weak), queening) <= 1
+}
+
+/// The score of a known ending from the side to move's point of view, when it should replace
+/// the general evaluation: mating drives in won endings and draws the material hides.
+pub fn evaluate(state: &ChessState) -> Option<i32> {
+    if state.occupied().count() > MAX_PIECES {
+        return None;
+    }
+
+    let (endgame, strong) = state.endgame();
+    let sign = if state.active == strong { 1 } else { -1 };
+
+    let score = match endgame {
+        Endgame::KQK => Some(mate_drive(state, strong, Piece::Queen)),
+        Endgame::KRK => Some(mate_drive(state, strong, Piece::Rook)),
+        Endgame::KBNK => Some(kbnk(state, strong)),
+        Endgame::KPK => Some(kpk(state, strong)),
+        _ if wrong_bishop(state, strong) => Some(0),
+        _ => None,
+    };
+
+    score.map(|score| sign * score)
+}
@@ -0,0 +1,183 @@
+use super::{ChessState, FenError, Move};
+
+use std::fmt;
+
+//a position with opcodes, e.g. a test suite entry such as
+//r1b1k2r/... w KQkq - bm Nxe5; id "WAC.001";
+pub struct Epd {
+    pub state: ChessState,
+    pub ops: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    Fen(FenError),
+    UnterminatedString,
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::Fen(error) => write!(f, "{}", error),
+            EpdError::UnterminatedString => write!(f, "A quoted operand is never closed."),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+//splits the operation part into opcodes with their operands, keeping quoted strings whole
+fn parse_ops(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut ops = Vec::new();
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    ops.push((opcode, words.split_off(0)));
+                }
+            }
+
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                words.push(word);
+            }
+
+            c if c.is_whitespace() => {}
+
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+    }
+
+    //the last operation may leave out its semicolon
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        ops.push((opcode, words));
+    }
+
+    Ok(ops)
+}
+
+//string opcodes are always quoted, like in the usual test suites
+fn quote(opcode: &str, operand: &str) -> String {
+    let comment = opcode.len() == 2 && opcode.starts_with('c') && opcode.as_bytes()[1].is_ascii_digit();
+
+    if opcode == "id" || comment || operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';') {
+        format!("\"{}\"", operand)
+    } else {
+        operand.to_string()
+    }
+}
+
+impl Epd {
+    /// Reads one EPD record. The hmvc and fmvn opcodes set the move counters.
+    pub fn parse(text: &str) -> Result<Self, EpdError> {
+        let text = text.trim();
+
+        //the position is the first four fields, everything after is operations
+        let mut split = text.len();
+        let mut fields = 0;
+        let mut in_field = false;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                if in_field && fields == 4 {
+                    split = i;
+                    break;
+                }
+                in_field = false;
+            } else if !in_field {
+                in_field = true;
+                fields += 1;
+            }
+        }
+
+        let (position, ops) = text.split_at(split);
+        let mut state = ChessState::parse_fen(&format!("{} 0 1", position)).map_err(EpdError::Fen)?;
+        let ops = parse_ops(ops)?;
+
+        for (opcode, operands) in &ops {
+            let counter = operands.first().and_then(|operand| operand.parse().ok());
+
+            match (opcode.as_str(), counter) {
+                ("hmvc", Some(count)) => state.move_rule = count,
+                ("fmvn", Some(count)) => state.fullmove = count,
+                _ => {}
+            }
+        }
+
+        Ok(Self { state, ops })
+    }
+
+    pub fn get(&self, opcode: &str) -> Option<&[String]> {
+        self.ops.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    //replaces the operands of opcode, or appends it
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.ops.iter_mut().find(|(op, _)| op == opcode) {
+            Some(op) => op.1 = operands,
+            None => self.ops.push((opcode.to_string(), operands)),
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("id").and_then(|operands| operands.first()).map(String::as_str)
+    }
+
+    fn moves(&self, opcode: &str) -> Vec<Move> {
+        self.get(opcode).unwrap_or(&[]).iter()
+            .filter_map(|san| self.state.parse_san(san).ok())
+            .collect()
+    }
+
+    pub fn best_moves(&self) -> Vec<Move> {
+        self.moves("bm")
+    }
+
+    pub fn avoid_moves(&self) -> Vec<Move> {
+        self.moves("am")
+    }
+
+    //centipawn evaluation from the side to move's point of view
+    pub fn centipawns(&self) -> Option<i32> {
+        self.get("ce").and_then(|operands| operands.first()).and_then(|ce| ce.parse().ok())
+    }
+
+    pub fn to_epd(&self) -> String {
+        let fen = self.state.to_fen();
+        let mut epd = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+
+        for (opcode, operands) in &self.ops {
+            epd.push(' ');
+            epd.push_str(opcode);
+
+            for operand in operands {
+                epd.push(' ');
+                epd.push_str(&quote(opcode, operand));
+            }
+            epd.push(';');
+        }
+
+        epd
+    }
+}
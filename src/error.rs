@@ -0,0 +1,92 @@
+use super::epd::EpdError;
+use super::pgn::PgnError;
+use super::FenError;
+
+use std::{fmt, io};
+
+/// Everything reading or playing a game can fail with, for callers that handle several
+/// formats at once. Each parser's own error converts into it with `?`.
+#[derive(Debug)]
+pub enum Error {
+    Fen(FenError),
+    //text that is not a legal move in standard algebraic notation
+    San(String),
+    Pgn(PgnError),
+    Epd(EpdError),
+    Io(io::Error),
+    //a move in coordinates, e.g. "e2e4", that is not legal in the position
+    IllegalMove(String),
+    //a network file that is not a supported NNUE network
+    Nnue(String),
+    //an opening book that is neither an opening tree's JSON nor a Polyglot book
+    Book(String),
+    //a UCI command with arguments that do not make sense
+    Uci(String),
+    //a request to lichess.org that failed, or an answer that could not be read
+    #[cfg(feature = "lichess")]
+    Lichess(String),
+    //a WebSocket connection that failed or broke off
+    #[cfg(feature = "websocket")]
+    WebSocket(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Fen(error) => write!(f, "FEN: {}", error),
+            Error::San(san) => write!(f, "{} is not a legal move.", san),
+            Error::Pgn(error) => write!(f, "PGN: {}", error),
+            Error::Epd(error) => write!(f, "EPD: {}", error),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::IllegalMove(text) => write!(f, "{} is not a legal move.", text),
+            Error::Nnue(reason) => write!(f, "NNUE: {}", reason),
+            Error::Book(reason) => write!(f, "Book: {}", reason),
+            Error::Uci(reason) => write!(f, "UCI: {}", reason),
+            #[cfg(feature = "lichess")]
+            Error::Lichess(reason) => write!(f, "Lichess: {}", reason),
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(reason) => write!(f, "WebSocket: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Fen(error) => Some(error),
+            Error::Pgn(error) => Some(error),
+            Error::Epd(error) => Some(error),
+            Error::Io(error) => Some(error),
+            Error::San(_) | Error::IllegalMove(_) | Error::Nnue(_) | Error::Book(_)
+            | Error::Uci(_) => None,
+            #[cfg(feature = "lichess")]
+            Error::Lichess(_) => None,
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(_) => None,
+        }
+    }
+}
+
+impl From<FenError> for Error {
+    fn from(error: FenError) -> Self {
+        Error::Fen(error)
+    }
+}
+
+impl From<PgnError> for Error {
+    fn from(error: PgnError) -> Self {
+        Error::Pgn(error)
+    }
+}
+
+impl From<EpdError> for Error {
+    fn from(error: EpdError) -> Self {
+        Error::Epd(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,409 @@
+use super::endgames;
+use super::pawns::{self, PawnTable};
+use super::{ChessState, Color, Piece};
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+//piece-square tables in centipawns, as seen from white with rank 8 on top, so a1 is index 56
+const PAWN: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+//in the middlegame the king stays sheltered behind its pawns
+const KING: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+//in the endgame pawns are worth more the closer they are to promoting
+const PAWN_ENDGAME: [i32; 64] = [
+      0,  0,  0,  0,  0,  0,  0,  0,
+     80, 80, 80, 80, 80, 80, 80, 80,
+     50, 50, 50, 50, 50, 50, 50, 50,
+     30, 30, 30, 30, 30, 30, 30, 30,
+     15, 15, 15, 15, 15, 15, 15, 15,
+      5,  5,  5,  5,  5,  5,  5,  5,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+//and the king comes to the center to support them
+const KING_ENDGAME: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+//every table by index, with the name it has here
+const TABLES: [(&str, &[i32; 64]); 8] = [
+    ("PAWN", &PAWN),
+    ("KNIGHT", &KNIGHT),
+    ("BISHOP", &BISHOP),
+    ("ROOK", &ROOK),
+    ("QUEEN", &QUEEN),
+    ("KING", &KING),
+    ("PAWN_ENDGAME", &PAWN_ENDGAME),
+    ("KING_ENDGAME", &KING_ENDGAME),
+];
+
+//how much each piece counts toward the middlegame, all of them on the board make PHASE_TOTAL
+const PHASE_TOTAL: i32 = 24;
+
+fn phase_weight(piece: Piece) -> i32 {
+    match piece {
+        Piece::Knight | Piece::Bishop => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+        Piece::Pawn | Piece::King => 0,
+    }
+}
+
+pub const fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+//minor pieces lose a little against pawns as the board empties, rooks gain
+const fn endgame_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 120,
+        Piece::Knight => 300,
+        Piece::Bishop => 320,
+        Piece::Rook => 530,
+        Piece::Queen => 930,
+        Piece::King => 0,
+    }
+}
+
+//middlegame and endgame tables, as indices into TABLES
+fn tables(piece: Piece) -> (usize, usize) {
+    match piece {
+        Piece::Pawn => (0, 6),
+        Piece::Knight => (1, 1),
+        Piece::Bishop => (2, 2),
+        Piece::Rook => (3, 3),
+        Piece::Queen => (4, 4),
+        Piece::King => (5, 7),
+    }
+}
+
+//the index into a table of a piece of color on pos
+fn table_index(color: Color, pos: u32) -> usize {
+    let index = match color {
+        Color::White => pos ^ 56,
+        Color::Black => pos,
+    };
+    index as usize
+}
+
+//what a piece of color on pos is worth in the middlegame and in the endgame,
+//its material and where it stands
+fn piece_square(piece: Piece, color: Color, pos: u32) -> (i32, i32) {
+    let index = table_index(color, pos);
+    let (middlegame, endgame) = tables(piece);
+    let (mg, eg) = material(piece);
+    (mg + TABLES[middlegame].1[index], eg + TABLES[endgame].1[index])
+}
+
+/// A weight of the evaluation that can be set while the engine runs, to try other styles of
+/// play without building it again.
+pub struct Weight {
+    pub name: &'static str,
+    pub default: i32,
+    value: AtomicI32,
+}
+
+impl Weight {
+    const fn new(name: &'static str, default: i32) -> Self {
+        Self { name, default, value: AtomicI32::new(default) }
+    }
+
+    fn get(&self) -> i32 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+//the material of the pieces but the king in the middlegame, then in the endgame, then the pawn
+//terms, each in the middlegame and in the endgame
+static WEIGHTS: [Weight; 18] = [
+    Weight::new("PawnValue", piece_value(Piece::Pawn)),
+    Weight::new("KnightValue", piece_value(Piece::Knight)),
+    Weight::new("BishopValue", piece_value(Piece::Bishop)),
+    Weight::new("RookValue", piece_value(Piece::Rook)),
+    Weight::new("QueenValue", piece_value(Piece::Queen)),
+    Weight::new("PawnEndgameValue", endgame_value(Piece::Pawn)),
+    Weight::new("KnightEndgameValue", endgame_value(Piece::Knight)),
+    Weight::new("BishopEndgameValue", endgame_value(Piece::Bishop)),
+    Weight::new("RookEndgameValue", endgame_value(Piece::Rook)),
+    Weight::new("QueenEndgameValue", endgame_value(Piece::Queen)),
+    Weight::new("DoubledPawn", pawns::terms()[0].1.0),
+    Weight::new("DoubledPawnEndgame", pawns::terms()[0].1.1),
+    Weight::new("IsolatedPawn", pawns::terms()[1].1.0),
+    Weight::new("IsolatedPawnEndgame", pawns::terms()[1].1.1),
+    Weight::new("BackwardPawn", pawns::terms()[2].1.0),
+    Weight::new("BackwardPawnEndgame", pawns::terms()[2].1.1),
+    Weight::new("ConnectedPawn", pawns::terms()[3].1.0),
+    Weight::new("ConnectedPawnEndgame", pawns::terms()[3].1.1),
+];
+
+/// The weights `set_weight` can change.
+pub fn weights() -> &'static [Weight] {
+    &WEIGHTS
+}
+
+/// Sets the weight called `name`, in any case, returning false when there is none.
+pub fn set_weight(name: &str, value: i32) -> bool {
+    match WEIGHTS.iter().find(|weight| weight.name.eq_ignore_ascii_case(name)) {
+        Some(weight) => {
+            weight.value.store(value, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+//what a piece is worth in the middlegame and in the endgame with the weights as they are set
+fn material(piece: Piece) -> (i32, i32) {
+    match VALUED.iter().position(|&valued| valued == piece) {
+        Some(i) => (WEIGHTS[i].get(), WEIGHTS[VALUED.len() + i].get()),
+        None => (0, 0),
+    }
+}
+
+/// The middlegame and endgame value of the pawn structure term at `term`, in the order of
+/// `pawns::terms`, with the weights as they are set.
+pub fn pawn_term(term: usize) -> (i32, i32) {
+    let start = 2 * VALUED.len();
+    (WEIGHTS[start + 2 * term].get(), WEIGHTS[start + 2 * term + 1].get())
+}
+
+/// How far the game is from the endgame, from PHASE_TOTAL with every piece on the board down
+/// to 0 with only kings and pawns left.
+pub fn phase(state: &ChessState) -> i32 {
+    let phase: i32 = Piece::kinds().iter()
+        .map(|&piece| state.piece_bb[piece as usize].count() as i32 * phase_weight(piece))
+        .sum();
+
+    phase.min(PHASE_TOTAL)
+}
+
+/// Static evaluation in centipawns from the side to move's point of view: material,
+/// piece-square tables and pawn structure, each scored for the middlegame and the endgame
+/// and blended by `phase`. Known endings are scored by what they are instead.
+pub fn evaluate(state: &ChessState) -> i32 {
+    endgames::evaluate(state).unwrap_or_else(|| {
+        let white = state.pieces_of(Color::White, Piece::Pawn);
+        let black = state.pieces_of(Color::Black, Piece::Pawn);
+        blend(state, pawns::pawn_structure(white, black))
+    })
+}
+
+/// `evaluate`, looking the pawn structure up in `table` first.
+pub fn evaluate_cached(state: &ChessState, table: &mut PawnTable) -> i32 {
+    endgames::evaluate(state).unwrap_or_else(|| blend(state, table.score(state)))
+}
+
+//pawn structure is from white's point of view
+fn blend(state: &ChessState, pawn_structure: (i32, i32)) -> i32 {
+    let sign = if state.active == Color::White { 1 } else { -1 };
+    let mut middlegame = sign * pawn_structure.0;
+    let mut endgame = sign * pawn_structure.1;
+
+    for &color in &[Color::White, Color::Black] {
+        let sign = if color == state.active { 1 } else { -1 };
+
+        for &piece in Piece::kinds() {
+            for pos in state.pieces_of(color, piece).get_indices() {
+                let (mg, eg) = piece_square(piece, color, pos);
+                middlegame += sign * mg;
+                endgame += sign * eg;
+            }
+        }
+    }
+
+    let phase = phase(state);
+    (middlegame * phase + endgame * (PHASE_TOTAL - phase)) / PHASE_TOTAL
+}
+
+//tuning sees the evaluation as a weighted sum of its parameters, laid out as the middlegame
+//then the endgame values of the pieces but the king, the tables, then the pawn terms
+const VALUED: [Piece; 5] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+const TABLES_START: usize = 2 * VALUED.len();
+const TERMS_START: usize = TABLES_START + 64 * TABLES.len();
+
+/// Every parameter of the evaluation as it is now, in the order of `tuning_coefficients`.
+pub fn tuning_parameters() -> Vec<i32> {
+    let mut parameters: Vec<i32> = VALUED.iter().map(|&piece| piece_value(piece)).collect();
+    parameters.extend(VALUED.iter().map(|&piece| endgame_value(piece)));
+
+    for (_, table) in TABLES.iter() {
+        parameters.extend(table.iter());
+    }
+
+    for (_, (mg, eg)) in pawns::terms().iter() {
+        parameters.push(*mg);
+        parameters.push(*eg);
+    }
+
+    parameters
+}
+
+/// What each parameter is multiplied by in the evaluation of `state` from white's point of
+/// view, before it is rounded, as pairs of parameter index and coefficient. None for known
+/// endings, which are not scored by the parameters.
+pub fn tuning_coefficients(state: &ChessState) -> Option<Vec<(usize, f64)>> {
+    if endgames::evaluate(state).is_some() {
+        return None;
+    }
+
+    let phase = phase(state) as f64 / PHASE_TOTAL as f64;
+    let mut coefficients = Vec::new();
+
+    for &color in &[Color::White, Color::Black] {
+        let sign = if color == Color::White { 1.0 } else { -1.0 };
+
+        for &piece in Piece::kinds() {
+            let (middlegame, endgame) = tables(piece);
+            let valued = VALUED.iter().position(|&valued| valued == piece);
+
+            for pos in state.pieces_of(color, piece).get_indices() {
+                let index = table_index(color, pos);
+                coefficients.push((TABLES_START + 64 * middlegame + index, sign * phase));
+                coefficients.push((TABLES_START + 64 * endgame + index, sign * (1.0 - phase)));
+
+                if let Some(valued) = valued {
+                    coefficients.push((valued, sign * phase));
+                    coefficients.push((VALUED.len() + valued, sign * (1.0 - phase)));
+                }
+            }
+        }
+    }
+
+    let white = state.pieces_of(Color::White, Piece::Pawn);
+    let black = state.pieces_of(Color::Black, Piece::Pawn);
+    for (term, &count) in pawns::term_counts(white, black).iter().enumerate() {
+        if count != 0 {
+            coefficients.push((TERMS_START + 2 * term, count as f64 * phase));
+            coefficients.push((TERMS_START + 2 * term + 1, count as f64 * (1.0 - phase)));
+        }
+    }
+
+    Some(coefficients)
+}
+
+/// Each piece's piece-square table in the middlegame and in the endgame, as the piece, the
+/// phase's name and the table, read from `parameters` laid out like `tuning_parameters`, e.g.
+/// the tuner's. Pieces with a single table for both phases have it twice.
+pub fn piece_square_tables(parameters: &[i32]) -> Vec<(Piece, &'static str, &[i32])> {
+    let table = |i: usize| &parameters[TABLES_START + 64 * i..TABLES_START + 64 * (i + 1)];
+
+    Piece::kinds().iter().flat_map(|&piece| {
+        let (middlegame, endgame) = tables(piece);
+        vec![(piece, "middlegame", table(middlegame)), (piece, "endgame", table(endgame))]
+    }).collect()
+}
+
+/// Rust source for `parameters`, laid out like the constants of eval.rs and pawns.rs that
+/// they replace.
+pub fn format_parameters(parameters: &[i32]) -> String {
+    let mut source = String::new();
+
+    for (name, start) in &[("piece_value", 0), ("endgame_value", VALUED.len())] {
+        source += &format!("//{}\n", name);
+        for (i, piece) in VALUED.iter().enumerate() {
+            source += &format!("Piece::{:?} => {},\n", piece, parameters[start + i]);
+        }
+        source += "\n";
+    }
+
+    for (i, (name, _)) in TABLES.iter().enumerate() {
+        let table = &parameters[TABLES_START + 64 * i..TABLES_START + 64 * (i + 1)];
+        //columns are as wide as their widest value, but at least three after the first
+        let width = |column: usize, least: usize| {
+            table.iter().skip(column).step_by(8).map(|value| value.to_string().len()).max().unwrap_or(0).max(least)
+        };
+        let widths: Vec<usize> = (0..8).map(|column| width(column, if column == 0 { 2 } else { 3 })).collect();
+
+        source += &format!("const {}: [i32; 64] = [\n", name);
+        for row in table.chunks(8) {
+            let row: Vec<String> = row.iter().zip(&widths).map(|(value, &width)| format!("{:>1$}", value, width)).collect();
+            source += &format!("    {},\n", row.join(","));
+        }
+        source += "];\n\n";
+    }
+
+    for (i, (name, _)) in pawns::terms().iter().enumerate() {
+        let (mg, eg) = (parameters[TERMS_START + 2 * i], parameters[TERMS_START + 2 * i + 1]);
+        source += &format!("const {}: (i32, i32) = ({}, {});\n", name, mg, eg);
+    }
+
+    source
+}
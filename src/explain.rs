@@ -0,0 +1,115 @@
+use super::{parse_pos, pos_to_algebra, ChessState, Move, Piece};
+
+use std::fmt;
+
+//why a move entered by the user was rejected
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Illegal {
+    Unreadable,
+    NoPiece { square: u32 },
+    NotYourPiece { square: u32 },
+    OwnPiece { square: u32 },
+    CannotReach { piece: Piece, origin: u32, dest: u32 },
+    BadPromotion,
+    CastlingRightsLost,
+    CastlingInCheck,
+    CastlingBlocked,
+    KingInCheck { attacker: Piece, square: u32 },
+}
+
+fn name(piece: Piece) -> String {
+    format!("{:?}", piece).to_lowercase()
+}
+
+impl fmt::Display for Illegal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Illegal::Unreadable => write!(f, "Moves are entered as origin and destination, e.g. e2e4 or e7e8q."),
+            Illegal::NoPiece { square } => write!(f, "There is no piece on {}.", pos_to_algebra(square)),
+            Illegal::NotYourPiece { square } => write!(f, "The piece on {} is not yours.", pos_to_algebra(square)),
+            Illegal::OwnPiece { square } => write!(f, "The square {} is occupied by your own piece.", pos_to_algebra(square)),
+            Illegal::CannotReach { piece, origin, dest } =>
+                write!(f, "The {} on {} cannot move to {}.", name(piece), pos_to_algebra(origin), pos_to_algebra(dest)),
+            Illegal::BadPromotion => write!(f, "Only a pawn reaching the last rank promotes, to a queen, rook, bishop or knight."),
+            Illegal::CastlingRightsLost => write!(f, "Castling rights on that side have been lost."),
+            Illegal::CastlingInCheck => write!(f, "You cannot castle out of check."),
+            Illegal::CastlingBlocked => write!(f, "Castling needs the squares between king and rook empty and the king's path safe."),
+            Illegal::KingInCheck { attacker, square } =>
+                write!(f, "Your king would be in check from the {} on {}.", name(attacker), pos_to_algebra(square)),
+        }
+    }
+}
+
+impl ChessState {
+    /// Explains why `find_move` rejected the text, for moves typed in by a player.
+    pub fn explain_illegal (&self, text: &str) -> Illegal {
+        let text = text.trim();
+        if (text.len() != 4 && text.len() != 5) || !text.is_ascii() {
+            return Illegal::Unreadable;
+        }
+
+        let (origin, dest) = match (parse_pos(&text[0..2]), parse_pos(&text[2..4])) {
+            (Some(origin), Some(dest)) => (origin, dest),
+            _ => return Illegal::Unreadable,
+        };
+
+        let promotion = match text[4..].chars().next() {
+            Some(c) => match Piece::from_letter(c.to_ascii_lowercase()) {
+                Some(piece) => Some(piece),
+                None => return Illegal::Unreadable,
+            },
+            None => None,
+        };
+
+        let piece = match self.piece_at(origin) {
+            Some(piece) => piece,
+            None => return Illegal::NoPiece { square: origin },
+        };
+
+        if self.color_at(origin) != Some(self.active) {
+            return Illegal::NotYourPiece { square: origin };
+        }
+
+        let action = Move { piece, origin, dest, promotion };
+
+        //castling onto the rook, or by the king's two square step outside of Chess960
+        let two_step = !self.chess960 && origin / 8 == dest / 8 && (origin as i32 - dest as i32).abs() == 2;
+        if piece == Piece::King && (self.is_castle(action) || two_step) {
+            let rook = if dest > origin {
+                self.castle_ks[self.active as usize]
+            } else {
+                self.castle_qs[self.active as usize]
+            };
+
+            return match rook {
+                None => Illegal::CastlingRightsLost,
+                Some(_) if self.in_check() => Illegal::CastlingInCheck,
+                Some(_) => Illegal::CastlingBlocked,
+            };
+        }
+
+        if self.color_at(dest) == Some(self.active) {
+            return Illegal::OwnPiece { square: dest };
+        }
+
+        if !self.reaches(action) {
+            return Illegal::CannotReach { piece, origin, dest };
+        }
+
+        let last_row = piece == Piece::Pawn && (dest / 8 == 0 || dest / 8 == 7);
+        let action = match (promotion, last_row) {
+            (None, true) => Move::promote(origin, dest, Piece::Queen),
+            (Some(Piece::Pawn), _) | (Some(Piece::King), _) | (Some(_), false) => return Illegal::BadPromotion,
+            _ => action,
+        };
+
+        let mut after = self.clone();
+        after.apply_move(action);
+
+        let king = after.king_pos(self.active);
+        match after.attackers_to(king, after.active, after.occupied()).get_indices().next() {
+            Some(square) => Illegal::KingInCheck { attacker: after.piece_at(square).unwrap(), square },
+            None => Illegal::CannotReach { piece, origin, dest },
+        }
+    }
+}
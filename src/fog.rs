@@ -0,0 +1,54 @@
+use super::{BitBoard, ChessState, Color, Move, Piece};
+
+//fog of war, or dark chess: players only see the squares their pieces can move to.
+//there is no check, moving into one is allowed and the game is won by capturing the king
+impl ChessState {
+    /// Squares `color` can see: its own pieces and every square they can move to.
+    pub fn visible (&self, color: Color) -> BitBoard {
+        let mut view = self.clone();
+        if view.active != color {
+            view.active = color;
+            view.en_passant = None;
+        }
+
+        view.unchecked_moves().iter()
+            .fold(self.occupied_by(color), |bb, action| bb.add_pos(action.dest))
+    }
+
+    /// The board as `color` sees it, rank 8 first: piece letters as in FEN,
+    /// '.' for empty squares and '?' for squares hidden in the fog.
+    pub fn fog_view (&self, color: Color) -> String {
+        let visible = self.visible(color);
+        let mut view = String::with_capacity(72);
+
+        for rank in (0..8).rev() {
+            for pos in rank * 8..rank * 8 + 8 {
+                view.push(match (self.piece_at(pos), self.color_at(pos)) {
+                    _ if visible.empty_at(pos) => '?',
+                    (Some(piece), Some(color)) => piece.letter(color),
+                    _ => '.',
+                });
+            }
+            view.push('\n');
+        }
+
+        view
+    }
+
+    /// Finds a move entered as e.g. "e2e4", allowing moves that leave the king attacked.
+    pub fn find_fog_move (&self, text: &str) -> Option<Move> {
+        self.find_among(text, &self.unchecked_moves())
+    }
+
+    /// The side that captured the enemy king, once one has.
+    pub fn fog_winner (&self) -> Option<Color> {
+        [Color::White, Color::Black].iter()
+            .find(|&&color| self.pieces_of(color, Piece::King).is_empty())
+            .map(|color| color.opposite())
+    }
+
+    /// Whether the game is over, which it also is, drawn, if the side to move has no move at all.
+    pub fn fog_finished (&self) -> bool {
+        self.fog_winner().is_some() || self.unchecked_moves().is_empty()
+    }
+}
@@ -0,0 +1,64 @@
+use super::error::Error;
+use super::eval;
+
+use std::path::Path;
+
+//pixels per square, and kept around the board for the file and rank labels and the title
+const SQUARE: usize = 48;
+const MARGIN: usize = 24;
+
+//a value as a color, white at 0, turning green above it and red below it as it nears `largest`
+fn color(value: i32, largest: i32) -> String {
+    let strength = (value.abs() as f64 / largest.max(1) as f64).min(1.0);
+    let faded = (255.0 * (1.0 - 0.75 * strength)).round() as u8;
+
+    if value >= 0 {
+        format!("rgb({},255,{})", faded, faded)
+    } else {
+        format!("rgb(255,{},{})", faded, faded)
+    }
+}
+
+/// A piece-square table as an SVG heatmap with `title` above it, as seen from white with
+/// rank 8 on top, each square colored and labelled with its value.
+pub fn svg(title: &str, table: &[i32]) -> String {
+    let size = 8 * SQUARE + 2 * MARGIN;
+    let largest = table.iter().map(|value| value.abs()).max().unwrap_or(0);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" font-family=\"sans-serif\" font-size=\"12\" text-anchor=\"middle\">\n",
+        size,
+    );
+    svg += &format!("<text x=\"{}\" y=\"{}\" font-size=\"14\">{}</text>\n", size / 2, MARGIN * 2 / 3, title);
+
+    for (index, &value) in table.iter().enumerate() {
+        let (x, y) = (MARGIN + SQUARE * (index % 8), MARGIN + SQUARE * (index / 8));
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{3}\" height=\"{3}\" fill=\"{}\" stroke=\"#999\"/>\n",
+            x, y, color(value, largest), SQUARE,
+        );
+        svg += &format!("<text x=\"{}\" y=\"{}\">{}</text>\n", x + SQUARE / 2, y + SQUARE / 2 + 4, value);
+    }
+
+    for i in 0..8 {
+        let middle = MARGIN + SQUARE * i + SQUARE / 2;
+        svg += &format!("<text x=\"{}\" y=\"{}\">{}</text>\n", middle, size - MARGIN / 3, (b'a' + i as u8) as char);
+        svg += &format!("<text x=\"{}\" y=\"{}\">{}</text>\n", MARGIN / 2, middle + 4, 8 - i);
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+/// Writes a heatmap of every piece's table in each phase to `directory`, e.g. knight_endgame.svg,
+/// from `parameters` laid out like `eval::tuning_parameters`.
+pub fn write_all(directory: &Path, parameters: &[i32]) -> Result<(), Error> {
+    std::fs::create_dir_all(directory)?;
+
+    for (piece, phase, table) in eval::piece_square_tables(parameters) {
+        let name = format!("{:?}", piece);
+        let path = directory.join(format!("{}_{}.svg", name.to_lowercase(), phase));
+        std::fs::write(path, svg(&format!("{}, {}", name, phase), table))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use super::error::Error;
+use super::metrics;
+use super::nnue::Network;
+use super::pgn;
+use super::search::{Engine, Score, SearchResult};
+use super::time::TimeManager;
+use super::ChessState;
+
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use rocket::response::content::Json;
+use rocket::response::status::BadRequest;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+
+//deeper searches would let a single job hold a worker for hours
+const MAX_DEPTH: u32 = 12;
+//a direct analysis holds its request open, so it must answer in seconds
+const DEFAULT_DEPTH: u32 = 8;
+const MAX_TIME: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Queued,
+    Running,
+    Done,
+    //the engine crashed, the results up to that position are kept
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    fen: String,
+    //in coordinates, None when the side to move has no legal move
+    best: Option<String>,
+    //exactly one of these is set: centipawns, or moves to mate, negative when mated
+    score: Option<i32>,
+    mate: Option<i32>,
+    pv: Vec<String>,
+    nodes: u64,
+}
+
+impl Analysis {
+    fn new(state: &ChessState, result: &SearchResult) -> Self {
+        let mut position = state.clone();
+        let mut pv = Vec::new();
+        for &action in &result.pv {
+            pv.push(position.coordinates(action));
+            position.apply_move(action);
+        }
+
+        let (score, mate) = match Score::new(result.score) {
+            Score::Centipawns(centipawns) => (Some(centipawns), None),
+            Score::Mate(moves) => (None, Some(moves)),
+        };
+
+        Self {
+            fen: state.to_fen(),
+            best: result.best.map(|action| state.coordinates(action)),
+            score,
+            mate,
+            pv,
+            nodes: result.nodes,
+        }
+    }
+}
+
+struct Job {
+    depth: u32,
+    positions: Vec<ChessState>,
+    status: Status,
+    results: Vec<Analysis>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    status: Status,
+    positions: usize,
+    results: &'a [Analysis],
+}
+
+struct Jobs {
+    jobs: HashMap<u64, Job>,
+    //ids of the jobs waiting for a worker, oldest first
+    queue: VecDeque<u64>,
+    next_id: u64,
+}
+
+/// Analysis jobs waiting for or being worked on by the worker pool, shared with the server.
+pub struct JobQueue {
+    jobs: Mutex<Jobs>,
+    //signalled whenever a job is queued
+    queued: Condvar,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self {
+            jobs: Mutex::new(Jobs { jobs: HashMap::new(), queue: VecDeque::new(), next_id: 1 }),
+            queued: Condvar::new(),
+        }
+    }
+
+    //a panic in one worker must not stop the others from taking jobs
+    fn lock(&self) -> MutexGuard<Jobs> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn submit(&self, positions: Vec<ChessState>, depth: u32) -> u64 {
+        let mut jobs = self.lock();
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+
+        jobs.jobs.insert(id, Job { depth, positions, status: Status::Queued, results: Vec::new() });
+        jobs.queue.push_back(id);
+        self.queued.notify_one();
+        id
+    }
+
+    //blocks until a job is queued, then hands its positions to the caller
+    fn take(&self) -> (u64, Vec<ChessState>, u32) {
+        let mut jobs = self.lock();
+        loop {
+            if let Some(id) = jobs.queue.pop_front() {
+                if let Some(job) = jobs.jobs.get_mut(&id) {
+                    job.status = Status::Running;
+                    return (id, job.positions.clone(), job.depth);
+                }
+            }
+
+            jobs = self.queued.wait(jobs).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.lock().jobs.get_mut(&id) {
+            update(job);
+        }
+    }
+}
+
+/// Starts `workers` threads, each with its own engine of `hash` megabytes, that work
+/// through the queue's jobs in the order they were submitted. The engines share `network`
+/// when given one.
+pub fn start_workers(queue: &Arc<JobQueue>, workers: usize, hash: usize, network: Option<Arc<Network>>) {
+    for _ in 0..workers {
+        let queue = Arc::clone(queue);
+        let network = network.clone();
+
+        thread::spawn(move || {
+            let stats = metrics::SearchStats::default();
+            let new_engine = || {
+                let mut engine = Engine::with_hash(hash, false);
+                engine.set_network(network.clone());
+                engine.set_listener(Some(stats.listener()));
+                engine
+            };
+            let mut engine = new_engine();
+
+            loop {
+                let (id, positions, depth) = queue.take();
+                engine.clear();
+
+                for state in &positions {
+                    let searched = panic::catch_unwind(AssertUnwindSafe(|| engine.search(state, depth)));
+                    stats.count_search();
+
+                    match searched {
+                        Ok(result) => {
+                            let analysis = Analysis::new(state, &result);
+                            queue.update(id, |job| job.results.push(analysis));
+                        }
+                        Err(_) => {
+                            eprintln!("Engine failed in position {}, job {} stopped.", state.to_fen(), id);
+                            queue.update(id, |job| job.status = Status::Failed);
+                            engine = new_engine();
+                            break;
+                        }
+                    }
+                }
+
+                queue.update(id, |job| if job.status == Status::Running {
+                    job.status = Status::Done;
+                });
+            }
+        });
+    }
+}
+
+//one FEN per line, blank lines ignored
+fn parse_fens(text: &str) -> Result<Vec<ChessState>, Error> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| ChessState::parse_fen(line).map_err(Error::from))
+        .collect()
+}
+
+//every position of every game's main line, from the start on
+fn parse_games(text: &str) -> Result<Vec<ChessState>, Error> {
+    let mut positions = Vec::new();
+
+    for game in pgn::parse_pgn(text)? {
+        positions.push(game.start);
+        positions.extend(game.moves.into_iter().map(|node| node.state));
+    }
+
+    Ok(positions)
+}
+
+fn submit(queue: &JobQueue, positions: Result<Vec<ChessState>, Error>, depth: u32) -> String {
+    match positions {
+        Ok(positions) if depth >= 1 && depth <= MAX_DEPTH => queue.submit(positions, depth).to_string(),
+        Ok(_) => format!("invalid: the depth must be from 1 to {}", MAX_DEPTH),
+        Err(error) => format!("invalid: {}", error),
+    }
+}
+
+//the body lists FENs, one per line, and the response is the job's id
+#[post("/jobs/fen/<depth>", data = "<body>")]
+pub fn submit_fens(depth: u32, body: String, queue: State<Arc<JobQueue>>) -> String {
+    submit(&queue, parse_fens(&body), depth)
+}
+
+//the body is a PGN file, every position of each game's main line is analysed
+#[post("/jobs/pgn/<depth>", data = "<body>")]
+pub fn submit_pgn(depth: u32, body: String, queue: State<Arc<JobQueue>>) -> String {
+    submit(&queue, parse_games(&body), depth)
+}
+
+//the job's status with the results so far, in the order of its positions
+#[get("/jobs/<id>")]
+pub fn status(id: u64, queue: State<Arc<JobQueue>>) -> Option<Json<String>> {
+    let jobs = queue.lock();
+    let job = jobs.jobs.get(&id)?;
+
+    let report = Report { status: job.status, positions: job.positions.len(), results: &job.results };
+    Some(Json(serde_json::to_string(&report).expect("Job reports always serialize.")))
+}
+
+//the body of a direct analysis, with the time in milliseconds. without a time the search goes
+//to its depth, with one it goes as deep as the time allows, up to the depth if given
+#[derive(Deserialize)]
+struct Query {
+    fen: String,
+    depth: Option<u32>,
+    time: Option<u64>,
+}
+
+/// Engines for direct analyses, one for each request being answered, kept between requests.
+pub struct Analysers {
+    engines: Mutex<Vec<Engine>>,
+    hash: usize,
+    network: Option<Arc<Network>>,
+}
+
+impl Analysers {
+    pub fn new(hash: usize, network: Option<Arc<Network>>) -> Self {
+        Self { engines: Mutex::new(Vec::new()), hash, network }
+    }
+
+    //an idle engine, or a new one when all are busy
+    fn take(&self) -> Engine {
+        let idle = self.engines.lock().unwrap_or_else(PoisonError::into_inner).pop();
+        idle.unwrap_or_else(|| {
+            let mut engine = Engine::with_hash(self.hash, false);
+            engine.set_network(self.network.clone());
+            engine
+        })
+    }
+
+    fn give_back(&self, engine: Engine) {
+        self.engines.lock().unwrap_or_else(PoisonError::into_inner).push(engine);
+    }
+
+    fn analyse(&self, query: &Query) -> Result<Analysis, String> {
+        let state = ChessState::parse_fen(&query.fen).map_err(|error| error.to_string())?;
+        let depth = query.depth.unwrap_or(if query.time.is_some() { MAX_DEPTH } else { DEFAULT_DEPTH });
+        if depth < 1 || depth > MAX_DEPTH {
+            return Err(format!("the depth must be from 1 to {}", MAX_DEPTH));
+        }
+
+        let mut engine = self.take();
+        engine.clear();
+        let stats = metrics::SearchStats::default();
+        engine.set_listener(Some(stats.listener()));
+
+        let searched = panic::catch_unwind(AssertUnwindSafe(|| match query.time {
+            Some(time) => engine.search_timed(&state, &mut TimeManager::fixed(Duration::from_millis(time.min(MAX_TIME))), depth),
+            None => engine.search(&state, depth),
+        }));
+
+        stats.count_search();
+
+        //an engine that panicked is dropped rather than trusted with the next request
+        match searched {
+            Ok(result) => {
+                self.give_back(engine);
+                Ok(Analysis::new(&state, &result))
+            }
+            Err(_) => {
+                eprintln!("Engine failed in position {}.", state.to_fen());
+                Err("the engine failed in this position".to_string())
+            }
+        }
+    }
+}
+
+//the body is JSON with a FEN and optionally a depth and a time, e.g.
+//{"fen": "...", "depth": 10} or {"fen": "...", "time": 2000}, and the response is the analysis
+//once the search is done
+#[post("/analyse", data = "<body>")]
+pub fn analyse(body: String, analysers: State<Analysers>) -> Result<Json<String>, BadRequest<String>> {
+    let invalid = |error: String| BadRequest(Some(format!("invalid: {}", error)));
+
+    let query: Query = serde_json::from_str(&body).map_err(|error| invalid(error.to_string()))?;
+    let analysis = analysers.analyse(&query).map_err(invalid)?;
+    Ok(Json(serde_json::to_string(&analysis).expect("Analyses always serialize.")))
+}
@@ -0,0 +1,184 @@
+use super::material::Endgame;
+use super::{cache, ChessState, Color, Piece};
+
+use lazy_static::lazy_static;
+
+//every king and pawn against king position, seen from the side with the pawn as white and the
+//pawn on files a to d, as the rest are mirror images: 24 pawn squares, 64 squares for each
+//king and the side to move
+const INDICES: usize = 24 * 64 * 64 * 2;
+
+fn index(white_to_move: bool, black_king: u32, white_king: u32, pawn: u32) -> usize {
+    let side = if white_to_move { 0 } else { 1 };
+    (white_king | black_king << 6 | side << 12 | (pawn % 8) << 13 | (6 - pawn / 8) << 15) as usize
+}
+
+fn distance(a: u32, b: u32) -> u32 {
+    let files = ((a % 8) as i32 - (b % 8) as i32).abs();
+    let ranks = ((a / 8) as i32 - (b / 8) as i32).abs();
+    files.max(ranks) as u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    white_to_move: bool,
+    white_king: u32,
+    black_king: u32,
+    pawn: u32,
+    result: Verdict,
+}
+
+impl Position {
+    fn new(index: usize) -> Self {
+        let index = index as u32;
+        let white_king = index & 0x3F;
+        let black_king = (index >> 6) & 0x3F;
+        let white_to_move = (index >> 12) & 1 == 0;
+        let pawn = (6 - (index >> 15)) * 8 + ((index >> 13) & 0x3);
+
+        let mut position = Self { white_to_move, white_king, black_king, pawn, result: Verdict::Unknown };
+        position.result = position.initial();
+        position
+    }
+
+    //what the position is before looking at any move: impossible, or decided at once when the
+    //pawn promotes safely, black is stalemated or black takes the undefended pawn
+    fn initial(&self) -> Verdict {
+        let (white_king, black_king, pawn) = (self.white_king, self.black_king, self.pawn);
+        let pawn_attacks = cache.pawn_attacks(Color::White, pawn);
+
+        if distance(white_king, black_king) <= 1 || white_king == pawn || black_king == pawn
+            || (self.white_to_move && !pawn_attacks.empty_at(black_king)) {
+            return Verdict::Invalid;
+        }
+
+        if self.white_to_move && pawn / 8 == 6 {
+            let queening = pawn + 8;
+            if white_king != queening && black_king != queening
+                && (distance(black_king, queening) > 1 || distance(white_king, queening) == 1) {
+                return Verdict::Win;
+            }
+        }
+
+        if !self.white_to_move {
+            let guarded = cache.king_moves(white_king) | pawn_attacks;
+            let escapes = cache.king_moves(black_king) & guarded.invert();
+
+            if escapes.is_empty() || !escapes.empty_at(pawn) {
+                return Verdict::Draw;
+            }
+        }
+
+        Verdict::Unknown
+    }
+
+    //the result from the results of the positions each move leads to, the side to move
+    //picking the best of them for itself
+    fn classify(&self, results: &[Verdict]) -> Verdict {
+        let (mut win, mut draw, mut unknown) = (false, false, false);
+        let mut record = |result: Verdict| match result {
+            Verdict::Win => win = true,
+            Verdict::Draw => draw = true,
+            Verdict::Unknown => unknown = true,
+            Verdict::Invalid => {}
+        };
+
+        if self.white_to_move {
+            for king in cache.king_moves(self.white_king).get_indices() {
+                record(results[index(false, self.black_king, king, self.pawn)]);
+            }
+
+            //promotions were decided in initial
+            if self.pawn / 8 < 6 {
+                record(results[index(false, self.black_king, self.white_king, self.pawn + 8)]);
+            }
+            if self.pawn / 8 == 1 && self.pawn + 8 != self.white_king && self.pawn + 8 != self.black_king {
+                record(results[index(false, self.black_king, self.white_king, self.pawn + 16)]);
+            }
+
+            if win {
+                Verdict::Win
+            } else if unknown {
+                Verdict::Unknown
+            } else {
+                Verdict::Draw
+            }
+        } else {
+            for king in cache.king_moves(self.black_king).get_indices() {
+                record(results[index(true, king, self.white_king, self.pawn)]);
+            }
+
+            if draw {
+                Verdict::Draw
+            } else if unknown {
+                Verdict::Unknown
+            } else {
+                Verdict::Win
+            }
+        }
+    }
+}
+
+//positions unknown at first are decided by their moves, over and over until nothing changes.
+//whatever is still unknown then cannot be forced either way and is a draw
+fn generate() -> Vec<u64> {
+    let mut positions: Vec<Position> = (0..INDICES).map(Position::new).collect();
+    let mut results: Vec<Verdict> = positions.iter().map(|position| position.result).collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, position) in positions.iter_mut().enumerate().filter(|(_, position)| position.result == Verdict::Unknown) {
+            position.result = position.classify(&results);
+            if position.result != Verdict::Unknown {
+                results[i] = position.result;
+                changed = true;
+            }
+        }
+    }
+
+    let mut wins = vec![0; INDICES / 64];
+    for (i, &result) in results.iter().enumerate() {
+        if result == Verdict::Win {
+            wins[i / 64] |= 1 << (i % 64);
+        }
+    }
+    wins
+}
+
+lazy_static! {
+    //one bit for each position, set when white wins
+    static ref bitbase: Vec<u64> = generate();
+}
+
+/// Whether king and pawn win against the bare king, with the side to move as given. The
+/// table behind it is computed the first time it is needed.
+pub fn wins(strong: Color, strong_king: u32, pawn: u32, weak_king: u32, strong_to_move: bool) -> bool {
+    //turned around for black, then mirrored onto files a to d
+    let flip = |pos: u32| if strong == Color::Black { pos ^ 56 } else { pos };
+    let mirror = if flip(pawn) % 8 >= 4 { 7 } else { 0 };
+    let normalize = |pos: u32| flip(pos) ^ mirror;
+
+    let i = index(strong_to_move, normalize(weak_king), normalize(strong_king), normalize(pawn));
+    bitbase[i / 64] & (1 << (i % 64)) != 0
+}
+
+/// For a king and pawn against king position, whether the side with the pawn wins, e.g. for
+/// adjudicating a game. None for any other material.
+pub fn probe(state: &ChessState) -> Option<bool> {
+    let (endgame, strong) = state.endgame();
+    if endgame != Endgame::KPK {
+        return None;
+    }
+
+    let pawn = state.pieces_of(strong, Piece::Pawn).solo_pos();
+    Some(wins(strong, state.king_pos(strong), pawn, state.king_pos(strong.opposite()), state.active == strong))
+}
@@ -0,0 +1,245 @@
+use super::error::Error;
+use super::search::Engine;
+use super::time::{TimeControl, TimeManager};
+use super::{ChessState, Color};
+
+use reqwest::{Client, Response};
+use serde::Deserialize;
+use std::time::Duration;
+
+const API: &str = "https://lichess.org/api";
+
+//deepest a search goes, which the clock ends long before
+const MAX_DEPTH: u32 = 64;
+
+fn lichess_error(error: impl ToString) -> Error {
+    Error::Lichess(error.to_string())
+}
+
+#[derive(Deserialize)]
+struct Account {
+    id: String,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct Variant {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    id: String,
+    variant: Variant,
+}
+
+#[derive(Deserialize)]
+struct Game {
+    id: String,
+}
+
+//what the event stream sends, among other things this bot ignores
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Event {
+    Challenge { challenge: Challenge },
+    GameStart { game: Game },
+    #[serde(other)]
+    Other,
+}
+
+//computer opponents have no id
+#[derive(Deserialize)]
+struct Player {
+    id: Option<String>,
+}
+
+//clock times in milliseconds
+#[derive(Deserialize)]
+struct GameState {
+    moves: String,
+    wtime: u64,
+    btime: u64,
+    winc: u64,
+    binc: u64,
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum GameEvent {
+    #[serde(rename_all = "camelCase")]
+    GameFull { white: Player, initial_fen: String, state: GameState },
+    GameState(GameState),
+    #[serde(other)]
+    Other,
+}
+
+//the lines of a response in newline delimited JSON, without the empty ones lichess sends to
+//keep the connection open
+struct Lines {
+    response: Response,
+    buffer: Vec<u8>,
+}
+
+impl Lines {
+    async fn next(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if !line.is_empty() {
+                    return Ok(Some(line));
+                }
+                continue;
+            }
+
+            match self.response.chunk().await.map_err(lichess_error)? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// A bot account on lichess.org, which accepts every standard chess challenge and plays each
+/// game with an engine of its own, searching on the clock.
+#[derive(Clone)]
+pub struct Bot {
+    client: Client,
+    token: String,
+    id: String,
+}
+
+impl Bot {
+    /// Logs in with the API token of a bot account.
+    pub async fn connect(token: &str) -> Result<Self, Error> {
+        let mut bot = Self { client: Client::new(), token: token.to_string(), id: String::new() };
+
+        let text = bot.get("/account").await?.text().await.map_err(lichess_error)?;
+        let account: Account = serde_json::from_str(&text).map_err(lichess_error)?;
+        println!("Logged in as {}.", account.username);
+
+        bot.id = account.id;
+        Ok(bot)
+    }
+
+    async fn get(&self, path: &str) -> Result<Response, Error> {
+        self.client.get(&format!("{}{}", API, path))
+            .bearer_auth(&self.token)
+            .send().await
+            .and_then(Response::error_for_status)
+            .map_err(lichess_error)
+    }
+
+    async fn post(&self, path: &str) -> Result<(), Error> {
+        self.client.post(&format!("{}{}", API, path))
+            .bearer_auth(&self.token)
+            .send().await
+            .and_then(Response::error_for_status)
+            .map_err(lichess_error)?;
+        Ok(())
+    }
+
+    async fn stream(&self, path: &str) -> Result<Lines, Error> {
+        Ok(Lines { response: self.get(path).await?, buffer: Vec::new() })
+    }
+
+    /// Answers challenges and starts playing every game that begins, until the event stream
+    /// closes.
+    pub async fn run(self) -> Result<(), Error> {
+        let mut events = self.stream("/stream/event").await?;
+
+        while let Some(line) = events.next().await? {
+            match serde_json::from_str(&line).map_err(lichess_error)? {
+                Event::Challenge { challenge } if challenge.variant.key == "standard" => {
+                    self.post(&format!("/challenge/{}/accept", challenge.id)).await?;
+                }
+                Event::Challenge { challenge } => {
+                    self.post(&format!("/challenge/{}/decline", challenge.id)).await?;
+                }
+                Event::GameStart { game } => {
+                    let bot = self.clone();
+                    tokio::spawn(async move {
+                        println!("Game {} started.", game.id);
+                        match bot.play(&game.id).await {
+                            Ok(()) => println!("Game {} is over.", game.id),
+                            Err(error) => println!("Game {}: {}", game.id, error),
+                        }
+                    });
+                }
+                Event::Other => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    //follows the game's stream, answering each position where it is the bot's turn with the
+    //engine's move. the search runs on a blocking thread, so the other games go on meanwhile
+    async fn play(&self, game: &str) -> Result<(), Error> {
+        let mut lines = self.stream(&format!("/bot/game/stream/{}", game)).await?;
+        let mut engine = Engine::new();
+        let mut start = ChessState::default();
+        let mut color = Color::White;
+
+        while let Some(line) = lines.next().await? {
+            let state = match serde_json::from_str(&line).map_err(lichess_error)? {
+                GameEvent::GameFull { white, initial_fen, state } => {
+                    color = if white.id.as_deref() == Some(self.id.as_str()) { Color::White } else { Color::Black };
+                    if initial_fen != "startpos" {
+                        start = ChessState::parse_fen(&initial_fen)?;
+                    }
+                    state
+                }
+                GameEvent::GameState(state) => state,
+                GameEvent::Other => continue,
+            };
+
+            if state.status != "started" {
+                break;
+            }
+
+            let mut position = start.clone();
+            let mut game = Vec::new();
+            for text in state.moves.split_whitespace() {
+                let action = position.find_move(text)?;
+                game.push(position.hash());
+                position.apply_move(action);
+            }
+
+            if position.active != color || position.legal_moves().is_empty() {
+                continue;
+            }
+
+            let control = TimeControl {
+                time: [Duration::from_millis(state.wtime), Duration::from_millis(state.btime)],
+                increment: [Duration::from_millis(state.winc), Duration::from_millis(state.binc)],
+                moves_to_go: None,
+            };
+
+            engine.set_game(game);
+            let (searched, best) = tokio::task::spawn_blocking(move || {
+                let mut time = TimeManager::new(&control, position.active);
+                let result = engine.search_timed(&position, &mut time, MAX_DEPTH);
+                let best = result.best.map(|action| position.coordinates(action));
+                (engine, best)
+            }).await.map_err(lichess_error)?;
+            engine = searched;
+
+            if let Some(best) = best {
+                self.post(&format!("/bot/game/{}/move/{}", game, best)).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs a bot with the API token of a bot account until lichess closes its event stream.
+pub fn run(token: &str) -> Result<(), Error> {
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        Bot::connect(token).await?.run().await
+    })
+}
@@ -41,7 +41,7 @@ impl MagicCache {
     pub fn rook_moves(&self, pos: u32, occupancy: BitBoard) -> BitBoard {
         let masked = self.rook_masks[pos as usize] & occupancy;
         let bits = self.rook_bits[pos as usize];
-        let key = (masked.0 * MAGIC_ROOKS[pos as usize]) >> (64 - bits);
+        let key = masked.0.wrapping_mul(MAGIC_ROOKS[pos as usize]) >> (64 - bits);
         
         self.rook_cache[pos as usize][key as usize]
     }
@@ -49,7 +49,7 @@ impl MagicCache {
     pub fn bishop_moves(&self, pos: u32, occupancy: BitBoard) -> BitBoard {
         let masked = self.bishop_masks[pos as usize] & occupancy;
         let bits = self.bishop_bits[pos as usize];
-        let key = (masked.0 * MAGIC_BISHOPS[pos as usize]) >> (64 - bits);
+        let key = masked.0.wrapping_mul(MAGIC_BISHOPS[pos as usize]) >> (64 - bits);
 
         self.bishop_cache[pos as usize][key as usize]
     }
@@ -115,7 +115,7 @@ impl MagicCache {
             let (x, y) = (pos % 8, pos / 8);
 
             let mut bb = BitBoard::new();
-            for y2 in 0..y { 
+            for y2 in (0..y).rev() { 
                 let other = x + y2 * 8;
                 bb = bb.add_pos(other);
                 rook_rays[(pos * 64 + other) as usize] = bb;
@@ -129,7 +129,7 @@ impl MagicCache {
             }
 
             let mut bb = BitBoard::new();
-            for x2 in 0..x { 
+            for x2 in (0..x).rev() { 
                 let other = x2 + y * 8;
                 bb = bb.add_pos(other);
                 rook_rays[(pos * 64 + other) as usize] = bb;
@@ -178,13 +178,13 @@ impl MagicCache {
             let possible_bishops = Self::gen_bishop(pos);
 
             for rook in possible_rooks {
-                let key = (rook.0 * MAGIC_ROOKS[pos as usize]) >> (64 - rb);
+                let key = rook.0.wrapping_mul(MAGIC_ROOKS[pos as usize]) >> (64 - rb);
                 let result = Self::solve_rook(rook, pos);
                 crc[key as usize] = result;
             }
 
             for bishop in possible_bishops {
-                let key = (bishop.0 * MAGIC_BISHOPS[pos as usize]) >> (64 - bb);
+                let key = bishop.0.wrapping_mul(MAGIC_BISHOPS[pos as usize]) >> (64 - bb);
                 let result = Self::solve_bishop(bishop, pos);
                 cbc[key as usize] = result;
             }
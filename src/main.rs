@@ -5,27 +5,79 @@
 extern crate lazy_static;
 extern crate rand;
 
-use std::sync::{MutexGuard, Mutex};
+use std::sync::{Arc, MutexGuard, Mutex};
+use std::cell::RefCell;
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
 use std::fmt;
+use std::convert::TryFrom;
 use std::char;
 use std::io::{self, BufRead};
+use std::time::{Duration, Instant};
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use lazy_static::lazy_static;
 
 const PLAYER_COUNT: usize = 2;
 const PIECE_TYPE_COUNT: usize = 6;
 
+mod chess960;
+mod duck;
+mod encoding;
+mod endgames;
+mod epd;
+mod error;
+mod eval;
+mod explain;
+mod fog;
+mod heatmap;
+mod jobs;
+mod kpk;
+#[cfg(feature = "lichess")]
+mod lichess;
 mod magic;
-
+mod material;
+mod metrics;
+mod nnue;
+mod openings;
+mod pawns;
+mod perft;
+mod pgn;
+#[cfg(test)]
+mod pgnfuzz;
+mod polyglot;
+mod positions;
+mod review;
+mod rooms;
+#[cfg(test)]
+mod roundtrip;
+mod san;
+mod search;
+mod shelter;
+mod skill;
+#[cfg(test)]
+mod symmetry;
+mod tactics;
+mod teach;
+mod time;
+mod tt;
+mod tune;
+mod uci;
+mod variants;
+#[cfg(feature = "websocket")]
+mod websocket;
+mod zobrist;
+
+use error::Error;
 use magic::MagicCache;
 
 use rocket::State;
 use rocket_contrib::serve::StaticFiles;
 
-#[derive(Debug, Copy, Clone)]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Color {
     White,
@@ -48,7 +100,7 @@ impl Color {
         }
     }
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Piece {
     Pawn,
@@ -85,6 +137,22 @@ impl Piece {
         }
     }
 
+    pub fn letter(&self, color: Color) -> char {
+        let c = match self {
+            Piece::King => 'k',
+            Piece::Queen => 'q',
+            Piece::Knight => 'n',
+            Piece::Pawn => 'p',
+            Piece::Bishop => 'b',
+            Piece::Rook => 'r',
+        };
+
+        match color {
+            Color::White => c.to_ascii_uppercase(),
+            Color::Black => c,
+        }
+    }
+
     pub fn render(&self, color: Color) -> char {
         match color {
             Color::White => {
@@ -112,7 +180,7 @@ impl Piece {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BitBoard(u64);
 
 impl fmt::Display for BitBoard {
@@ -138,23 +206,68 @@ impl fmt::Display for BitBoard {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SquareColor {
+    Light,
+    Dark,
+}
+
+//b1, d1 and so on, a1 is dark
+const LIGHT_SQUARES: BitBoard = BitBoard(0x55AA_55AA_55AA_55AA);
+//ranks 1 and 8, where no pawn can stand
+const BACK_RANKS: BitBoard = BitBoard(0xFF00_0000_0000_00FF);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Square(u32);
+
+impl Square {
+    pub fn new(index: u32) -> Self {
+        assert!(index < 64, "Invalid square.");
+        Self(index)
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+
+    pub fn color(&self) -> SquareColor {
+        if LIGHT_SQUARES.empty_at(self.0) {
+            SquareColor::Dark
+        } else {
+            SquareColor::Light
+        }
+    }
+}
+
+impl SquareColor {
+    pub fn squares(self) -> BitBoard {
+        match self {
+            SquareColor::Light => LIGHT_SQUARES,
+            SquareColor::Dark => LIGHT_SQUARES.invert(),
+        }
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", pos_to_algebra(self.0))
+    }
+}
+
 struct IndexIterator {
     curr: u64,
-    pos: u32,
 }
 
 impl Iterator for IndexIterator {
     type Item = u32;
 
     fn next(&mut self) -> Option<u32> {
-        let trail = self.curr.trailing_zeros() + 1;
-        self.pos += trail;
-
-        if self.pos >= 65 {
+        if self.curr == 0 {
             None
         } else {
-            self.curr >>= trail;
-            Some(self.pos - 1)
+            let pos = self.curr.trailing_zeros();
+            self.curr &= self.curr - 1;
+            Some(pos)
         }
     }
 }
@@ -198,7 +311,6 @@ impl BitBoard {
 
     fn get_indices (&self) -> IndexIterator {
         IndexIterator {
-            pos: 0,
             curr: self.0,
         }
     }
@@ -206,6 +318,12 @@ impl BitBoard {
     fn solo_pos (&self) -> u32 {
         self.0.trailing_zeros()
     }
+
+    //flips the board vertically, rank 1 becomes rank 8
+    #[cfg(test)]
+    fn mirror (&self) -> Self {
+        Self(self.0.swap_bytes())
+    }
 }
 
 impl BitAnd for BitBoard {
@@ -240,10 +358,53 @@ pub struct ChessState {
     pub active: Color,
     pub piece_bb: [BitBoard; PIECE_TYPE_COUNT],
     pub player_bb: [BitBoard; PLAYER_COUNT],
-    pub castle_ks: [bool; PLAYER_COUNT],
-    pub castle_qs: [bool; PLAYER_COUNT],
+    //files of the rooks each side may still castle with
+    pub castle_ks: [Option<u32>; PLAYER_COUNT],
+    pub castle_qs: [Option<u32>; PLAYER_COUNT],
+    pub chess960: bool,
     pub en_passant: Option<BitBoard>,
     pub move_rule: u32,
+    //starts at 1 and increases after each black move
+    pub fullmove: u32,
+    //square of the duck in duck chess, which blocks every piece
+    pub duck: Option<u32>,
+    //in crazyhouse, the captured pieces each side holds by piece, and the pieces on the board
+    //that were promoted, which are pawns again once captured
+    pub pockets: Option<[[u32; PIECE_TYPE_COUNT]; PLAYER_COUNT]>,
+    pub promoted: BitBoard,
+    //in three-check, the checks each side has given
+    pub checks: Option<[u32; PLAYER_COUNT]>,
+    //zobrist hash, kept up to date by every move
+    key: u64,
+    //legal moves of this position, cleared whenever a move is applied. shared rather than
+    //copied by every caller, and an Arc as states move between threads
+    move_cache: RefCell<Option<Arc<[Move]>>>,
+    //first layer of an NNUE network, only in positions made for it by with_network
+    nnue: Option<Box<nnue::Accumulator>>,
+}
+
+//a copy is usually made to apply a move to, which would clear the cache anyway
+impl Clone for ChessState {
+    fn clone(&self) -> Self {
+        Self {
+            active: self.active,
+            piece_bb: self.piece_bb,
+            player_bb: self.player_bb,
+            castle_ks: self.castle_ks,
+            castle_qs: self.castle_qs,
+            chess960: self.chess960,
+            en_passant: self.en_passant,
+            move_rule: self.move_rule,
+            fullmove: self.fullmove,
+            duck: self.duck,
+            pockets: self.pockets,
+            promoted: self.promoted,
+            checks: self.checks,
+            key: self.key,
+            move_cache: RefCell::new(None),
+            nnue: self.nnue.clone(),
+        }
+    }
 }
 
 
@@ -254,6 +415,7 @@ struct ExtraState {
 struct Cache {
     knight_moves: Vec<BitBoard>,
     king_moves: Vec<BitBoard>,
+    pawn_attacks: [Vec<BitBoard>; PLAYER_COUNT],
 }
 
 impl Cache {
@@ -329,7 +491,29 @@ impl Cache {
             king_moves.push(bb);
         }
 
-        Cache { king_moves, knight_moves }
+        let mut pawn_attacks = [Vec::new(), Vec::new()];
+        for pos in 0..64 {
+            let x = pos % 8;
+            let y = pos / 8;
+
+            let mut white = BitBoard::new();
+            let mut black = BitBoard::new();
+
+            if y < 7 {
+                if x > 0 { white = white.add_pos(pos + 7); }
+                if x < 7 { white = white.add_pos(pos + 9); }
+            }
+
+            if y > 0 {
+                if x > 0 { black = black.add_pos(pos - 9); }
+                if x < 7 { black = black.add_pos(pos - 7); }
+            }
+
+            pawn_attacks[Color::White as usize].push(white);
+            pawn_attacks[Color::Black as usize].push(black);
+        }
+
+        Cache { king_moves, knight_moves, pawn_attacks }
     }
 
     fn knight_moves (&self, pos: u32) -> BitBoard {
@@ -339,6 +523,10 @@ impl Cache {
     fn king_moves(&self, pos: u32) -> BitBoard {
         self.king_moves[pos as usize]
     }
+
+    fn pawn_attacks(&self, color: Color, pos: u32) -> BitBoard {
+        self.pawn_attacks[color as usize][pos as usize]
+    }
 }
 
 lazy_static! {
@@ -346,98 +534,383 @@ lazy_static! {
     static ref magic_cache: MagicCache = MagicCache::new();
 }
 
+fn piece_attacks(piece: Piece, color: Color, pos: u32, occupied: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Pawn => cache.pawn_attacks(color, pos),
+        Piece::Knight => cache.knight_moves(pos),
+        Piece::King => cache.king_moves(pos),
+        Piece::Bishop => magic_cache.bishop_moves(pos, occupied),
+        Piece::Rook => magic_cache.rook_moves(pos, occupied),
+        Piece::Queen => magic_cache.bishop_moves(pos, occupied) | magic_cache.rook_moves(pos, occupied),
+    }
+}
+
 impl ChessState {
     fn default() -> Self {
         Self::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
     }
 
     fn from_fen (fen: &str) -> Self {
+        Self::parse_fen(fen).expect("Invalid FEN.")
+    }
+
+    /// Parses a FEN, or X-FEN and Shredder-FEN for Chess960, reporting the first field and
+    /// character that cannot be read. A missing fullmove number is taken as 1.
+    pub fn parse_fen (fen: &str) -> Result<Self, FenError> {
+        //fields with the offset of their first character, so errors can point into the FEN
+        let mut fields = fen.split(' ')
+            .scan(0, |offset, field| {
+                let start = *offset;
+                *offset += field.len() + 1;
+                Some((start, field))
+            })
+            .filter(|(_, field)| !field.is_empty())
+            .peekable();
+
+        let mut next = |field| fields.next().ok_or(FenError::MissingField(field));
+
         let mut player_bb = [BitBoard::new(); PLAYER_COUNT];
-        let mut piece_bb = [BitBoard::new(); PIECE_TYPE_COUNT];        
+        let mut piece_bb = [BitBoard::new(); PIECE_TYPE_COUNT];
+        let mut duck = None;
+        let mut promoted = BitBoard::new();
+
+        //crazyhouse pockets follow the board in brackets, or as a ninth rank
+        let (start, placement) = next(FenField::Placement)?;
+        let (placement, pockets) = match (placement.find('['), placement.match_indices('/').nth(7)) {
+            (Some(i), _) => match placement[i + 1..].strip_suffix(']') {
+                Some(pocket) => (&placement[..i], Some(Self::parse_pockets(pocket, start + i + 1)?)),
+                None => return Err(FenError::invalid(FenField::Placement, start, placement, i)),
+            },
+            (None, Some((i, _))) => (&placement[..i], Some(Self::parse_pockets(&placement[i + 1..], start + i + 1)?)),
+            (None, None) => (placement, None),
+        };
 
-        let mut chars = fen.chars();
-        let mut i = 0;
+        let mut rank = 7;
+        let mut file = 0;
 
-        loop {
-            let c = chars.next().expect("Invalid FEN.");
+        for (i, c) in placement.char_indices() {
+            let invalid = FenError::InvalidChar { field: FenField::Placement, index: start + i, found: c };
 
             if c == '/' {
-                continue;
-            } else if c == ' ' {
-                break;
-            } else if c.is_ascii_digit() {
-                i += c.to_digit(10).unwrap();
+                if file != 8 || rank == 0 {
+                    return Err(FenError::RankLength { rank: rank + 1 });
+                }
+
+                rank -= 1;
+                file = 0;
                 continue;
             }
 
-            let piece = Piece::from_letter(
-                c.to_ascii_lowercase())
-                .expect("Invalid FEN.");
-            
-            let color = if c.is_uppercase() { Color::White } else { Color::Black };
+            match c.to_digit(10) {
+                Some(0) | Some(9) => return Err(invalid),
+                Some(n) => file += n,
+                None if c == '*' && duck.is_none() => {
+                    if file < 8 {
+                        duck = Some(rank * 8 + file);
+                    }
+                    file += 1;
+                }
+                //a crazyhouse piece that was promoted
+                None if c == '~' && pockets.is_some() && file > 0 => promoted = promoted.add_pos(rank * 8 + file - 1),
+                None => {
+                    let piece = Piece::from_letter(c.to_ascii_lowercase()).ok_or(invalid)?;
+                    let color = if c.is_uppercase() { Color::White } else { Color::Black };
+
+                    if file < 8 {
+                        player_bb[color as usize] = player_bb[color as usize].add_pos(rank * 8 + file);
+                        piece_bb[piece as usize] = piece_bb[piece as usize].add_pos(rank * 8 + file);
+                    }
+                    file += 1;
+                }
+            }
 
-            let pos = 8 * (8 - (i / 8) - 1) + i % 8;
+            if file > 8 {
+                return Err(FenError::RankLength { rank: rank + 1 });
+            }
+        }
+
+        if rank != 0 || file != 8 {
+            return Err(FenError::RankLength { rank: rank + 1 });
+        }
 
-            let pos_bb = BitBoard::from_pos(pos);
+        for &color in &[Color::White, Color::Black] {
+            if (piece_bb[Piece::King as usize] & player_bb[color as usize]).count() != 1 {
+                return Err(FenError::KingCount(color));
+            }
+        }
 
-            player_bb[color as usize] |= pos_bb;
-            piece_bb[piece as usize] |= pos_bb;
-            i += 1;
+        if !(piece_bb[Piece::Pawn as usize] & BACK_RANKS).is_empty() {
+            return Err(FenError::PawnOnBackRank);
         }
 
-        let active = match chars.next().expect("Invalid FEN.") {
-            'w' => Color::White,
-            'b' => Color::Black,
-            _ => panic!("Invalid FEN."),
+        let (start, field) = next(FenField::ActiveColor)?;
+        let mut chars = field.chars();
+        let active = match (chars.next().and_then(Color::from_letter), chars.next()) {
+            (Some(color), None) => color,
+            (None, _) => return Err(FenError::invalid(FenField::ActiveColor, start, field, 0)),
+            (Some(_), Some(_)) => return Err(FenError::invalid(FenField::ActiveColor, start, field, 1)),
         };
 
-        chars.next().expect("Invalid FEN.");
+        let mut castle_ks = [None; PLAYER_COUNT];
+        let mut castle_qs = [None; PLAYER_COUNT];
+        let mut chess960 = false;
+
+        //KQkq pick the outermost rook on that side (X-FEN), file letters name the rook (Shredder-FEN)
+        let (start, field) = next(FenField::Castling)?;
+        for (i, c) in field.char_indices().filter(|_| field != "-") {
+            let invalid = FenError::invalid(FenField::Castling, start, field, i);
+            let color = if c.is_uppercase() { Color::White } else { Color::Black };
+            let rank = match color {
+                Color::White => 0,
+                Color::Black => 56,
+            };
+
+            let king = (piece_bb[Piece::King as usize] & player_bb[color as usize]).solo_pos();
+            let king_file = king % 8;
+            let rooks = piece_bb[Piece::Rook as usize] & player_bb[color as usize];
+            let mut rook_files = (0..8).filter(|&file| !rooks.empty_at(rank + file));
+
+            let (king_side, file) = match c.to_ascii_lowercase() {
+                'k' => (true, rook_files.filter(|&file| file > king_file).max()),
+                'q' => (false, rook_files.find(|&file| file < king_file)),
+                letter @ 'a'..='h' => {
+                    let file = letter as u32 - 'a' as u32;
+                    chess960 = true;
+                    (file > king_file, Some(file).filter(|&file| !rooks.empty_at(rank + file)))
+                }
+                _ => return Err(invalid),
+            };
+
+            //castling needs the king on its back rank and a rook to castle with
+            let file = file.filter(|_| king - king_file == rank).ok_or(invalid)?;
 
-        let mut castle_ks = [false; PLAYER_COUNT];
-        let mut castle_qs = [false; PLAYER_COUNT];
+            if (file != 0 && file != 7) || king_file != 4 {
+                chess960 = true;
+            }
 
-        loop {
-            let c = chars.next().expect("Invalid FEN.");
-            match c {
-                'k' => castle_ks[Color::Black as usize] = true,
-                'K' => castle_ks[Color::White as usize] = true,
-                'q' => castle_qs[Color::Black as usize] = true,
-                'Q' => castle_qs[Color::White as usize] = true,
-                '-' => continue,
-                ' '=> break,
-                _ => panic!("Invalid FEN."),
+            if king_side {
+                castle_ks[color as usize] = Some(file);
+            } else {
+                castle_qs[color as usize] = Some(file);
             }
         }
 
-        let c = chars.next().expect("Invalid FEN.");
-        let en_passant = match c {
-            '-' => {
-                None
+        let (start, field) = next(FenField::EnPassant)?;
+        let en_passant = if field == "-" {
+            None
+        } else {
+            let bad = field.char_indices().find(|&(i, c)| match i {
+                0 => !('a'..='h').contains(&c),
+                1 => c != '3' && c != '6',
+                _ => true,
+            });
+
+            match (bad, parse_pos(field)) {
+                (None, Some(pos)) => Some(BitBoard::from_pos(pos)),
+                (Some((i, _)), _) => return Err(FenError::invalid(FenField::EnPassant, start, field, i)),
+                (None, None) => return Err(FenError::invalid(FenField::EnPassant, start, field, 0)),
             }
-
-            r => {
-                let f = chars.next().expect("Invalid FEN.");
-                Some(BitBoard::from_pos(algebra_to_pos(r, f)))
-            },
         };
 
-        chars.next().expect("Invalid FEN.");
+        let (start, field) = next(FenField::HalfmoveClock)?;
+        let move_rule = FenError::counter(FenField::HalfmoveClock, start, field)?;
 
-        let move_rule = chars.take_while(|&c| c != ' ')
-            .collect::<String>()
-            .parse::<u32>()
-            .expect("Invalid FEN.");
+        let fullmove = match fields.next_if(|(_, field)| !field.starts_with('+')) {
+            Some((start, field)) => FenError::counter(FenField::FullmoveNumber, start, field)?,
+            None => 1,
+        };
 
-        Self {
+        let checks = match fields.next() {
+            Some((start, field)) => Some(Self::parse_checks(field, start)?),
+            None => None,
+        };
+
+        let mut state = Self {
             active,
             piece_bb,
             player_bb,
             castle_ks,
             castle_qs,
+            chess960,
             en_passant,
-            move_rule
+            move_rule,
+            fullmove,
+            duck,
+            pockets,
+            promoted,
+            checks,
+            key: 0,
+            move_cache: RefCell::new(None),
+            nnue: None,
+        };
+
+        //the king of the side that just moved cannot be left in check, except in duck chess,
+        //which has no check
+        let waiting = active.opposite();
+        if duck.is_none() && !state.attackers_to(state.king_pos(waiting), active, state.occupied()).is_empty() {
+            return Err(FenError::OpponentInCheck(waiting));
+        }
+
+        state.key = state.compute_hash();
+        Ok(state)
+    }
+
+    /// FEN with X-FEN castling letters, which read as classic FEN outside of Chess960.
+    pub fn to_fen (&self) -> String {
+        self.fen_with(false)
+    }
+
+    /// FEN naming every castling rook by its file, as Shredder-FEN does for Chess960.
+    pub fn to_shredder_fen (&self) -> String {
+        self.fen_with(true)
+    }
+
+    fn fen_with (&self, shredder: bool) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+
+            for file in 0..8 {
+                let pos = rank * 8 + file;
+
+                match (self.piece_at(pos), self.color_at(pos)) {
+                    (Some(piece), Some(color)) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.letter(color));
+                        if !self.promoted.empty_at(pos) {
+                            fen.push('~');
+                        }
+                    }
+                    _ if self.duck == Some(pos) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push('*');
+                    }
+                    _ => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        if let Some(pockets) = self.pockets_fen() {
+            fen.push_str(&pockets);
+        }
+
+        fen.push_str(match self.active {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        //KQkq for the outermost rook, like X-FEN, otherwise the rook's file letter
+        let mut castling = String::new();
+        for &color in &[Color::White, Color::Black] {
+            let rank = match color {
+                Color::White => 0,
+                Color::Black => 56,
+            };
+
+            let king_file = self.king_pos(color) % 8;
+            let rooks = self.pieces_of(color, Piece::Rook);
+            let has_rook = |file: &u32| !rooks.empty_at(rank + file);
+
+            let sides = [
+                (self.castle_ks[color as usize], (king_file + 1..8).rev().find(has_rook), 'K'),
+                (self.castle_qs[color as usize], (0..king_file).find(has_rook), 'Q'),
+            ];
+
+            for &(file, outer, letter) in sides.iter() {
+                if let Some(file) = file {
+                    let c = if Some(file) == outer && !shredder {
+                        letter
+                    } else {
+                        (b'A' + file as u8) as char
+                    };
+
+                    castling.push(match color {
+                        Color::White => c,
+                        Color::Black => c.to_ascii_lowercase(),
+                    });
+                }
+            }
+        }
+
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        match self.en_passant {
+            Some(bb) => fen.push_str(&format!(" {} ", pos_to_algebra(bb.solo_pos()))),
+            None => fen.push_str(" - "),
         }
-    } 
+
+        fen.push_str(&format!("{} {}", self.move_rule, self.fullmove));
+        if let Some(checks) = self.checks_fen() {
+            fen.push(' ');
+            fen.push_str(&checks);
+        }
+        fen
+    }
+
+    //the same position with colors swapped and the board flipped, so every score should negate
+    #[cfg(test)]
+    fn mirror (&self) -> Self {
+        let mut piece_bb = self.piece_bb;
+        for bb in piece_bb.iter_mut() {
+            *bb = bb.mirror();
+        }
+
+        let swap = |files: [Option<u32>; PLAYER_COUNT]| [files[1], files[0]];
+
+        let mut mirrored = Self {
+            active: self.active.opposite(),
+            piece_bb,
+            player_bb: [self.player_bb[1].mirror(), self.player_bb[0].mirror()],
+            castle_ks: swap(self.castle_ks),
+            castle_qs: swap(self.castle_qs),
+            chess960: self.chess960,
+            en_passant: self.en_passant.map(|bb| bb.mirror()),
+            move_rule: self.move_rule,
+            fullmove: self.fullmove,
+            duck: self.duck.map(|pos| pos ^ 56),
+            pockets: self.pockets.map(|pockets| [pockets[1], pockets[0]]),
+            promoted: self.promoted.mirror(),
+            checks: self.checks.map(|checks| [checks[1], checks[0]]),
+            key: 0,
+            move_cache: RefCell::new(None),
+            nnue: None,
+        };
+
+        mirrored.key = mirrored.compute_hash();
+        mirrored
+    }
+
+    pub fn occupied_by (&self, color: Color) -> BitBoard {
+        self.player_bb[color as usize]
+    }
+
+    pub fn pieces_of (&self, color: Color, piece: Piece) -> BitBoard {
+        self.piece_bb[piece as usize] & self.player_bb[color as usize]
+    }
+
+    pub fn pieces (&self, color: Color) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        Piece::kinds().iter().flat_map(move |&piece| {
+            self.pieces_of(color, piece).get_indices().map(move |pos| (Square(pos), piece))
+        })
+    }
 
     fn color_at (&self, pos: u32) -> Option<Color> {
         if !(self.player_bb[Color::White as usize].empty_at(pos)) {
@@ -449,122 +922,331 @@ impl ChessState {
         }
     }
 
-    fn legal_moves (&self) -> Vec<Move> {
+    fn piece_at (&self, pos: u32) -> Option<Piece> {
+        Piece::kinds().iter()
+            .find(|&&piece| !self.piece_bb[piece as usize].empty_at(pos))
+            .copied()
+    }
+
+    //includes the duck, so it blocks sliders and pawn pushes like any piece
+    fn occupied (&self) -> BitBoard {
+        let pieces = self.player_bb[0] | self.player_bb[1];
+        match self.duck {
+            Some(pos) => pieces.add_pos(pos),
+            None => pieces,
+        }
+    }
+
+    //all pieces of the given color attacking pos, given a custom occupancy for sliders
+    fn attackers_to (&self, pos: u32, by: Color, occupied: BitBoard) -> BitBoard {
+        let rooks = self.piece_bb[Piece::Rook as usize] | self.piece_bb[Piece::Queen as usize];
+        let bishops = self.piece_bb[Piece::Bishop as usize] | self.piece_bb[Piece::Queen as usize];
+
+        let attackers = (cache.knight_moves(pos) & self.piece_bb[Piece::Knight as usize])
+            | (cache.king_moves(pos) & self.piece_bb[Piece::King as usize])
+            | (cache.pawn_attacks(by.opposite(), pos) & self.piece_bb[Piece::Pawn as usize])
+            | (magic_cache.rook_moves(pos, occupied) & rooks)
+            | (magic_cache.bishop_moves(pos, occupied) & bishops);
+
+        attackers & self.player_bb[by as usize]
+    }
+
+    /// Strictly legal moves of the side to move, as a GUI or protocol should offer them.
+    /// Cached until the next move is applied, every call shares the same moves.
+    pub fn legal_moves (&self) -> Arc<[Move]> {
+        if let Some(moves) = self.move_cache.borrow().as_ref() {
+            return Arc::clone(moves);
+        }
+
+        let moves: Arc<[Move]> = self.generate_legal_moves().into();
+        *self.move_cache.borrow_mut() = Some(Arc::clone(&moves));
+        moves
+    }
+
+    /// Legal moves with only the promotions `promotions` allows, to be ordered by the caller.
+    /// Never empty when there is a legal move, as queening is always among them. Not cached,
+    /// as the search asks once for each position.
+    pub fn legal_moves_with (&self, promotions: Promotions) -> Vec<Move> {
+        let mut moves = match self.move_cache.borrow().as_ref() {
+            Some(moves) => moves.to_vec(),
+            None => self.generate_legal_moves(),
+        };
+        if promotions == Promotions::QueenOnly {
+            moves.retain(|action| action.promotion.is_none_or(|piece| piece == Piece::Queen));
+        }
+        moves
+    }
+
+    //only pinned pieces and en passant captures can expose the king in a pseudo-legal move
+    fn generate_legal_moves (&self) -> Vec<Move> {
+        let pinned = self.pinned(self.active);
+        let en_passant = self.en_passant.unwrap_or_else(BitBoard::new);
+
+        let mut moves = self.pseudo_legal_moves();
+        moves.retain(|&action| {
+            let risky = !pinned.empty_at(action.origin)
+                || (action.piece == Piece::Pawn && !en_passant.empty_at(action.dest));
+            !risky || self.keeps_king_safe(action)
+        });
+        moves
+    }
+
+    /// Legal moves landing on `target`, e.g. every capture of one piece or every move onto the
+    /// squares that give check. Castling counts as landing on the castling rook, and en passant
+    /// as landing on the captured pawn as well.
+    pub fn generate_moves_to (&self, target: BitBoard) -> Vec<Move> {
         let mut moves = Vec::new();
+        self.king_steps(&mut moves, target);
+
+        if !self.in_check() {
+            self.castle_moves(&mut moves);
+            moves.retain(|action| !target.empty_at(action.dest));
+        }
+
+        self.piece_moves(&mut moves, target & self.player_bb[self.active as usize].invert());
+        moves.retain(|&action| self.keeps_king_safe(action));
+        moves
+    }
 
-        let occupied = self.player_bb[0] | self.player_bb[1];
+    /// Legal non-captures that give check, e.g. for quiescence search or forcing-move puzzles.
+    pub fn quiet_checks (&self) -> Vec<Move> {
+        let enemy = self.active.opposite();
+        let king = self.king_pos(enemy);
+        let occupied = self.occupied();
         let player = self.player_bb[self.active as usize];
-        let enemy = self.player_bb[self.active.opposite() as usize];
 
-        let our_king = player & self.piece_bb[Piece::King as usize];
-        let our_king_pos = our_king.solo_pos();
-        
-        let occupied_no_king = occupied & our_king.invert();
+        //squares a piece could check from, or anywhere if a blocker can step off the line
+        let candidates = if (self.blockers_for_king(enemy) & player).is_empty() {
+            cache.knight_moves(king)
+                | cache.pawn_attacks(enemy, king)
+                | magic_cache.bishop_moves(king, occupied)
+                | magic_cache.rook_moves(king, occupied)
+        } else {
+            BitBoard::new().invert()
+        };
 
-        let mut enemy_attacking = BitBoard::new();
-        let mut king_attacks = 0;
-        let mut block = BitBoard::new();
+        //our own rooks stand for castling moves, which might check with the rook
+        let rooks = self.piece_bb[Piece::Rook as usize] & player;
 
-        let mut targetable = self.player_bb[self.active as usize].invert();
-        let mut movable = occupied.invert();
-        let mut attackable = enemy;
+        let mut moves = self.generate_moves_to((candidates & occupied.invert()) | rooks);
+        moves.retain(|&action| {
+            let en_passant = action.piece == Piece::Pawn && action.origin % 8 != action.dest % 8;
+            !en_passant && self.gives_check(action)
+        });
+        moves
+    }
 
-        //ENEMY KNIGHTS
-        let bb = self.piece_bb[Piece::Knight as usize] & enemy;
-        for index in bb.get_indices() {
-            let possible = cache.knight_moves(index);
-            if possible.collides(our_king) { 
-                king_attacks += 1; 
-                block = BitBoard::from_pos(index); 
+    /// Pieces of either color standing alone between the king of `color` and an enemy slider.
+    /// Moving one off the line uncovers an attack on that king.
+    pub fn blockers_for_king (&self, color: Color) -> BitBoard {
+        let king = self.king_pos(color);
+        let enemy = self.player_bb[color.opposite() as usize];
+        let occupied = self.occupied();
+        let queens = self.piece_bb[Piece::Queen as usize];
+
+        let rooks = (self.piece_bb[Piece::Rook as usize] | queens) & enemy
+            & magic_cache.rook_moves(king, BitBoard::new());
+        let bishops = (self.piece_bb[Piece::Bishop as usize] | queens) & enemy
+            & magic_cache.bishop_moves(king, BitBoard::new());
+
+        let mut blockers = BitBoard::new();
+        for sniper in rooks.get_indices() {
+            let between = magic_cache.rook_ray(sniper, king).clear_pos(king) & occupied;
+            if between.count() == 1 {
+                blockers |= between;
             }
-            enemy_attacking |= possible;
         }
 
-        //ENEMY BISHOPS
-        let bb = self.piece_bb[Piece::Bishop as usize] & enemy;
-        for index in bb.get_indices() {
-            let possible = magic_cache.bishop_moves(index, occupied_no_king);
-            if possible.collides(our_king) { 
-                king_attacks += 1; 
-                block = magic_cache.bishop_ray(index, our_king_pos);
+        for sniper in bishops.get_indices() {
+            let between = magic_cache.bishop_ray(sniper, king).clear_pos(king) & occupied;
+            if between.count() == 1 {
+                blockers |= between;
             }
-            enemy_attacking |= possible;
         }
 
-        //ENEMY ROOKS
-        let bb = self.piece_bb[Piece::Rook as usize] & enemy;
-        for index in bb.get_indices() {
-            let possible = magic_cache.rook_moves(index, occupied_no_king);
-            if possible.collides(our_king) { 
-                king_attacks += 1; 
-                block = magic_cache.rook_ray(index, our_king_pos);
-            }
-            enemy_attacking |= possible;
+        blockers
+    }
+
+    /// Pieces of `color` that are pinned to their own king.
+    pub fn pinned (&self, color: Color) -> BitBoard {
+        self.blockers_for_king(color) & self.player_bb[color as usize]
+    }
+
+    /// Every legal move, plus moves that may still leave our own king in check (typically
+    /// by moving a pinned piece). King steps, castling and check evasions are already exact,
+    /// so engines can generate these and call `keeps_king_safe` only on moves they search.
+    pub fn pseudo_legal_moves (&self) -> Vec<Move> {
+        let checkers = self.checkers();
+
+        if checkers.is_empty() {
+            let targetable = self.player_bb[self.active as usize].invert();
+            let mut moves = Vec::new();
+            self.king_steps(&mut moves, targetable);
+            self.castle_moves(&mut moves);
+            self.piece_moves(&mut moves, targetable);
+            moves
+        } else {
+            self.evasion_moves(checkers)
         }
+    }
 
-        //ENEMY QUEENS
-        let bb = self.piece_bb[Piece::Queen as usize] & enemy;
-        for index in bb.get_indices() {
-            let rook_possible = magic_cache.rook_moves(index, occupied_no_king);
-            let bishop_possible = magic_cache.bishop_moves(index, occupied_no_king);
+    /// Every move with no regard for checks, for variants where the king can simply be captured.
+    pub fn unchecked_moves (&self) -> Vec<Move> {
+        //empty squares and enemy pieces, which leaves out the duck
+        let targetable = self.occupied().invert() | self.player_bb[self.active.opposite() as usize];
+        let mut moves = Vec::new();
 
-            if rook_possible.collides(our_king) { 
-                king_attacks += 1;
-                block = magic_cache.rook_ray(index, our_king_pos); 
+        for king in self.pieces_of(self.active, Piece::King).get_indices() {
+            for dest in (cache.king_moves(king) & targetable).get_indices() {
+                moves.push(Move::new(Piece::King, king, dest));
             }
+        }
+
+        if self.pieces_of(self.active, Piece::King).count() == 1 {
+            moves.extend(self.castle_candidates());
+        }
+        self.piece_moves(&mut moves, targetable);
+        moves
+    }
+
+    //the king steps out of check, or a single checker is captured or blocked
+    fn evasion_moves (&self, checkers: BitBoard) -> Vec<Move> {
+        let mut moves = Vec::new();
+        self.king_steps(&mut moves, self.player_bb[self.active as usize].invert());
+
+        //if the king is under attack twice, the king must move
+        if checkers.count() >= 2 {
+            return moves;
+        }
+
+        let checker = checkers.solo_pos();
+        let king = self.king_pos(self.active);
+        let block = magic_cache.rook_ray(checker, king) | magic_cache.bishop_ray(checker, king);
+
+        self.piece_moves(&mut moves, block.clear_pos(king).add_pos(checker));
+        moves
+    }
+
+    /// Enemy pieces currently giving check to the king of the side to move.
+    pub fn checkers (&self) -> BitBoard {
+        self.attackers_to(self.king_pos(self.active), self.active.opposite(), self.occupied())
+    }
 
-            else if bishop_possible.collides(our_king) {
-                king_attacks += 1;
-                block = magic_cache.bishop_ray(index, our_king_pos);
+    //squares attacked by the enemy, seeing through our king so it cannot retreat along a ray
+    fn enemy_attacks (&self) -> BitBoard {
+        let enemy = self.active.opposite();
+        let occupied = self.occupied().clear_pos(self.king_pos(self.active));
+
+        let mut attacked = BitBoard::new();
+        for &piece in Piece::kinds() {
+            let bb = self.piece_bb[piece as usize] & self.player_bb[enemy as usize];
+            for index in bb.get_indices() {
+                attacked |= piece_attacks(piece, enemy, index, occupied);
             }
+        }
+
+        attacked
+    }
+
+    //castling is encoded as the king moving onto its own rook, which also covers Chess960
+    fn is_castle (&self, action: Move) -> bool {
+        action.piece == Piece::King && !self.player_bb[self.active as usize].empty_at(action.dest)
+    }
 
-            enemy_attacking |= rook_possible | bishop_possible;
+    /// The piece `action` takes, a pawn for en passant and None for castling onto the own rook.
+    pub fn captured (&self, action: Move) -> Option<Piece> {
+        if self.color_at(action.dest) == Some(self.active.opposite()) {
+            self.piece_at(action.dest)
+        } else if action.piece == Piece::Pawn && action.origin % 8 != action.dest % 8 {
+            Some(Piece::Pawn)
+        } else {
+            None
         }
+    }
 
-        //ENEMY PAWNS
-        let bb = self.piece_bb[Piece::Pawn as usize] & enemy;
-        for index in bb.get_indices() {
-            let x = index % 8;
-            let mut possible = BitBoard::new();
-            if x > 0 { possible = possible.add_pos(index + 7); }
-            if x < 7 { possible = possible.add_pos(index + 9); }
+    //final king and rook squares of a castling move, on the g and f or c and d files
+    fn castle_squares (action: Move) -> (u32, u32) {
+        let rank = action.origin / 8 * 8;
+
+        if action.dest > action.origin {
+            (rank + 6, rank + 5)
+        } else {
+            (rank + 2, rank + 3)
+        }
+    }
+
+    //castling moves whose path is clear, whether or not the king passes through check
+    fn castle_candidates (&self) -> Vec<Move> {
+        let king = self.king_pos(self.active);
+        let rank = king / 8 * 8;
+        let rooks = self.piece_bb[Piece::Rook as usize] & self.player_bb[self.active as usize];
+        let occupied = self.occupied();
+        let mut moves = Vec::new();
+
+        for &file in &[self.castle_ks[self.active as usize], self.castle_qs[self.active as usize]] {
+            let rook = match file {
+                Some(file) if !rooks.empty_at(rank + file) => rank + file,
+                _ => continue,
+            };
 
-            if possible.collides(our_king) { 
-                king_attacks += 1; 
-                block = BitBoard::from_pos(index);
+            let action = Move::new(Piece::King, king, rook);
+            let (king_dest, rook_dest) = Self::castle_squares(action);
+
+            //everything the king and rook cross must be empty apart from themselves
+            let path = (span(king, king_dest) | span(rook, rook_dest)).clear_pos(king).clear_pos(rook);
+            if !occupied.collides(path) {
+                moves.push(action);
             }
-            enemy_attacking |= possible;
         }
 
-        let bb = self.piece_bb[Piece::King as usize] & enemy;
-        let king_pos = bb.solo_pos();
-        let possible = cache.king_moves(king_pos);
-        enemy_attacking |= possible;
+        moves
+    }
+
+    /// Whether `by` attacks any square of `path` with `occupied` as the blockers, the test
+    /// a king crossing those squares must pass in any variant that castles.
+    pub fn path_attacked (&self, path: BitBoard, by: Color, occupied: BitBoard) -> bool {
+        path.get_indices().any(|pos| !self.attackers_to(pos, by, occupied).is_empty())
+    }
+
+    fn castle_moves (&self, moves: &mut Vec<Move>) {
+        let enemy = self.active.opposite();
+        let occupied = self.occupied();
 
-        let safe_king = targetable & enemy_attacking.invert();
+        for action in self.castle_candidates() {
+            let (king_dest, _) = Self::castle_squares(action);
 
-        //KING MOVES
-        let possible = cache.king_moves(our_king_pos) & safe_king;
-        for target in possible.get_indices() {
-            moves.push(Move::new(Piece::King, our_king_pos, target));
+            //the king may not start on, cross or land on an attacked square. the castling rook
+            //is lifted too, in Chess960 it can stand between an enemy slider and the king's path
+            let occupied = occupied.clear_pos(action.origin).clear_pos(action.dest);
+            if !self.path_attacked(span(action.origin, king_dest), enemy, occupied) {
+                moves.push(action);
+            }
         }
+    }
 
-        //if the king is under attack twice, he the king must move
-        if king_attacks >= 2 { return moves; }
+    fn king_steps (&self, moves: &mut Vec<Move>, target: BitBoard) {
+        let king = self.king_pos(self.active);
+        let safe = target & self.player_bb[self.active as usize].invert() & self.enemy_attacks().invert();
 
-        //if the king is under attack, other pieces must step in between or take
-        if king_attacks == 1 {
-            targetable = targetable & block;
-            movable = movable & block;
-            attackable = attackable & block;
+        for target in (cache.king_moves(king) & safe).get_indices() {
+            moves.push(Move::new(Piece::King, king, target));
         }
+    }
+
+    //moves of every piece except the king that land on target, which must not contain our own pieces
+    fn piece_moves (&self, moves: &mut Vec<Move>, target: BitBoard) {
+        let occupied = self.occupied();
+        let player = self.player_bb[self.active as usize];
+        let enemy = self.player_bb[self.active.opposite() as usize];
+
+        let movable = occupied.invert() & target;
+        let attackable = enemy & target;
 
         //KNIGHT MOVES
         let bb = self.piece_bb[Piece::Knight as usize] & player;
-
         for index in bb.get_indices() {
-            for target in (cache.knight_moves(index) & targetable).get_indices() {
-                moves.push(Move::new(Piece::Knight, index, target));
+            for dest in (cache.knight_moves(index) & target).get_indices() {
+                moves.push(Move::new(Piece::Knight, index, dest));
             }
         }
 
@@ -574,61 +1256,48 @@ impl ChessState {
             Color::Black => 6,
         };
 
-        let end_row = match self.active {
-            Color::White => 7,
-            Color::Black => 0,
-        };
-
         let bb = self.piece_bb[Piece::Pawn as usize] & player;
         for index in bb.get_indices() {
-            let y = index / 8;
-            let x = index % 8;
-
-            if y != end_row {
+            for dest in (cache.pawn_attacks(self.active, index) & attackable).get_indices() {
+                Move::push_pawn(moves, index, dest);
+            }
 
-                //left attack
-                if x != 0 {
-                    let new_pos = match self.active {
-                        Color::White => index + 8 - 1,
-                        Color::Black => index - 8 - 1,
-                    };
+            let new_pos = match self.active {
+                Color::White => index + 8,
+                Color::Black => index - 8,
+            };
 
-                    if !attackable.empty_at(new_pos) {
-                        moves.push(Move::new(Piece::Pawn, index, new_pos));
-                    }
+            //move and double move
+            if occupied.empty_at(new_pos) {
+                if !movable.empty_at(new_pos) {
+                    Move::push_pawn(moves, index, new_pos);
                 }
 
-                //right attack
-                if x != 7 {
-                    let new_pos = match self.active {
-                        Color::White => index + 8 + 1,
-                        Color::Black => index - 8 + 1,
+                if index / 8 == double_row {
+                    let double_pos = match self.active {
+                        Color::White => index + 16,
+                        Color::Black => index - 16,
                     };
 
-                    if !attackable.empty_at(new_pos) {
-                        moves.push(Move::new(Piece::Pawn, index, new_pos));
+                    if !movable.empty_at(double_pos) {
+                        moves.push(Move::new(Piece::Pawn, index, double_pos));
                     }
                 }
+            }
+        }
 
-                let new_pos = match self.active {
-                    Color::White => index + 8,
-                    Color::Black => index - 8,
-                };
-
-                //move and double move
-                if !movable.empty_at(new_pos) {
-                    moves.push(Move::new(Piece::Pawn, index, new_pos));
-
-                    if y == double_row {
-                        let double_pos = match self.active {
-                            Color::White => index + 16,
-                            Color::Black => index - 16,
-                        };
-
-                        if !movable.empty_at(double_pos) {
-                            moves.push(Move::new(Piece::Pawn, index, double_pos));
-                        }
-                    }
+        //EN PASSANT
+        if let Some(en_passant) = self.en_passant {
+            let dest = en_passant.solo_pos();
+            let captured = match self.active {
+                Color::White => dest - 8,
+                Color::Black => dest + 8,
+            };
+
+            //when evading, capturing the checking pawn counts as hitting the target
+            if occupied.empty_at(dest) && (!target.empty_at(dest) || !target.empty_at(captured)) {
+                for index in (cache.pawn_attacks(self.active.opposite(), dest) & bb).get_indices() {
+                    moves.push(Move::new(Piece::Pawn, index, dest));
                 }
             }
         }
@@ -637,8 +1306,8 @@ impl ChessState {
         let bb = self.piece_bb[Piece::Bishop as usize] & player;
         for index in bb.get_indices() {
             let possible = magic_cache.bishop_moves(index, occupied);
-            for target in (possible & targetable).get_indices() {
-                moves.push(Move::new(Piece::Bishop, index, target));
+            for dest in (possible & target).get_indices() {
+                moves.push(Move::new(Piece::Bishop, index, dest));
             }
         }
 
@@ -646,8 +1315,8 @@ impl ChessState {
         let bb = self.piece_bb[Piece::Queen as usize] & player;
         for index in bb.get_indices() {
             let possible = magic_cache.bishop_moves(index, occupied) | magic_cache.rook_moves(index, occupied);
-            for target in (possible & targetable).get_indices() {
-                moves.push(Move::new(Piece::Queen, index, target));
+            for dest in (possible & target).get_indices() {
+                moves.push(Move::new(Piece::Queen, index, dest));
             }
         }
 
@@ -655,46 +1324,526 @@ impl ChessState {
         let bb = self.piece_bb[Piece::Rook as usize] & player;
         for index in bb.get_indices() {
             let possible = magic_cache.rook_moves(index, occupied);
-            for target in (possible & targetable).get_indices() {
-                moves.push(Move::new(Piece::Rook, index, target));
+            for dest in (possible & target).get_indices() {
+                moves.push(Move::new(Piece::Rook, index, dest));
             }
         }
-
-        moves
     }
 
-    fn apply_move (&mut self, action: Move) {
-        self.player_bb[self.active.opposite() as usize] = self.player_bb[self.active.opposite() as usize].clear_pos(action.dest);
-        for &piece in Piece::kinds() {
-            self.piece_bb[piece as usize] = self.piece_bb[piece as usize].clear_pos(action.dest);
+    //captures and promotions only, for quiescence and tactical tooling
+    fn capture_moves (&self) -> Vec<Move> {
+        let enemy = self.player_bb[self.active.opposite() as usize];
+
+        let mut moves = Vec::new();
+        self.king_steps(&mut moves, enemy);
+        self.piece_moves(&mut moves, enemy);
+
+        //PROMOTION PUSHES
+        let (seventh_row, step) = match self.active {
+            Color::White => (6, 8),
+            Color::Black => (1, -8),
+        };
+
+        let bb = self.piece_bb[Piece::Pawn as usize] & self.player_bb[self.active as usize];
+        for index in bb.get_indices().filter(|index| index / 8 == seventh_row) {
+            let dest = (index as i32 + step) as u32;
+            if self.occupied().empty_at(dest) {
+                Move::push_pawn(&mut moves, index, dest);
+            }
+        }
+
+        moves.retain(|&action| self.keeps_king_safe(action));
+        moves
+    }
+
+    /// Checks an arbitrary move, e.g. read from a protocol, without generating the move list.
+    pub fn is_legal (&self, action: Move) -> bool {
+        let player = self.player_bb[self.active as usize];
+
+        if action.origin >= 64 || action.dest >= 64 || self.piece_bb[action.piece as usize].empty_at(action.origin) || player.empty_at(action.origin) {
+            return false;
+        }
+
+        if self.is_castle(action) {
+            let mut castles = Vec::new();
+            if !self.in_check() {
+                self.castle_moves(&mut castles);
+            }
+            return castles.contains(&action);
+        }
+
+        //nothing may move onto the duck
+        if !player.empty_at(action.dest) || self.duck == Some(action.dest) {
+            return false;
+        }
+
+        let reachable = self.reaches(action);
+
+        //pawns reaching the last rank must promote to a real piece, nothing else may promote
+        let last_row = action.piece == Piece::Pawn && (action.dest / 8 == 0 || action.dest / 8 == 7);
+        let promotion_valid = match action.promotion {
+            None => !last_row,
+            Some(Piece::Pawn) | Some(Piece::King) => false,
+            Some(_) => last_row,
+        };
+
+        reachable && promotion_valid && self.keeps_king_safe(action)
+    }
+
+    //whether the piece on origin can move to dest by its movement rules, ignoring castling and pins
+    fn reaches (&self, action: Move) -> bool {
+        let enemy = self.player_bb[self.active.opposite() as usize];
+        let occupied = self.occupied();
+
+        match action.piece {
+            Piece::Pawn => {
+                let (step, double_row) = match self.active {
+                    Color::White => (8, 1),
+                    Color::Black => (-8, 6),
+                };
+
+                let single = (action.origin as i32 + step) as u32;
+                let double = (action.origin as i32 + 2 * step) as u32;
+
+                let capture = !cache.pawn_attacks(self.active, action.origin).empty_at(action.dest)
+                    && (!enemy.empty_at(action.dest) || self.en_passant.is_some_and(|bb| !bb.empty_at(action.dest)));
+                let push = action.dest == single && occupied.empty_at(single);
+                let double_push = action.dest == double && action.origin / 8 == double_row
+                    && occupied.empty_at(single) && occupied.empty_at(double);
+
+                capture || push || double_push
+            }
+            piece => !piece_attacks(piece, self.active, action.origin, occupied).empty_at(action.dest),
+        }
+    }
+
+    /// Whether a move puts the enemy king in check, directly or by uncovering a slider,
+    /// worked out from the resulting occupancy without applying the move.
+    pub fn gives_check (&self, action: Move) -> bool {
+        let king = self.king_pos(self.active.opposite());
+        let mut occupied = self.occupied().clear_pos(action.origin);
+        let mut moved = BitBoard::from_pos(action.origin);
+
+        let (piece, dest) = if self.is_castle(action) {
+            let (king_dest, rook_dest) = Self::castle_squares(action);
+            occupied = occupied.clear_pos(action.dest).add_pos(king_dest).add_pos(rook_dest);
+            moved = moved.add_pos(action.dest);
+            (Piece::Rook, rook_dest)
+        } else {
+            occupied = occupied.add_pos(action.dest);
+
+            //the pawn captured en passant can uncover a check along the rank
+            if action.piece == Piece::Pawn && self.en_passant.is_some_and(|bb| !bb.empty_at(action.dest)) {
+                occupied = occupied.clear_pos(match self.active {
+                    Color::White => action.dest - 8,
+                    Color::Black => action.dest + 8,
+                });
+            }
+
+            (action.promotion.unwrap_or(action.piece), action.dest)
+        };
+
+        let direct = !piece_attacks(piece, self.active, dest, occupied).empty_at(king);
+        let discovered = !(self.attackers_to(king, self.active, occupied) & moved.invert()).is_empty();
+
+        direct || discovered
+    }
+
+    /// Whether a pseudo-legal move leaves our king out of check, which makes it legal.
+    pub fn keeps_king_safe (&self, action: Move) -> bool {
+        let mut next = self.clone();
+        next.apply_move(action);
+
+        let king = next.king_pos(self.active);
+        next.attackers_to(king, next.active, next.occupied()).is_empty()
+    }
+
+    fn apply_move (&mut self, action: Move) {
+        *self.move_cache.get_mut() = None;
+
+        //castling rights, en passant and the side to move are hashed back in once they are updated
+        self.key ^= self.rules_key();
+        let boards = self.nnue.as_ref().map(|_| (self.piece_bb, self.player_bb));
+
+        if self.pockets.is_some() {
+            self.pocket_capture(action);
+        }
+
+        let en_passant = self.en_passant.take();
+        if action.piece == Piece::Pawn && en_passant.is_some_and(|bb| !bb.empty_at(action.dest)) {
+            let captured = match self.active {
+                Color::White => action.dest - 8,
+                Color::Black => action.dest + 8,
+            };
+
+            self.key ^= zobrist::piece_key(self.active.opposite(), Piece::Pawn, captured);
+            let enemy = self.active.opposite() as usize;
+            self.player_bb[enemy] = self.player_bb[enemy].clear_pos(captured);
+            self.piece_bb[Piece::Pawn as usize] = self.piece_bb[Piece::Pawn as usize].clear_pos(captured);
+        }
+
+        //the square skipped by a double push can be captured en passant on the next move only
+        if action.piece == Piece::Pawn && (action.origin as i32 - action.dest as i32).abs() == 16 {
+            self.en_passant = Some(BitBoard::from_pos((action.origin + action.dest) / 2));
+        }
+
+        let capture = !self.player_bb[self.active.opposite() as usize].empty_at(action.dest);
+
+        if capture || action.piece == Piece::Pawn {
+            self.move_rule = 0;
+        } else {
+            self.move_rule += 1;
+        }
+
+        let castling = self.is_castle(action);
+
+        //moving the king, or moving or losing a castling rook, gives up castling on that side
+        if action.piece == Piece::King {
+            self.castle_ks[self.active as usize] = None;
+            self.castle_qs[self.active as usize] = None;
+        }
+
+        for &color in &[Color::White, Color::Black] {
+            let rank = match color {
+                Color::White => 0,
+                Color::Black => 56,
+            };
+
+            for &pos in &[action.origin, action.dest] {
+                if self.castle_ks[color as usize].map(|file| rank + file) == Some(pos) {
+                    self.castle_ks[color as usize] = None;
+                }
+
+                if self.castle_qs[color as usize].map(|file| rank + file) == Some(pos) {
+                    self.castle_qs[color as usize] = None;
+                }
+            }
+        }
+
+        if castling {
+            let (king_dest, rook_dest) = Self::castle_squares(action);
+
+            self.key ^= zobrist::piece_key(self.active, Piece::King, action.origin)
+                ^ zobrist::piece_key(self.active, Piece::King, king_dest)
+                ^ zobrist::piece_key(self.active, Piece::Rook, action.dest)
+                ^ zobrist::piece_key(self.active, Piece::Rook, rook_dest);
+
+            self.player_bb[self.active as usize] = self.player_bb[self.active as usize]
+                .clear_pos(action.origin).clear_pos(action.dest).add_pos(king_dest).add_pos(rook_dest);
+            self.piece_bb[Piece::King as usize] = self.piece_bb[Piece::King as usize]
+                .clear_pos(action.origin).add_pos(king_dest);
+            self.piece_bb[Piece::Rook as usize] = self.piece_bb[Piece::Rook as usize]
+                .clear_pos(action.dest).add_pos(rook_dest);
+        } else {
+            if let (Some(piece), true) = (self.piece_at(action.dest), capture) {
+                self.key ^= zobrist::piece_key(self.active.opposite(), piece, action.dest);
+            }
+
+            let placed = action.promotion.unwrap_or(action.piece);
+            self.key ^= zobrist::piece_key(self.active, action.piece, action.origin)
+                ^ zobrist::piece_key(self.active, placed, action.dest);
+
+            self.player_bb[self.active.opposite() as usize] = self.player_bb[self.active.opposite() as usize].clear_pos(action.dest);
+            for &piece in Piece::kinds() {
+                self.piece_bb[piece as usize] = self.piece_bb[piece as usize].clear_pos(action.dest);
+            }
+
+            self.player_bb[self.active as usize] = self.player_bb[self.active as usize]
+                .clear_pos(action.origin).add_pos(action.dest);
+            self.piece_bb[action.piece as usize] = self.piece_bb[action.piece as usize].clear_pos(action.origin);
+            self.piece_bb[placed as usize] = self.piece_bb[placed as usize].add_pos(action.dest);
+        }
+
+        if self.active == Color::Black {
+            self.fullmove += 1;
         }
 
-        self.player_bb[self.active as usize] = self.player_bb[self.active as usize]
-            .clear_pos(action.origin).add_pos(action.dest);
-        self.piece_bb[action.piece as usize] = self.piece_bb[action.piece as usize]
-            .clear_pos(action.origin).add_pos(action.dest);
-            
         self.active = self.active.opposite();
+        self.key ^= self.rules_key();
+
+        if self.checks.is_some() {
+            self.count_check();
+        }
+
+        if let Some((pieces, players)) = boards {
+            self.update_accumulator(&pieces, &players);
+        }
+    }
+
+    /// Passes the turn without moving, for null-move pruning and threat analysis.
+    /// Returns the en passant square it clears, which `unmake_null_move` needs to restore.
+    pub fn make_null_move (&mut self) -> Option<BitBoard> {
+        *self.move_cache.get_mut() = None;
+        self.key ^= self.rules_key();
+        self.active = self.active.opposite();
+        let en_passant = self.en_passant.take();
+        self.key ^= self.rules_key();
+        en_passant
+    }
+
+    pub fn unmake_null_move (&mut self, en_passant: Option<BitBoard>) {
+        *self.move_cache.get_mut() = None;
+        self.key ^= self.rules_key();
+        self.active = self.active.opposite();
+        self.en_passant = en_passant;
+        self.key ^= self.rules_key();
+    }
+
+    fn king_pos (&self, color: Color) -> u32 {
+        (self.piece_bb[Piece::King as usize] & self.player_bb[color as usize]).solo_pos()
+    }
+
+    fn in_check (&self) -> bool {
+        !self.checkers().is_empty()
+    }
+
+    //a lone minor piece, or only bishops that all stand on one square color, cannot mate
+    fn insufficient_material (&self) -> bool {
+        let heavy = self.piece_bb[Piece::Pawn as usize]
+            | self.piece_bb[Piece::Rook as usize]
+            | self.piece_bb[Piece::Queen as usize];
+        let knights = self.piece_bb[Piece::Knight as usize];
+        let minors = self.piece_bb[Piece::Bishop as usize] | knights;
+
+        heavy.is_empty() && (minors.count() <= 1 || (knights.is_empty() && self.same_color_bishops()))
+    }
+
+    /// Whether every bishop on the board, of either side, stands on the same square color.
+    pub fn same_color_bishops (&self) -> bool {
+        let bishops = self.piece_bb[Piece::Bishop as usize];
+        (bishops & LIGHT_SQUARES).is_empty() || (bishops & LIGHT_SQUARES.invert()).is_empty()
+    }
+
+    /// Pawns of `color` standing on squares of `square_color`.
+    pub fn pawns_on (&self, color: Color, square_color: SquareColor) -> u32 {
+        (self.pieces_of(color, Piece::Pawn) & square_color.squares()).count()
+    }
+
+    //a hundred halfmoves without a pawn move or capture
+    fn fifty_move_draw (&self) -> bool {
+        self.move_rule >= 100
+    }
+
+    fn outcome (&self) -> Option<GameResult> {
+        if self.legal_moves().is_empty() {
+            if self.in_check() {
+                Some(GameResult::Checkmate { winner: self.active.opposite() })
+            } else {
+                Some(GameResult::Stalemate)
+            }
+        } else if self.fifty_move_draw() {
+            Some(GameResult::FiftyMoveRule)
+        } else if self.insufficient_material() {
+            Some(GameResult::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
+    //matches user input such as "e2e4" or "e7e8n" against the legal moves,
+    //promotions without a piece letter become queens
+    fn find_move (&self, text: &str) -> Result<Move, Error> {
+        self.find_among(text, &self.legal_moves()).ok_or_else(|| Error::IllegalMove(text.trim().to_string()))
+    }
+
+    /// A move in coordinates, as find_move reads it and UCI writes it, e.g. "e2e4" or "e7e8q".
+    /// Castling is the king's two square step, or taking its own rook in Chess960.
+    pub fn coordinates (&self, action: Move) -> String {
+        let dest = if self.is_castle(action) && !self.chess960 {
+            Self::castle_squares(action).0
+        } else {
+            action.dest
+        };
+
+        let mut text = format!("{}{}", pos_to_algebra(action.origin), pos_to_algebra(dest));
+        if let Some(piece) = action.promotion {
+            text.push(piece.letter(Color::Black));
+        }
+        text
+    }
+
+    fn find_among (&self, text: &str, moves: &[Move]) -> Option<Move> {
+        let text = text.trim();
+        if (text.len() != 4 && text.len() != 5) || !text.is_ascii() {
+            return None;
+        }
+
+        let origin = parse_pos(&text[0..2])?;
+        let dest = parse_pos(&text[2..4])?;
+
+        let promotion = match text[4..].chars().next() {
+            Some(c) => Some(Piece::from_letter(c.to_ascii_lowercase())?),
+            None => None,
+        };
+
+        //outside of Chess960 castling can also be entered as the king's two square step
+        let castle_dest = |action: Move| if self.is_castle(action) && !self.chess960 {
+            Some(Self::castle_squares(action).0)
+        } else {
+            None
+        };
+
+        moves.iter()
+            .copied()
+            .find(|&action| {
+                action.origin == origin
+                && (action.dest == dest || castle_dest(action) == Some(dest))
+                && match action.promotion {
+                    None => promotion.is_none(),
+                    Some(piece) => piece == promotion.unwrap_or(Piece::Queen),
+                }
+            })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameResult {
+    Checkmate { winner: Color },
+    Stalemate,
+    FiftyMoveRule,
+    InsufficientMaterial,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::Checkmate { winner } => write!(f, "Checkmate, {:?} wins.", winner),
+            GameResult::Stalemate => write!(f, "Stalemate, the game is drawn."),
+            GameResult::FiftyMoveRule => write!(f, "Fifty moves without progress, the game is drawn."),
+            GameResult::InsufficientMaterial => write!(f, "Insufficient material, the game is drawn."),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FenField {
+    Placement,
+    ActiveColor,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber,
+    //the checks given in three-check, after the fullmove number
+    Checks,
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FenField::Placement => "piece placement",
+            FenField::ActiveColor => "active color",
+            FenField::Castling => "castling",
+            FenField::EnPassant => "en passant",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveNumber => "fullmove number",
+            FenField::Checks => "checks",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(FenField),
+    //index is the character offset into the whole FEN
+    InvalidChar { field: FenField, index: usize, found: char },
+    RankLength { rank: u32 },
+    KingCount(Color),
+    PawnOnBackRank,
+    //the side not to move is in check, so its king could be captured
+    OpponentInCheck(Color),
+}
+
+impl FenError {
+    fn invalid (field: FenField, start: usize, text: &str, i: usize) -> Self {
+        let found = text[i..].chars().next().unwrap_or(' ');
+        FenError::InvalidChar { field, index: start + i, found }
+    }
+
+    fn counter (field: FenField, start: usize, text: &str) -> Result<u32, Self> {
+        if let Some(i) = text.find(|c: char| !c.is_ascii_digit()) {
+            return Err(Self::invalid(field, start, text, i));
+        }
+
+        text.parse().map_err(|_| Self::invalid(field, start, text, 0))
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "The {} field is missing.", field),
+            FenError::InvalidChar { field, index, found } =>
+                write!(f, "Unexpected '{}' at character {} in the {} field.", found, index, field),
+            FenError::RankLength { rank } => write!(f, "Rank {} does not have eight squares.", rank),
+            FenError::KingCount(color) => write!(f, "{:?} must have exactly one king.", color),
+            FenError::PawnOnBackRank => write!(f, "Pawns cannot stand on the first or eighth rank."),
+            FenError::OpponentInCheck(color) => write!(f, "{:?} is in check but it is not their move.", color),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl TryFrom<&str> for ChessState {
+    type Error = FenError;
+
+    fn try_from(fen: &str) -> Result<Self, FenError> {
+        Self::parse_fen(fen)
     }
 }
 
-#[derive(Copy, Clone)]
-struct Move {
-    piece: Piece,
-    origin: u32,
-    dest: u32,
+/// Which promotions move generation offers. Every generator lists all of them by default,
+/// an engine may choose to only look at queening to save the underpromotion branches.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Promotions {
+    All,
+    QueenOnly,
+}
+
+/// A move as the piece moving, its origin and destination square and the piece a pawn promotes to.
+/// Castling is the king moving onto its own rook.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub piece: Piece,
+    pub origin: u32,
+    pub dest: u32,
+    pub promotion: Option<Piece>,
 }
 
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}: {} -> {}", self.piece, pos_to_algebra(self.origin), pos_to_algebra(self.dest))
+        write!(f, "{:?}: {} -> {}", self.piece, pos_to_algebra(self.origin), pos_to_algebra(self.dest))?;
+
+        if let Some(piece) = self.promotion {
+            write!(f, " = {:?}", piece)?;
+        }
+
+        Ok(())
     }
 }
 
 impl Move {
     fn new(piece: Piece, origin: u32, dest: u32) -> Self {
-        Self { piece, origin, dest }
+        Self { piece, origin, dest, promotion: None }
+    }
+
+    fn promote(origin: u32, dest: u32, piece: Piece) -> Self {
+        Self { piece: Piece::Pawn, origin, dest, promotion: Some(piece) }
+    }
+
+    //pushes a pawn move, expanded into every promotion when it reaches the last rank
+    fn push_pawn(moves: &mut Vec<Move>, origin: u32, dest: u32) {
+        let y = dest / 8;
+
+        if y == 0 || y == 7 {
+            for &piece in &[Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                moves.push(Move::promote(origin, dest, piece));
+            }
+        } else {
+            moves.push(Move::new(Piece::Pawn, origin, dest));
+        }
     }
 }
 
@@ -719,6 +1868,10 @@ impl fmt::Display for ChessState {
             }
         }
 
+        if let Some(duck) = self.duck {
+            board[duck as usize] = '●';
+        }
+
         for chunk in board.chunks(8).rev() {
             writeln!(f, "{}", chunk.iter().collect::<String>())?;
         }
@@ -726,22 +1879,39 @@ impl fmt::Display for ChessState {
     }
 }
 
-fn algebra_to_pos(rank: char, file: char) -> u32 {
-    let rank_bin = match rank {
-        'a' => 0,
-        'b' => 1,
-        'c' => 2,
-        'd' => 3,
-        'e' => 4,
-        'f' => 5,
-        'g' => 6,
-        'h' => 7,
-        _ => panic!("Invalid position.") 
-    };
+//positions travel as FEN, in Shredder-FEN for Chess960 so the variant survives the trip
+impl Serialize for ChessState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.chess960 {
+            serializer.serialize_str(&self.to_shredder_fen())
+        } else {
+            serializer.serialize_str(&self.to_fen())
+        }
+    }
+}
 
-    let file_bin = file.to_digit(10).expect("Invalid position.") - 1;
+impl<'de> Deserialize<'de> for ChessState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fen = String::deserialize(deserializer)?;
+        Self::parse_fen(&fen).map_err(de::Error::custom)
+    }
+}
 
-    file_bin * 8 + rank_bin
+//every square from a to b inclusive, for squares on the same rank
+fn span(a: u32, b: u32) -> BitBoard {
+    (a.min(b)..=a.max(b)).fold(BitBoard::new(), |bb, pos| bb.add_pos(pos))
+}
+
+fn parse_pos(square: &str) -> Option<u32> {
+    let mut chars = square.chars();
+    let rank = chars.next()?;
+    let file = chars.next()?;
+
+    if chars.next().is_some() || !('a'..='h').contains(&rank) || !('1'..='8').contains(&file) {
+        return None;
+    }
+
+    Some((file as u32 - '1' as u32) * 8 + (rank as u32 - 'a' as u32))
 }
 
 fn pos_to_algebra(pos: u32) -> String {
@@ -777,41 +1947,878 @@ fn pos_to_algebra(pos: u32) -> String {
     algebra
 }
 
-#[post("/move/<origin>/<dest>")]
-fn web_move(origin: String, dest: String, state: State<Mutex<ChessState>>) -> &str {
-    let mut current_state: MutexGuard<ChessState> = state.lock().unwrap();
+//a game that reached king and pawn against king is adjudicated by the bitbase rather than
+//played out, returning why it ended. games against a person only do so when they ask for it,
+//as a player may want to practise the ending
+fn adjudicate_kpk(state: &ChessState, game: &mut pgn::Game) -> Option<String> {
+    let wins = kpk::probe(state)?;
+    let (_, strong) = state.endgame();
 
-    let orig = origin.chars().collect::<Vec<_>>();
-    let dest = dest.chars().collect::<Vec<_>>();
+    if wins {
+        game.adjudicate(Some(strong));
+        Some(format!("King and pawn against king, {:?} wins.", strong))
+    } else {
+        game.adjudicate(None);
+        Some("King and pawn against king, the game is drawn.".to_string())
+    }
+}
+
+//rewinds to before the requester's last move, two plies if the opponent already replied
+fn take_back(history: &mut Vec<ChessState>, current: &ChessState, requester: Color) -> Option<ChessState> {
+    let plies = if current.active == requester { 2 } else { 1 };
+    if history.len() < plies {
+        return None;
+    }
 
-    let origin = algebra_to_pos(orig[0], orig[1]);
-    let dest = algebra_to_pos(dest[0], dest[1]);
+    let keep = history.len() - plies;
+    let state = history[keep].clone();
+    history.truncate(keep);
+    Some(state)
+}
 
-    let moves = current_state.legal_moves();
-    let mut moved = false;
+struct WebGame {
+    state: ChessState,
+    history: Vec<ChessState>,
+    takeback_request: Option<Color>,
+    //fog of war rules: no check, and the game ends when a king is captured
+    fog: bool,
+}
 
-    for &action in &moves {
-        if origin == action.origin && dest == action.dest {
-            current_state.apply_move(action);
-            moved = true;
-            break;
+impl WebGame {
+    fn new() -> Self {
+        Self {
+            state: ChessState::default(),
+            history: Vec::new(),
+            takeback_request: None,
+            fog: false,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        if self.fog {
+            self.state.fog_finished()
+        } else {
+            self.state.outcome().is_some()
         }
     }
 
-    println!("Valid #: {}", moves.len());
-    println!("Valid: {}", moved);
 
-    if moved {
+    fn play(&mut self, text: &str) -> bool {
+        let action = if self.fog {
+            self.state.find_fog_move(text)
+        } else {
+            self.state.find_move(text).ok()
+        };
+        if let Some(action) = action {
+            metrics::count_move();
+            self.history.push(self.state.clone());
+            self.state.apply_move(action);
+            self.takeback_request = None;
+        }
+
+        action.is_some()
+    }
+}
+
+fn web_apply(game: &Mutex<WebGame>, text: &str) -> &'static str {
+    let mut game: MutexGuard<WebGame> = game.lock().unwrap();
+
+    let valid = game.play(text);
+
+    if valid {
         "valid"
     } else {
         "invalid"
     }
 }
 
-fn main() {
+//promotions default to a queen unless the piece is given
+#[post("/move/<origin>/<dest>")]
+fn web_move(origin: String, dest: String, game: State<Mutex<WebGame>>) -> &'static str {
+    web_apply(&game, &format!("{}{}", origin, dest))
+}
+
+#[post("/move/<origin>/<dest>/<promotion>")]
+fn web_promote(origin: String, dest: String, promotion: String, game: State<Mutex<WebGame>>) -> &'static str {
+    web_apply(&game, &format!("{}{}{}", origin, dest, promotion))
+}
+
+//the requesting side is given by its FEN letter, the takeback waits for the opponent to accept
+#[post("/takeback/request/<color>")]
+fn web_takeback_request(color: String, game: State<Mutex<WebGame>>) -> &'static str {
+    let mut game = game.lock().unwrap();
+
+    match color.chars().next().and_then(Color::from_letter) {
+        Some(color) if game.takeback_request.is_none() => {
+            game.takeback_request = Some(color);
+            "requested"
+        }
+        _ => "invalid",
+    }
+}
+
+#[post("/takeback/accept")]
+fn web_takeback_accept(game: State<Mutex<WebGame>>) -> &'static str {
+    let mut game = game.lock().unwrap();
+    let game = &mut *game;
+
+    let requester = match game.takeback_request.take() {
+        Some(requester) => requester,
+        None => return "invalid",
+    };
+
+    match take_back(&mut game.history, &game.state, requester) {
+        Some(state) => {
+            game.state = state;
+            "accepted"
+        }
+        None => "invalid",
+    }
+}
+
+#[post("/takeback/decline")]
+fn web_takeback_decline(game: State<Mutex<WebGame>>) -> &'static str {
+    match game.lock().unwrap().takeback_request.take() {
+        Some(_) => "declined",
+        None => "invalid",
+    }
+}
+
+//randomized play draws from a seeded generator, so a game can be replayed from its seed
+fn seeded_rng(seed: Option<u64>) -> (u64, StdRng) {
+    let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+    (seed, StdRng::seed_from_u64(seed))
+}
+
+//deepest a search on the clock goes, which runs out long before
+const CLOCK_DEPTH: u32 = 64;
+
+//how a game against the engine on the command line is set up
+struct CliGame {
+    auto_queen: bool,
+    //the finished game is written as PGN here when given
+    pgn_path: Option<String>,
+    //the hint level from the teach module, 0 for none
+    teaching: u32,
+    seed: Option<u64>,
+    depth: Option<u32>,
+    engine: search::Engine,
+    clock: Option<time::TimeControl>,
+    skill: Option<skill::Skill>,
+    book: Option<(openings::Book, openings::BookChoice)>,
+    //ends the game by the bitbase once it reaches king and pawn against king
+    adjudicate: bool,
+}
+
+//black plays from `book` while the game is in it and says when it leaves it. after that it
+//plays at `skill` when given, else searches `depth` plies when given, or as long as its clock
+//allows with one, otherwise it plays random moves
+fn play_cli(options: CliGame) {
+    let CliGame { auto_queen, pgn_path, teaching, seed, depth, mut engine, mut clock, skill, book, adjudicate } = options;
+    let mut state = ChessState::default();
+    let (seed, mut rng) = seeded_rng(seed);
+
+    //on the clock the engine thinks for a while, so each depth it finishes is shown
+    if clock.is_some() {
+        engine.set_listener(Some(Box::new(|event| if let search::SearchEvent::Depth { depth, score, nodes, nps, .. } = event {
+            println!("depth {} score {} nodes {} nps {}", depth, score, nodes, nps);
+        })));
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    let mut history = Vec::new();
+    let mut times = Vec::new();
+    //the clocks as they stood before each move in history
+    let mut clocks = Vec::new();
+    //whether black's last move came from the book
+    let mut in_book = book.is_some();
+
+    let mut game = pgn::Game::new();
+    game.set_tag("White", "Player");
+    game.set_tag("Black", if depth.is_some() || clock.is_some() || skill.is_some() { "Engine" } else { "Random mover" });
+    game.set_tag("Seed", &seed.to_string());
+    if let Some(skill) = skill {
+        game.set_tag("BlackSkill", &skill.level().to_string());
+    }
+
+    'game: loop {
+        print!("{}", state);
+        print_material(&game);
+        println!();
+
+        if let Some(result) = state.outcome() {
+            println!("{}", result);
+            break;
+        }
+
+        if adjudicate {
+            if let Some(reason) = adjudicate_kpk(&state, &mut game) {
+                println!("{}", reason);
+                break;
+            }
+        }
+
+        if state.active == Color::White && !state.checkers().is_empty() {
+            println!("You are in check!");
+        }
+
+        let started = Instant::now();
+        let clock_before = clock;
+        //so the engine sees repetitions of the positions played so far
+        engine.set_game(history.iter().map(ChessState::hash).collect());
+
+        let action = match state.active {
+            Color::White => loop {
+                let line = match lines.next() {
+                    Some(Ok(line)) => line,
+                    _ => break 'game,
+                };
+
+                let mut text = line.trim().to_string();
+
+                //the engine always agrees to take back
+                if text == "takeback" {
+                    match take_back(&mut history, &state, Color::White) {
+                        Some(previous) => {
+                            println!("The engine accepts the takeback.");
+                            state = previous;
+                            times.truncate(history.len());
+                            game.truncate(history.len());
+                            clock = clocks[history.len()];
+                            clocks.truncate(history.len());
+                            continue 'game;
+                        }
+                        None => println!("There is no move to take back."),
+                    }
+                    continue;
+                }
+
+                let promoting = state.find_move(&text).is_ok_and(|action| action.promotion.is_some());
+
+                if promoting && text.len() == 4 && !auto_queen {
+                    println!("Promote to (q, r, b, n)?");
+                    match lines.next() {
+                        Some(Ok(piece)) => text.push_str(piece.trim()),
+                        _ => break 'game,
+                    }
+                }
+
+                match state.find_move(&text) {
+                    Ok(action) => break action,
+                    Err(_) => println!("Illegal move. {}", state.explain_illegal(&text)),
+                }
+            },
+
+            Color::Black => match book.as_ref().and_then(|(book, choice)| book.pick(&state, *choice, &mut rng)) {
+                Some(action) => {
+                    in_book = true;
+                    match book.as_ref().and_then(|(book, _)| book.comment(&state, action)) {
+                        Some(comment) => println!("Book move: {}", comment),
+                        None => println!("Book move."),
+                    }
+                    action
+                }
+                None => {
+                    let leaving_book = std::mem::replace(&mut in_book, false);
+                    if leaving_book {
+                        println!("Out of book.");
+                    }
+
+                    match (skill, depth, clock) {
+                        (Some(skill), _, _) => skill.choose(&mut engine, &state, &mut rng).unwrap(),
+                        (None, _, Some(clock)) => {
+                            let mut time = time::TimeManager::new(&clock, Color::Black);
+                            if leaving_book {
+                                time.leave_book();
+                            }
+                            let result = engine.search_timed(&state, &mut time, depth.unwrap_or(CLOCK_DEPTH));
+                            println!("{}", result.info(&state));
+                            result.best.unwrap()
+                        }
+                        (None, Some(depth), None) => {
+                            let result = engine.search(&state, depth);
+                            println!("{}", result.info(&state));
+                            result.best.unwrap()
+                        }
+                        (None, None, None) => {
+                            let moves = state.legal_moves();
+                            moves[rng.gen_range(0, moves.len())]
+                        }
+                    }
+                }
+            },
+        };
+
+        println!("{}", state.san(action));
+        times.push(started.elapsed());
+
+        if let Some(clock) = clock.as_mut() {
+            if !clock.charge(state.active, started.elapsed()) {
+                println!("{:?} ran out of time.", state.active);
+                break;
+            }
+            println!("White {}, Black {}", format_clock(clock.time[0]), format_clock(clock.time[1]));
+        }
+
+        if state.active == Color::White {
+            for hint in teach::hints(&state, action, teaching) {
+                println!("{}", hint);
+            }
+        }
+
+        history.push(state.clone());
+        clocks.push(clock_before);
+        state.apply_move(action);
+        game.push(action);
+    }
+
+    print_time_report(&times);
+
+    if let Some(path) = pgn_path {
+        if let Err(error) = std::fs::write(&path, game.to_pgn()) {
+            println!("Could not write {}: {}", path, error);
+        }
+    }
+}
+
+//duck chess against a random mover, moves are entered as e.g. "e2e4@d5"
+fn play_duck_cli(pgn_path: Option<String>, seed: Option<u64>) {
+    let mut state = ChessState::default();
+    let (seed, mut rng) = seeded_rng(seed);
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    let mut game = pgn::Game::duck_chess();
+    game.set_tag("White", "Player");
+    game.set_tag("Black", "Random mover");
+    game.set_tag("Seed", &seed.to_string());
+
+    loop {
+        println!("{}", state);
+
+        if let Some(winner) = state.duck_winner() {
+            println!("{:?} wins.", winner);
+            break;
+        }
+
+        let (action, duck) = match state.active {
+            Color::White => match lines.next() {
+                Some(Ok(line)) => match state.parse_duck_move(&line) {
+                    Some(duck_move) => duck_move,
+                    None => {
+                        println!("Illegal move, enter the move and the duck's square like e2e4@d5.");
+                        continue;
+                    }
+                },
+                _ => break,
+            },
+
+            Color::Black => {
+                let moves = state.unchecked_moves();
+                let action = moves[rng.gen_range(0, moves.len())];
+                let squares: Vec<u32> = state.duck_squares(action).get_indices().collect();
+                (action, squares[rng.gen_range(0, squares.len())])
+            }
+        };
+
+        println!("{}", state.duck_san(action, duck));
+        state.apply_duck_move(action, duck);
+        game.push_duck(action, duck);
+    }
+
+    if let Some(path) = pgn_path {
+        if let Err(error) = std::fs::write(&path, game.to_pgn()) {
+            println!("Could not write {}: {}", path, error);
+        }
+    }
+}
+
+//fog of war against a random mover, showing only what white can see until the game is over
+fn play_fog_cli(seed: Option<u64>) {
+    let mut state = ChessState::default();
+    let (seed, mut rng) = seeded_rng(seed);
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    while !state.fog_finished() {
+        let action = match state.active {
+            Color::White => {
+                println!("{}", state.fog_view(Color::White));
+
+                match lines.next() {
+                    Some(Ok(line)) => match state.find_fog_move(&line) {
+                        Some(action) => action,
+                        None => {
+                            println!("Illegal move.");
+                            continue;
+                        }
+                    },
+                    _ => return,
+                }
+            }
+
+            Color::Black => {
+                let moves = state.unchecked_moves();
+                moves[rng.gen_range(0, moves.len())]
+            }
+        };
+
+        state.apply_move(action);
+    }
+
+    println!("{}", state);
+    match state.fog_winner() {
+        Some(winner) => println!("The king was captured, {:?} wins.", winner),
+        None => println!("No moves left, the game is drawn."),
+    }
+    println!("Seed: {}", seed);
+}
+
+//the pieces each side has taken, beside the material lead of the side ahead
+fn print_material(game: &pgn::Game) {
+    let captured = game.captured_pieces();
+    let balance = game.state().material_balance();
+    if captured.iter().all(Vec::is_empty) {
+        return;
+    }
+
+    for &color in &[Color::White, Color::Black] {
+        let mut pieces = captured[color as usize].clone();
+        pieces.sort_by_key(|&piece| std::cmp::Reverse(tactics::value(piece)));
+
+        let line: String = pieces.iter().map(|piece| piece.render(color.opposite())).collect();
+        let lead = if color == Color::White { balance } else { -balance };
+
+        if lead > 0 {
+            println!("{:?}: {} +{}", color, line, lead);
+        } else {
+            println!("{:?}: {}", color, line);
+        }
+    }
+}
+
+//minutes and seconds, e.g. 4:05
+fn format_clock(time: Duration) -> String {
+    format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+}
+
+//a clock given as minutes and increment seconds, e.g. "5+3"
+fn parse_clock(text: &str) -> Option<time::TimeControl> {
+    let mut parts = text.splitn(2, '+');
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let increment: f64 = parts.next().unwrap_or("0").parse().ok()?;
+
+    if minutes <= 0.0 || increment < 0.0 {
+        return None;
+    }
+    Some(time::TimeControl::new(Duration::from_secs_f64(minutes * 60.0), Duration::from_secs_f64(increment)))
+}
+
+//per-move thinking time of both sides, the game is assumed to start with white
+fn print_time_report(times: &[Duration]) {
+    println!("{:<6}{:>10}{:>10}", "Move", "White", "Black");
+
+    for (i, pair) in times.chunks(2).enumerate() {
+        let black = pair.get(1)
+            .map(|time| format!("{:.1}s", time.as_secs_f64()))
+            .unwrap_or_default();
+
+        println!("{:<6}{:>10}{:>10}", i + 1, format!("{:.1}s", pair[0].as_secs_f64()), black);
+    }
+
+    let total = |color: usize| times.iter().skip(color).step_by(2).sum::<Duration>().as_secs_f64();
+    println!("{:<6}{:>10}{:>10}", "Total", format!("{:.1}s", total(0)), format!("{:.1}s", total(1)));
+}
+
+fn serve() {
     rocket::ignite()
-        .manage(Mutex::new(ChessState::default()))
-        .mount("/", routes![web_move])
+        .manage(Mutex::new(WebGame::new()))
+        .mount("/", routes![
+            web_move,
+            web_promote,
+            web_takeback_request,
+            web_takeback_accept,
+            web_takeback_decline,
+            metrics::metrics
+        ])
+        .manage(Mutex::new(rooms::Rooms::new()))
+        .mount("/", routes![
+            rooms::list,
+            rooms::create,
+            rooms::create_fog,
+            rooms::join,
+            rooms::spectate,
+            rooms::view,
+            rooms::room_move,
+            rooms::room_promote,
+            rooms::configure_engine
+        ])
         .mount("/", StaticFiles::from("./src/web"))
         .launch();
 }
+
+//only the analysis endpoints, queued jobs and direct analyses, for a machine that does nothing
+//but analyse
+fn serve_jobs(workers: usize, hash: usize, network: Option<std::sync::Arc<nnue::Network>>) {
+    let queue = std::sync::Arc::new(jobs::JobQueue::new());
+    jobs::start_workers(&queue, workers, hash, network.clone());
+
+    rocket::ignite()
+        .manage(queue)
+        .manage(jobs::Analysers::new(hash, network))
+        .mount("/", routes![jobs::submit_fens, jobs::submit_pgn, jobs::status, jobs::analyse])
+        .launch();
+}
+
+//prints the opening tree of a PGN file as JSON
+fn export_opening_tree(path: &str, plies: usize) -> Result<(), Error> {
+    let games = pgn::parse_pgn(&std::fs::read_to_string(path)?)?;
+
+    let mut tree = openings::OpeningTree::new(plies);
+    let skipped = games.iter().filter(|game| !tree.add_game(game)).count();
+    if skipped > 0 {
+        eprintln!("Skipped {} games not starting from the standard position.", skipped);
+    }
+
+    println!("{}", tree.to_json());
+    Ok(())
+}
+
+//replays every game of a PGN file, writes the SAN of each move again and prints the moves where
+//it differs from the file's, returning how many there were
+fn validate_pgn(path: &str) -> Result<usize, Error> {
+    let games = pgn::parse_pgn(&std::fs::read_to_string(path)?)?;
+    let mut mismatches = 0;
+
+    for (i, game) in games.iter().enumerate() {
+        for node in game.san_mismatches() {
+            println!("Game {}, line {}: {} written as {}", i + 1, node.line, node.source, node.san);
+            mismatches += 1;
+        }
+    }
+    println!("{} games, {} mismatches.", games.len(), mismatches);
+    Ok(mismatches)
+}
+
+//reviews every game of a PGN file searching depth plies, writing the annotated games to output
+//or printing them, then printing each game's report
+fn review_games(path: &str, depth: u32, output: Option<String>) -> Result<(), Error> {
+    let games = pgn::parse_pgn(&std::fs::read_to_string(path)?)?;
+    let mut engine = search::Engine::new();
+    let (mut pgn, mut reports) = (String::new(), String::new());
+
+    for (i, tree) in games.iter().enumerate() {
+        match review::review(&mut engine, tree, depth) {
+            Some(review) => {
+                pgn += &review.game.to_pgn();
+                pgn.push('\n');
+                reports += &format!("Game {}\n{}\n", i + 1, review.report());
+            }
+            None => eprintln!("Skipped game {}, its variant cannot be reviewed.", i + 1),
+        }
+    }
+
+    match output {
+        Some(output) => std::fs::write(output, pgn)?,
+        None => print!("{}", pgn),
+    }
+    print!("{}", reports);
+    Ok(())
+}
+
+//converts an opening book to the format output's extension names: Polyglot for .bin, the
+//native format for anything else
+fn convert_book(input: &str, output: &str) -> Result<(), Error> {
+    let book = openings::Book::load(std::path::Path::new(input))?;
+
+    if output.ends_with(".bin") {
+        std::fs::write(output, book.to_polyglot())?;
+    } else {
+        std::fs::write(output, book.to_native())?;
+    }
+    Ok(())
+}
+
+//tunes the evaluation on labelled positions, then prints the tuned constants or writes them
+//to output, and draws the tuned piece-square tables to heatmaps when given a directory
+fn tune_evaluation(path: &str, iterations: usize, passes: usize, output: Option<String>, heatmaps: Option<String>) -> Result<(), Error> {
+    let mut tuner = tune::Tuner::load(std::path::Path::new(path))?;
+    eprintln!("{} positions, error {:.6}", tuner.samples(), tuner.error());
+
+    let scale = tuner.fit_scale();
+    eprintln!("Scale {:.3}, error {:.6}", scale, tuner.error());
+
+    tuner.descend(iterations, |iteration, error| eprintln!("Iteration {}: error {:.6}", iteration, error));
+    tuner.local_search(passes, |pass, error| eprintln!("Pass {}: error {:.6}", pass, error));
+
+    match output {
+        Some(output) => std::fs::write(output, tuner.source())?,
+        None => println!("{}", tuner.source()),
+    }
+    if let Some(directory) = heatmaps {
+        heatmap::write_all(std::path::Path::new(&directory), &tuner.parameters())?;
+    }
+    Ok(())
+}
+
+//searches every position of an EPD test suite for movetime, counts the ones solved by their bm
+//or am opcodes and writes each result back as the pm, ce (or dm), acd and acn opcodes, to output
+//or to the suite itself
+fn run_suite(path: &str, movetime: Duration, output: Option<String>) -> Result<(), Error> {
+    //deepest a search goes, which the move time ends long before
+    const MAX_DEPTH: u32 = 64;
+
+    let mut records = std::fs::read_to_string(path)?.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(epd::Epd::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut engine = search::Engine::new();
+    let (mut solved, mut tests) = (0, 0);
+
+    for (i, record) in records.iter_mut().enumerate() {
+        engine.clear();
+        let result = engine.search_timed(&record.state, &mut time::TimeManager::fixed(movetime), MAX_DEPTH);
+        let best = match result.best {
+            Some(best) => best,
+            None => continue,
+        };
+        let san = record.state.san(best);
+
+        let (best_moves, avoid_moves) = (record.best_moves(), record.avoid_moves());
+        if !best_moves.is_empty() || !avoid_moves.is_empty() {
+            let passed = (best_moves.is_empty() || best_moves.contains(&best)) && !avoid_moves.contains(&best);
+            tests += 1;
+            solved += passed as usize;
+
+            let id = record.id().map_or_else(|| (i + 1).to_string(), str::to_string);
+            println!("{}: {} {}", id, san, if passed { "solved" } else { "failed" });
+        }
+
+        record.set("pm", vec![san]);
+        //mates found are written as the moves to them
+        match search::Score::new(result.score) {
+            search::Score::Mate(moves) if moves > 0 => record.set("dm", vec![moves.to_string()]),
+            _ => record.set("ce", vec![result.score.to_string()]),
+        }
+        record.set("acd", vec![result.depth.to_string()]);
+        record.set("acn", vec![result.nodes.to_string()]);
+    }
+    println!("Solved {} of {}.", solved, tests);
+
+    let text: String = records.iter().map(|record| record.to_epd() + "\n").collect();
+    std::fs::write(output.as_deref().unwrap_or(path), text)?;
+    Ok(())
+}
+
+//the value following a command line option, e.g. the path in "--pgn game.pgn"
+fn option_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+//the network named by --nnue, without one the engine uses the handcrafted evaluation
+fn load_network(args: &[String]) -> Option<std::sync::Arc<nnue::Network>> {
+    let path = option_value(args, "--nnue")?;
+
+    match nnue::Network::load(std::path::Path::new(&path)) {
+        Ok(network) => Some(std::sync::Arc::new(network)),
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
+}
+
+//the opening book given with --book, an opening tree's JSON or a Polyglot .bin file, exiting
+//when it cannot be read
+fn load_book(args: &[String]) -> Option<openings::Book> {
+    let path = option_value(args, "--book")?;
+
+    match openings::Book::load(std::path::Path::new(&path)) {
+        Ok(book) => Some(book),
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let seed = option_value(&args, "--seed").and_then(|seed| seed.parse().ok());
+
+    match args.get(1).map(String::as_str) {
+        Some("cli") => {
+            //--teach takes an optional level, defaulting to en prise warnings and threats
+            let teaching = args.iter()
+                .position(|arg| arg == "--teach")
+                .map(|i| args.get(i + 1).and_then(|level| level.parse().ok()).unwrap_or(teach::THREATS))
+                .unwrap_or(0);
+
+            let depth = option_value(&args, "--depth").and_then(|depth| depth.parse().ok());
+            let clock = option_value(&args, "--clock").and_then(|clock| parse_clock(&clock));
+
+            let mut engine = search::Engine::new();
+            engine.set_network(load_network(&args));
+            engine.set_threads(option_value(&args, "--threads").and_then(|threads| threads.parse().ok()).unwrap_or(1));
+            engine.set_contempt(option_value(&args, "--contempt").and_then(|contempt| contempt.parse().ok()).unwrap_or(0));
+
+            //--elo picks the level closest to a rating
+            let skill = option_value(&args, "--skill").and_then(|level| level.parse().ok()).map(skill::Skill::new)
+                .or_else(|| option_value(&args, "--elo").and_then(|elo| elo.parse().ok()).map(skill::Skill::from_elo));
+
+            //book moves are drawn by their weight, how often they were played in an opening tree,
+            //or with --most-played always the heaviest one
+            let choice = if args.iter().any(|arg| arg == "--most-played") {
+                openings::BookChoice::MostPlayed
+            } else {
+                openings::BookChoice::Weighted
+            };
+            let book = load_book(&args).map(|book| (book, choice));
+
+            play_cli(CliGame {
+                auto_queen: args.iter().any(|arg| arg == "--auto-queen"),
+                pgn_path: option_value(&args, "--pgn"),
+                teaching,
+                seed,
+                depth,
+                engine,
+                clock,
+                skill,
+                book,
+                adjudicate: args.iter().any(|arg| arg == "--adjudicate"),
+            })
+        }
+        Some("uci") => uci::run(),
+        //the token is read from LICHESS_TOKEN unless given, to keep it out of the shell history
+        #[cfg(feature = "lichess")]
+        Some("lichess") => {
+            let token = option_value(&args, "--token").or_else(|| std::env::var("LICHESS_TOKEN").ok());
+            match token {
+                Some(token) => if let Err(error) = lichess::run(&token) {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                },
+                None => eprintln!("Usage: chess lichess [--token token], or the token in LICHESS_TOKEN"),
+            }
+        }
+        #[cfg(feature = "websocket")]
+        Some("websocket") => {
+            let port = option_value(&args, "--port").and_then(|port| port.parse().ok()).unwrap_or(9000);
+            if let Err(error) = websocket::run(port) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        Some("duck") => play_duck_cli(option_value(&args, "--pgn"), seed),
+        Some("fog") => play_fog_cli(seed),
+        Some("tree") => {
+            let plies = option_value(&args, "--plies").and_then(|plies| plies.parse().ok()).unwrap_or(12);
+
+            match args.get(2) {
+                Some(path) => if let Err(error) = export_opening_tree(path, plies) {
+                    eprintln!("{}: {}", path, error);
+                    std::process::exit(1);
+                },
+                None => eprintln!("Usage: chess tree <games.pgn> [--plies N]"),
+            }
+        }
+        Some("validate-pgn") => match args.get(2) {
+            Some(path) => match validate_pgn(path) {
+                Ok(0) => {}
+                Ok(_) => std::process::exit(1),
+                Err(error) => {
+                    eprintln!("{}: {}", path, error);
+                    std::process::exit(1);
+                }
+            },
+            None => eprintln!("Usage: chess validate-pgn <games.pgn>"),
+        },
+        Some("review") => {
+            let depth = option_value(&args, "--depth").and_then(|depth| depth.parse().ok()).unwrap_or(8);
+
+            match args.get(2) {
+                Some(path) => if let Err(error) = review_games(path, depth, option_value(&args, "--output")) {
+                    eprintln!("{}: {}", path, error);
+                    std::process::exit(1);
+                },
+                None => eprintln!("Usage: chess review <games.pgn> [--depth N] [--output annotated.pgn]"),
+            }
+        }
+        Some("book") => match (args.get(2), args.get(3)) {
+            (Some(input), Some(output)) => if let Err(error) = convert_book(input, output) {
+                eprintln!("{}: {}", input, error);
+                std::process::exit(1);
+            },
+            _ => eprintln!("Usage: chess book <input> <output.bin|output.json>"),
+        },
+        Some("jobs") => {
+            let workers = option_value(&args, "--workers").and_then(|workers| workers.parse().ok()).unwrap_or(1);
+            let hash = option_value(&args, "--hash").and_then(|hash| hash.parse().ok()).unwrap_or(16);
+
+            serve_jobs(workers, hash, load_network(&args))
+        }
+        Some("tune") => {
+            let iterations = option_value(&args, "--iterations").and_then(|n| n.parse().ok()).unwrap_or(1000);
+            let passes = option_value(&args, "--passes").and_then(|n| n.parse().ok()).unwrap_or(10);
+
+            match args.get(2) {
+                Some(path) => if let Err(error) = tune_evaluation(path, iterations, passes, option_value(&args, "--output"), option_value(&args, "--heatmaps")) {
+                    eprintln!("{}: {}", path, error);
+                    std::process::exit(1);
+                },
+                None => eprintln!("Usage: chess tune <positions.epd> [--iterations N] [--passes N] [--output file] [--heatmaps directory]"),
+            }
+        }
+        //the piece-square tables the engine plays with, as SVG heatmaps
+        Some("heatmap") => match args.get(2) {
+            Some(directory) => if let Err(error) = heatmap::write_all(std::path::Path::new(directory), &eval::tuning_parameters()) {
+                eprintln!("{}: {}", directory, error);
+                std::process::exit(1);
+            },
+            None => eprintln!("Usage: chess heatmap <directory>"),
+        },
+        Some("suite") => {
+            let movetime = option_value(&args, "--movetime").and_then(|ms| ms.parse().ok()).unwrap_or(1000);
+
+            match args.get(2) {
+                Some(path) => if let Err(error) = run_suite(path, Duration::from_millis(movetime), option_value(&args, "--output")) {
+                    eprintln!("{}: {}", path, error);
+                    std::process::exit(1);
+                },
+                None => eprintln!("Usage: chess suite <suite.epd> [--movetime ms] [--output file]"),
+            }
+        }
+        Some("perft") => {
+            let threads = option_value(&args, "--threads").and_then(|threads| threads.parse().ok()).unwrap_or(1);
+            let expected: Option<u64> = option_value(&args, "--expect").and_then(|nodes| nodes.parse().ok());
+
+            let state = match option_value(&args, "--fen").map(|fen| ChessState::parse_fen(&fen)) {
+                Some(Ok(state)) => state,
+                Some(Err(error)) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+                None => ChessState::default(),
+            };
+
+            match args.get(2).and_then(|depth| depth.parse().ok()) {
+                Some(depth) => {
+                    let nodes = perft::report(&state, depth, threads);
+                    if let Some(expected) = expected.filter(|&expected| expected != nodes) {
+                        eprintln!("Expected {} nodes.", expected);
+                        std::process::exit(1);
+                    }
+                }
+                None => eprintln!("Usage: chess perft <depth> [--fen \"FEN\"] [--threads N] [--expect nodes]"),
+            }
+        }
+        _ => serve(),
+    }
+}
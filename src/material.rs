@@ -0,0 +1,97 @@
+use super::{tactics, ChessState, Color, Piece};
+
+//the order pieces appear in a material signature
+const ORDER: [Piece; 6] = [Piece::King, Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endgame {
+    //kings with at most a single minor piece between them
+    Insufficient,
+    KPK,
+    KBNK,
+    KRK,
+    KQK,
+    KQKR,
+    KRKB,
+    KRKN,
+    KRPKR,
+    //kings and pawns only
+    Pawns,
+    //a rook each plus pawns
+    Rooks,
+    //a bishop each, on squares of opposite colors, plus pawns
+    OppositeBishops,
+    Other,
+}
+
+impl ChessState {
+    fn side_key (&self, color: Color) -> String {
+        ORDER.iter()
+            .flat_map(|&piece| {
+                let count = self.pieces_of(color, piece).count() as usize;
+                std::iter::repeat(piece.letter(Color::White)).take(count)
+            })
+            .collect()
+    }
+
+    //material of one side in pawns, the king not counted
+    fn material (&self, color: Color) -> u32 {
+        Piece::kinds().iter()
+            .filter(|&&piece| piece != Piece::King)
+            .map(|&piece| self.pieces_of(color, piece).count() * tactics::value(piece))
+            .sum()
+    }
+
+    /// White's material lead in pawns, negative when black is ahead.
+    pub fn material_balance (&self) -> i32 {
+        self.material(Color::White) as i32 - self.material(Color::Black) as i32
+    }
+
+    //the side with more material, white when equal unless black's pieces sort first
+    fn stronger (&self) -> Color {
+        let (white, black) = (self.material(Color::White), self.material(Color::Black));
+        if black > white || (black == white && self.side_key(Color::Black) < self.side_key(Color::White)) {
+            Color::Black
+        } else {
+            Color::White
+        }
+    }
+
+    /// Canonical material signature, the stronger side first and pieces in KQRBNP order,
+    /// e.g. "KRPKR". Positions that differ only in which color is stronger share a key.
+    pub fn material_key (&self) -> String {
+        let strong = self.stronger();
+        self.side_key(strong) + &self.side_key(strong.opposite())
+    }
+
+    /// The kind of endgame on the board and the stronger side, as named by `material_key`.
+    pub fn endgame (&self) -> (Endgame, Color) {
+        let strong = self.stronger();
+        let key = self.material_key();
+
+        let endgame = match key.as_str() {
+            "KK" | "KBK" | "KNK" => Endgame::Insufficient,
+            "KPK" => Endgame::KPK,
+            "KBNK" => Endgame::KBNK,
+            "KRK" => Endgame::KRK,
+            "KQK" => Endgame::KQK,
+            "KQKR" => Endgame::KQKR,
+            "KRKB" => Endgame::KRKB,
+            "KRKN" => Endgame::KRKN,
+            "KRPKR" => Endgame::KRPKR,
+            _ => {
+                let pieces: String = key.chars().filter(|&c| c != 'K' && c != 'P').collect();
+
+                match pieces.as_str() {
+                    "" => Endgame::Pawns,
+                    "RR" if self.pieces_of(Color::White, Piece::Rook).count() == 1 => Endgame::Rooks,
+                    "BB" if self.pieces_of(Color::White, Piece::Bishop).count() == 1
+                        && !self.same_color_bishops() => Endgame::OppositeBishops,
+                    _ => Endgame::Other,
+                }
+            }
+        };
+
+        (endgame, strong)
+    }
+}
@@ -0,0 +1,79 @@
+use super::WebGame;
+use super::rooms::{self, Rooms};
+use super::search::{Listener, SearchEvent};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use rocket::State;
+
+static MOVES_SERVED: AtomicU64 = AtomicU64::new(0);
+//searches the server's engines finished, with the sums of their depths and speeds
+static SEARCHES: AtomicU64 = AtomicU64::new(0);
+static SEARCH_DEPTHS: AtomicU64 = AtomicU64::new(0);
+static SEARCH_NPS: AtomicU64 = AtomicU64::new(0);
+
+//called for every move played through the server, by players or the engine
+pub fn count_move() {
+    MOVES_SERVED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Follows the searches of an engine on the server: its `listener` keeps the last depth a
+/// search finished, which `count_search` adds to the metrics once the search returned.
+/// Searches that finished no depth, like those of a Skill, are not counted.
+#[derive(Clone, Default)]
+pub struct SearchStats {
+    //the depth and nodes per second of the last finished depth
+    last: Arc<Mutex<Option<(u32, u64)>>>,
+}
+
+impl SearchStats {
+    pub fn listener(&self) -> Listener {
+        let last = Arc::clone(&self.last);
+        Box::new(move |event| if let SearchEvent::Depth { depth, nps, .. } = event {
+            *last.lock().unwrap_or_else(PoisonError::into_inner) = Some((*depth, *nps));
+        })
+    }
+
+    pub fn count_search(&self) {
+        if let Some((depth, nps)) = self.last.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            SEARCHES.fetch_add(1, Ordering::Relaxed);
+            SEARCH_DEPTHS.fetch_add(depth as u64, Ordering::Relaxed);
+            SEARCH_NPS.fetch_add(nps, Ordering::Relaxed);
+        }
+    }
+}
+
+//Prometheus text exposition format
+#[get("/metrics")]
+pub fn metrics(game: State<Mutex<WebGame>>, rooms: State<Mutex<Rooms>>) -> String {
+    let main_active = game.lock().unwrap().state.outcome().is_none() as usize;
+    let active = main_active + rooms::lock(&rooms).active_games();
+
+    let mut text = String::new();
+
+    text.push_str("# HELP chess_active_games Games on the server that have not ended.\n");
+    text.push_str("# TYPE chess_active_games gauge\n");
+    text.push_str(&format!("chess_active_games {}\n", active));
+
+    text.push_str("# HELP chess_moves_served_total Moves played through the server, including engine replies.\n");
+    text.push_str("# TYPE chess_moves_served_total counter\n");
+    text.push_str(&format!("chess_moves_served_total {}\n", MOVES_SERVED.load(Ordering::Relaxed)));
+
+    let searches = SEARCHES.load(Ordering::Relaxed);
+    let average = |sum: &AtomicU64| if searches > 0 { sum.load(Ordering::Relaxed) as f64 / searches as f64 } else { 0.0 };
+
+    text.push_str("# HELP chess_searches_total Searches the server's engines finished, for games and analysis.\n");
+    text.push_str("# TYPE chess_searches_total counter\n");
+    text.push_str(&format!("chess_searches_total {}\n", searches));
+
+    text.push_str("# HELP chess_search_depth_average Average depth the server's searches reached.\n");
+    text.push_str("# TYPE chess_search_depth_average gauge\n");
+    text.push_str(&format!("chess_search_depth_average {:.2}\n", average(&SEARCH_DEPTHS)));
+
+    text.push_str("# HELP chess_search_nodes_per_second Average speed of the server's searches.\n");
+    text.push_str("# TYPE chess_search_nodes_per_second gauge\n");
+    text.push_str(&format!("chess_search_nodes_per_second {:.0}\n", average(&SEARCH_NPS)));
+
+    text
+}
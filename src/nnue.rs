@@ -0,0 +1,281 @@
+use super::error::Error;
+use super::{BitBoard, ChessState, Color, Piece, PIECE_TYPE_COUNT, PLAYER_COUNT};
+
+use std::path::Path;
+use std::sync::Arc;
+
+//the HalfKP networks of Stockfish 12: for each side a half of 256 neurons, fed by every
+//piece other than the kings by where it stands relative to that side's king
+const VERSION: u32 = 0x7AF3_2F16;
+const HALF_DIMENSIONS: usize = 256;
+//10 kinds of piece on 64 squares, plus one unused index, for each of the 64 king squares
+const PIECE_SQUARES: usize = 641;
+const FEATURES: usize = 64 * PIECE_SQUARES;
+const HIDDEN: usize = 32;
+
+//the output is 16 times Stockfish's internal score, in which a pawn is worth 208
+const OUTPUT_SCALE: i32 = 16;
+const PAWN_VALUE: i32 = 208;
+
+//hidden layers divide their sums by 64 before clipping them to 0..=127
+const WEIGHT_SHIFT: i32 = 6;
+
+struct Layer {
+    inputs: usize,
+    biases: Vec<i32>,
+    //by output, then input
+    weights: Vec<i8>,
+}
+
+impl Layer {
+    fn forward(&self, input: &[u8], output: &mut [i32]) {
+        for (i, out) in output.iter_mut().enumerate() {
+            let weights = &self.weights[i * self.inputs..(i + 1) * self.inputs];
+            *out = self.biases[i] + weights.iter().zip(input).map(|(&w, &x)| w as i32 * x as i32).sum::<i32>();
+        }
+    }
+}
+
+fn clipped(values: &[i32], output: &mut [u8]) {
+    for (out, &value) in output.iter_mut().zip(values) {
+        *out = (value >> WEIGHT_SHIFT).clamp(0, 127) as u8;
+    }
+}
+
+//little endian values from the start of a network file
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            return Err(Error::Nnue("the file ends early".to_string()));
+        }
+
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32s(&mut self, n: usize) -> Result<Vec<i32>, Error> {
+        Ok(self.take(4 * n)?.chunks(4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+
+    fn i16s(&mut self, n: usize) -> Result<Vec<i16>, Error> {
+        Ok(self.take(2 * n)?.chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+    }
+
+    fn i8s(&mut self, n: usize) -> Result<Vec<i8>, Error> {
+        Ok(self.take(n)?.iter().map(|&b| b as i8).collect())
+    }
+
+    fn layer(&mut self, inputs: usize, outputs: usize) -> Result<Layer, Error> {
+        let biases = self.i32s(outputs)?;
+        let weights = self.i8s(inputs * outputs)?;
+        Ok(Layer { inputs, biases, weights })
+    }
+}
+
+/// An NNUE network, evaluating positions as an alternative to the handcrafted evaluation.
+/// Positions carry an accumulator of its first layer that each move updates (see
+/// `ChessState::with_network`).
+pub struct Network {
+    biases: Vec<i16>,
+    //by feature, then neuron
+    weights: Vec<i16>,
+    hidden1: Layer,
+    hidden2: Layer,
+    output: Layer,
+}
+
+impl Network {
+    /// Reads a network in the `.nnue` format of Stockfish 12 (HalfKP, 256x2-32-32-1).
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        Self::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { bytes };
+
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(Error::Nnue(format!("unsupported version {:#x}", version)));
+        }
+
+        //the architecture hash and its description, neither of which says more than the sizes
+        reader.u32()?;
+        let description = reader.u32()? as usize;
+        reader.take(description)?;
+
+        reader.u32()?;
+        let biases = reader.i16s(HALF_DIMENSIONS)?;
+        let weights = reader.i16s(HALF_DIMENSIONS * FEATURES)?;
+
+        reader.u32()?;
+        let hidden1 = reader.layer(2 * HALF_DIMENSIONS, HIDDEN)?;
+        let hidden2 = reader.layer(HIDDEN, HIDDEN)?;
+        let output = reader.layer(HIDDEN, 1)?;
+
+        if !reader.bytes.is_empty() {
+            return Err(Error::Nnue("the file is larger than a HalfKP network".to_string()));
+        }
+
+        Ok(Self { biases, weights, hidden1, hidden2, output })
+    }
+
+    fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.weights[feature * HALF_DIMENSIONS..(feature + 1) * HALF_DIMENSIONS]
+    }
+}
+
+//the input index of a piece other than a king, seen by perspective, whose king is on king.
+//black sees the board turned around, so that both sides see their own pieces alike
+fn feature(perspective: Color, king: u32, color: Color, piece: Piece, pos: u32) -> usize {
+    let orient = |pos: u32| match perspective {
+        Color::White => pos,
+        Color::Black => pos ^ 63,
+    } as usize;
+
+    let kind = match piece {
+        Piece::Pawn => 0,
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        Piece::King => unreachable!("Kings are not features of HalfKP."),
+    };
+    let theirs = if color == perspective { 0 } else { 64 };
+
+    orient(pos) + 1 + 128 * kind + theirs + PIECE_SQUARES * orient(king)
+}
+
+const FEATURE_PIECES: [Piece; 5] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// The first layer of the network for a position, from both sides' point of view.
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    //by perspective
+    values: [[i16; HALF_DIMENSIONS]; PLAYER_COUNT],
+}
+
+impl Accumulator {
+    fn refresh(&mut self, perspective: Color, pieces: &[BitBoard; PIECE_TYPE_COUNT], players: &[BitBoard; PLAYER_COUNT]) {
+        let network = Arc::clone(&self.network);
+        let values = &mut self.values[perspective as usize];
+        values.copy_from_slice(&network.biases);
+
+        let king = (pieces[Piece::King as usize] & players[perspective as usize]).solo_pos();
+        for &color in &[Color::White, Color::Black] {
+            for &piece in &FEATURE_PIECES {
+                for pos in (pieces[piece as usize] & players[color as usize]).get_indices() {
+                    let weights = network.feature_weights(feature(perspective, king, color, piece, pos));
+                    for (value, &weight) in values.iter_mut().zip(weights) {
+                        *value += weight;
+                    }
+                }
+            }
+        }
+    }
+
+    //adds the pieces that appeared and removes those that left, unless the king moved, which
+    //changes every feature of its side
+    fn update(&mut self, before: (&[BitBoard; PIECE_TYPE_COUNT], &[BitBoard; PLAYER_COUNT]), state: &ChessState) {
+        let (old_pieces, old_players) = before;
+
+        for &perspective in &[Color::White, Color::Black] {
+            let king = state.king_pos(perspective);
+            let old_king = old_pieces[Piece::King as usize] & old_players[perspective as usize];
+            if old_king.solo_pos() != king {
+                self.refresh(perspective, &state.piece_bb, &state.player_bb);
+                continue;
+            }
+
+            let network = Arc::clone(&self.network);
+            let values = &mut self.values[perspective as usize];
+
+            for &color in &[Color::White, Color::Black] {
+                for &piece in &FEATURE_PIECES {
+                    let old = old_pieces[piece as usize] & old_players[color as usize];
+                    let new = state.pieces_of(color, piece);
+
+                    for pos in (old & new.invert()).get_indices() {
+                        let weights = network.feature_weights(feature(perspective, king, color, piece, pos));
+                        for (value, &weight) in values.iter_mut().zip(weights) {
+                            *value -= weight;
+                        }
+                    }
+
+                    for pos in (new & old.invert()).get_indices() {
+                        let weights = network.feature_weights(feature(perspective, king, color, piece, pos));
+                        for (value, &weight) in values.iter_mut().zip(weights) {
+                            *value += weight;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    //in centipawns from active's point of view
+    fn evaluate(&self, active: Color) -> i32 {
+        let mut input = [0; 2 * HALF_DIMENSIONS];
+        for (half, &perspective) in [active, active.opposite()].iter().enumerate() {
+            let values = &self.values[perspective as usize];
+            for (i, &value) in values.iter().enumerate() {
+                input[half * HALF_DIMENSIONS + i] = value.clamp(0, 127) as u8;
+            }
+        }
+
+        let network = &self.network;
+        let (mut sums, mut hidden) = ([0; HIDDEN], [0; HIDDEN]);
+        network.hidden1.forward(&input, &mut sums);
+        clipped(&sums, &mut hidden);
+        network.hidden2.forward(&hidden, &mut sums);
+        clipped(&sums, &mut hidden);
+
+        let mut output = [0];
+        network.output.forward(&hidden, &mut output);
+        output[0] / OUTPUT_SCALE * 100 / PAWN_VALUE
+    }
+}
+
+impl ChessState {
+    /// A copy of the position that keeps an accumulator for `network` up to date through
+    /// every move applied to it, so that `network_score` is cheap.
+    pub fn with_network (&self, network: &Arc<Network>) -> ChessState {
+        let mut accumulator = Accumulator {
+            network: Arc::clone(network),
+            values: [[0; HALF_DIMENSIONS]; PLAYER_COUNT],
+        };
+
+        for &perspective in &[Color::White, Color::Black] {
+            accumulator.refresh(perspective, &self.piece_bb, &self.player_bb);
+        }
+
+        let mut state = self.clone();
+        state.nnue = Some(Box::new(accumulator));
+        state
+    }
+
+    /// The network's evaluation in centipawns from the side to move's point of view, for
+    /// positions made by `with_network`.
+    pub fn network_score (&self) -> Option<i32> {
+        self.nnue.as_ref().map(|accumulator| accumulator.evaluate(self.active))
+    }
+
+    //called by apply_move with the boards from before the move
+    pub fn update_accumulator (&mut self, pieces: &[BitBoard; PIECE_TYPE_COUNT], players: &[BitBoard; PLAYER_COUNT]) {
+        if let Some(mut accumulator) = self.nnue.take() {
+            accumulator.update((pieces, players), self);
+            self.nnue = Some(accumulator);
+        }
+    }
+}
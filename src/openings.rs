@@ -0,0 +1,402 @@
+use super::error::Error;
+use super::pgn::GameTree;
+use super::polyglot::{self, PolyglotBook};
+use super::{ChessState, Move};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Results {
+    pub games: u32,
+    pub white: u32,
+    pub draws: u32,
+    pub black: u32,
+}
+
+impl Results {
+    fn add(&mut self, result: &str) {
+        self.games += 1;
+        match result {
+            "1-0" => self.white += 1,
+            "0-1" => self.black += 1,
+            "1/2-1/2" => self.draws += 1,
+            _ => {}
+        }
+    }
+
+    //white's score from the decided games, a draw counting half
+    fn score(&self) -> Option<f64> {
+        let decided = self.white + self.draws + self.black;
+        if decided == 0 {
+            None
+        } else {
+            Some((self.white as f64 + self.draws as f64 / 2.0) / decided as f64)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Edge {
+    san: String,
+    #[serde(flatten)]
+    results: Results,
+    score: Option<f64>,
+    //hash of the position the move leads to, a key of `positions`
+    position: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Node {
+    fen: String,
+    #[serde(flatten)]
+    results: Results,
+    score: Option<f64>,
+    moves: Vec<Edge>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Export {
+    root: String,
+    positions: BTreeMap<String, Node>,
+}
+
+struct Position {
+    fen: String,
+    results: Results,
+    //by the hash of the position each move leads to, in the order they were first seen
+    moves: Vec<(u64, String, Results)>,
+}
+
+//keys travel as hex, JSON numbers cannot hold 64 bits exactly
+fn key(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+/// The first moves of a collection of games from the standard position, with how often each
+/// was played and how it scored. Positions are merged by hash, so transpositions share a node.
+pub struct OpeningTree {
+    plies: usize,
+    root: u64,
+    positions: HashMap<u64, Position>,
+}
+
+impl OpeningTree {
+    pub fn new(plies: usize) -> Self {
+        Self { plies, root: ChessState::default().hash(), positions: HashMap::new() }
+    }
+
+    //games set up from another position are left out
+    pub fn add_game(&mut self, game: &GameTree) -> bool {
+        if game.start.hash() != self.root {
+            return false;
+        }
+
+        let mut state = game.start.clone();
+        let mut hash = self.root;
+
+        for node in game.moves.iter().take(self.plies) {
+            let child = node.state.hash();
+
+            let position = self.positions.entry(hash).or_insert_with(|| Position {
+                fen: state.to_fen(),
+                results: Results::default(),
+                moves: Vec::new(),
+            });
+            position.results.add(&game.result);
+
+            match position.moves.iter_mut().find(|(next, _, _)| *next == child) {
+                Some((_, _, results)) => results.add(&game.result),
+                None => {
+                    let mut results = Results::default();
+                    results.add(&game.result);
+                    position.moves.push((child, node.san.clone(), results));
+                }
+            }
+
+            state = node.state.clone();
+            hash = child;
+        }
+
+        //the last position reached is a leaf
+        self.positions.entry(hash)
+            .or_insert_with(|| Position { fen: state.to_fen(), results: Results::default(), moves: Vec::new() })
+            .results.add(&game.result);
+
+        true
+    }
+
+    /// The tree as JSON: the root's key and every position by key, each with its moves most
+    /// played first and the key of the position they lead to.
+    pub fn to_json(&self) -> String {
+        let positions = self.positions.iter()
+            .map(|(&hash, position)| {
+                let mut moves: Vec<Edge> = position.moves.iter()
+                    .map(|(next, san, results)| Edge {
+                        san: san.clone(),
+                        results: *results,
+                        score: results.score(),
+                        position: key(*next),
+                    })
+                    .collect();
+                moves.sort_by(|a, b| b.results.games.cmp(&a.results.games));
+
+                let node = Node {
+                    fen: position.fen.clone(),
+                    results: position.results,
+                    score: position.results.score(),
+                    moves,
+                };
+                (key(hash), node)
+            })
+            .collect();
+
+        serde_json::to_string_pretty(&Export { root: key(self.root), positions })
+            .expect("Opening trees always serialize.")
+    }
+}
+
+/// How a book move is chosen among those played in a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookChoice {
+    MostPlayed,
+    //at random, each move as likely as it was played
+    Weighted,
+}
+
+/// An opening book giving the moves of each position with a weight: an opening tree's JSON
+/// (see `OpeningTree::to_json`), weighted by how often each move was played, a book in this
+/// engine's own format (see `Book::to_native`), which may comment on its moves, or a Polyglot
+/// book (see `PolyglotBook`).
+pub struct Book {
+    source: Source,
+}
+
+enum Source {
+    //by the hash of each position
+    Positions(HashMap<u64, Entry>),
+    Polyglot(PolyglotBook),
+}
+
+#[derive(Clone)]
+struct Entry {
+    state: ChessState,
+    //each move with its weight and comment
+    moves: Vec<(Move, u32, Option<String>)>,
+}
+
+//the native format: positions by the hex of their hash, each with its FEN and its moves in
+//coordinates, with their weights and comments
+#[derive(Serialize, Deserialize)]
+struct NativeMove {
+    #[serde(rename = "move")]
+    text: String,
+    weight: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NativePosition {
+    fen: String,
+    moves: Vec<NativeMove>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NativeBook {
+    positions: BTreeMap<String, NativePosition>,
+}
+
+//the positions a Polyglot book has moves for, found by following its moves from the standard
+//position, as its keys cannot be turned back into positions
+fn walk(book: &PolyglotBook) -> HashMap<u64, Entry> {
+    let mut positions = HashMap::new();
+    let mut pending = vec![ChessState::default()];
+
+    while let Some(state) = pending.pop() {
+        let moves = book.moves(&state);
+        if moves.is_empty() || positions.contains_key(&state.hash()) {
+            continue;
+        }
+
+        for &(action, _) in &moves {
+            let mut child = state.clone();
+            child.apply_move(action);
+            pending.push(child);
+        }
+
+        let moves = moves.into_iter().map(|(action, weight)| (action, weight, None)).collect();
+        positions.insert(state.hash(), Entry { state, moves });
+    }
+
+    positions
+}
+
+impl Book {
+    /// Reads a Polyglot book from a .bin file, an opening tree's JSON or a native book from
+    /// anything else.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        if path.extension().is_some_and(|extension| extension == "bin") {
+            Ok(Self { source: Source::Polyglot(PolyglotBook::load(path)?) })
+        } else {
+            Self::parse(&std::fs::read_to_string(path)?)
+        }
+    }
+
+    /// Reads an opening tree's JSON or a native book.
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let mut positions = HashMap::new();
+
+        match serde_json::from_str::<Export>(json) {
+            Ok(export) => for node in export.positions.values().filter(|node| !node.moves.is_empty()) {
+                let state = ChessState::parse_fen(&node.fen)?;
+                let mut moves = Vec::new();
+                for edge in &node.moves {
+                    moves.push((state.parse_san(&edge.san)?, edge.results.games, None));
+                }
+                positions.insert(state.hash(), Entry { state, moves });
+            },
+            Err(_) => {
+                let native: NativeBook = serde_json::from_str(json).map_err(|error| Error::Book(error.to_string()))?;
+                for position in native.positions.values().filter(|position| !position.moves.is_empty()) {
+                    let state = ChessState::parse_fen(&position.fen)?;
+                    let mut moves = Vec::new();
+                    for book_move in &position.moves {
+                        moves.push((state.find_move(&book_move.text)?, book_move.weight, book_move.comment.clone()));
+                    }
+                    positions.insert(state.hash(), Entry { state, moves });
+                }
+            }
+        }
+
+        Ok(Self { source: Source::Positions(positions) })
+    }
+
+    /// The moves in `state` and their weights, empty out of book.
+    pub fn moves(&self, state: &ChessState) -> Vec<(Move, u32)> {
+        match &self.source {
+            Source::Positions(positions) => positions.get(&state.hash())
+                .map(|entry| entry.moves.iter().map(|&(action, weight, _)| (action, weight)).collect())
+                .unwrap_or_default(),
+            Source::Polyglot(book) => book.moves(state),
+        }
+    }
+
+    /// What the book says about playing `action` in `state`, only native books have comments.
+    pub fn comment(&self, state: &ChessState, action: Move) -> Option<&str> {
+        match &self.source {
+            Source::Positions(positions) => positions.get(&state.hash())?.moves.iter()
+                .find(|&&(book_move, _, _)| book_move == action)
+                .and_then(|(_, _, comment)| comment.as_deref()),
+            Source::Polyglot(_) => None,
+        }
+    }
+
+    //the positions with their moves, a Polyglot book's as far as they can be reached
+    fn positions(&self) -> Cow<'_, HashMap<u64, Entry>> {
+        match &self.source {
+            Source::Positions(positions) => Cow::Borrowed(positions),
+            Source::Polyglot(book) => Cow::Owned(walk(book)),
+        }
+    }
+
+    /// The book in the native format, as JSON.
+    pub fn to_native(&self) -> String {
+        let positions = self.positions().values()
+            .map(|entry| {
+                let moves = entry.moves.iter()
+                    .map(|(action, weight, comment)| NativeMove {
+                        text: entry.state.coordinates(*action),
+                        weight: *weight,
+                        comment: comment.clone(),
+                    })
+                    .collect();
+                (key(entry.state.hash()), NativePosition { fen: entry.state.to_fen(), moves })
+            })
+            .collect();
+
+        serde_json::to_string_pretty(&NativeBook { positions }).expect("Books always serialize.")
+    }
+
+    /// The book as a Polyglot .bin file. Comments have no place in the format and are lost.
+    pub fn to_polyglot(&self) -> Vec<u8> {
+        let positions: Vec<(u64, Vec<(Move, u32)>)> = self.positions().values()
+            .map(|entry| {
+                let moves = entry.moves.iter().map(|&(action, weight, _)| (action, weight)).collect();
+                (polyglot::key(&entry.state), moves)
+            })
+            .collect();
+
+        PolyglotBook::write(&positions)
+    }
+
+    /// The book move to play in `state`, None out of book.
+    pub fn pick(&self, state: &ChessState, choice: BookChoice, rng: &mut impl Rng) -> Option<Move> {
+        let moves = self.moves(state);
+        let total: u32 = moves.iter().map(|&(_, games)| games).sum();
+        if total == 0 {
+            return None;
+        }
+
+        match choice {
+            BookChoice::MostPlayed => moves.iter().max_by_key(|&&(_, games)| games).map(|&(action, _)| action),
+            BookChoice::Weighted => {
+                let mut roll = rng.gen_range(0, total);
+                moves.iter()
+                    .find(|&&(_, games)| {
+                        if roll < games {
+                            return true;
+                        }
+                        roll -= games;
+                        false
+                    })
+                    .map(|&(action, _)| action)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NATIVE: &str = r#"{"positions": {
+        "0": {"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+              "moves": [{"move": "e2e4", "weight": 5, "comment": "Best by test."}, {"move": "d2d4", "weight": 2}]},
+        "1": {"fen": "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+              "moves": [{"move": "c7c5", "weight": 3}]}
+    }}"#;
+
+    fn sorted(mut moves: Vec<(Move, u32)>) -> Vec<(Move, u32)> {
+        moves.sort_by_key(|&(action, _)| (action.origin, action.dest));
+        moves
+    }
+
+    #[test]
+    fn native_books_keep_their_comments() {
+        let book = Book::parse(NATIVE).unwrap();
+        let state = ChessState::default();
+
+        assert_eq!(book.comment(&state, state.find_move("e2e4").unwrap()), Some("Best by test."));
+        assert_eq!(book.comment(&state, state.find_move("d2d4").unwrap()), None);
+    }
+
+    #[test]
+    fn books_convert_to_polyglot_and_back() {
+        let book = Book::parse(NATIVE).unwrap();
+        let polyglot = Book { source: Source::Polyglot(PolyglotBook::parse(&book.to_polyglot()).unwrap()) };
+        let native = Book::parse(&polyglot.to_native()).unwrap();
+
+        let start = ChessState::default();
+        let mut after = start.clone();
+        after.apply_move(start.find_move("e2e4").unwrap());
+
+        for state in &[start, after] {
+            assert_eq!(sorted(polyglot.moves(state)), sorted(book.moves(state)));
+            assert_eq!(sorted(native.moves(state)), sorted(book.moves(state)));
+        }
+    }
+}
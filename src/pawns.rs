@@ -0,0 +1,144 @@
+use super::eval::pawn_term;
+use super::shelter::{file_bb, relative_rank};
+use super::{cache, BitBoard, ChessState, Color, Piece};
+
+//per pawn, in the middlegame and in the endgame
+const DOUBLED: (i32, i32) = (-10, -20);
+const ISOLATED: (i32, i32) = (-10, -15);
+const BACKWARD: (i32, i32) = (-8, -12);
+const CONNECTED: (i32, i32) = (8, 12);
+
+//in the order side_counts counts them
+const TERMS: [(&str, (i32, i32)); 4] = [
+    ("DOUBLED", DOUBLED),
+    ("ISOLATED", ISOLATED),
+    ("BACKWARD", BACKWARD),
+    ("CONNECTED", CONNECTED),
+];
+
+fn adjacent_files(file: u32) -> BitBoard {
+    let mut files = BitBoard::new();
+    if file > 0 {
+        files |= file_bb(file - 1);
+    }
+    if file < 7 {
+        files |= file_bb(file + 1);
+    }
+    files
+}
+
+//the squares of pos's rank and the ranks behind it, seen from color
+fn level_or_behind(color: Color, pos: u32) -> BitBoard {
+    let rank = relative_rank(color, pos);
+    (0..64).filter(|&square| relative_rank(color, square) <= rank)
+        .fold(BitBoard::new(), |bb, square| bb.add_pos(square))
+}
+
+//how many doubled, isolated, backward and connected pawns one side has
+fn side_counts(color: Color, own: BitBoard, enemy: BitBoard) -> [i32; 4] {
+    let mut counts = [0; 4];
+
+    for pos in own.get_indices() {
+        let file = pos % 8;
+        let neighbours = own & adjacent_files(file);
+        let behind = level_or_behind(color, pos);
+
+        //only the rear pawn of a doubled pair counts, so each extra pawn costs once
+        if !(own & file_bb(file) & behind.invert()).is_empty() {
+            counts[0] += 1;
+        }
+
+        if neighbours.is_empty() {
+            counts[1] += 1;
+            continue;
+        }
+
+        //no neighbour can come up to support it and its stop square is held by an enemy pawn
+        let stop = if color == Color::White { pos + 8 } else { pos.wrapping_sub(8) };
+        if stop < 64 && (neighbours & behind).is_empty() && !(cache.pawn_attacks(color, stop) & enemy).is_empty() {
+            counts[2] += 1;
+        }
+
+        let supported = !(cache.pawn_attacks(color.opposite(), pos) & own).is_empty();
+        let phalanx = !(neighbours & behind & level_or_behind(color.opposite(), pos)).is_empty();
+        if supported || phalanx {
+            counts[3] += 1;
+        }
+    }
+
+    counts
+}
+
+/// White's count of each pawn structure term minus black's, in the order of `terms`.
+pub fn term_counts(white: BitBoard, black: BitBoard) -> [i32; 4] {
+    let white_counts = side_counts(Color::White, white, black);
+    let black_counts = side_counts(Color::Black, black, white);
+
+    let mut counts = [0; 4];
+    for (i, count) in counts.iter_mut().enumerate() {
+        *count = white_counts[i] - black_counts[i];
+    }
+    counts
+}
+
+/// The names and middlegame and endgame values of the pawn structure terms, for tuning.
+pub const fn terms() -> [(&'static str, (i32, i32)); 4] {
+    TERMS
+}
+
+//the counts weighed by the terms as they are set, which can change while the engine runs
+fn weigh(counts: &[i32; 4]) -> (i32, i32) {
+    counts.iter().enumerate().fold((0, 0), |(mg, eg), (term, &count)| {
+        let (term_mg, term_eg) = pawn_term(term);
+        (mg + term_mg * count, eg + term_eg * count)
+    })
+}
+
+/// The pawn structure from white's point of view, in the middlegame and in the endgame.
+pub fn pawn_structure(white: BitBoard, black: BitBoard) -> (i32, i32) {
+    weigh(&term_counts(white, black))
+}
+
+//counts rather than a score, so a table survives the terms being set
+#[derive(Clone, Copy)]
+struct PawnEntry {
+    white: BitBoard,
+    black: BitBoard,
+    counts: [i32; 4],
+}
+
+/// Pawn structure scores by the pawns of both sides. Pawns move rarely, so most positions of a
+/// search share their structure with one evaluated before.
+pub struct PawnTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl PawnTable {
+    pub fn new(entries: usize) -> Self {
+        Self { entries: vec![None; entries.max(1)] }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    /// `pawn_structure` of the position, computed only when the table does not hold it.
+    pub fn score(&mut self, state: &ChessState) -> (i32, i32) {
+        let white = state.pieces_of(Color::White, Piece::Pawn);
+        let black = state.pieces_of(Color::Black, Piece::Pawn);
+
+        let mixed = (white.0 ^ black.0.rotate_left(29)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let index = (mixed >> 32) as usize % self.entries.len();
+
+        match self.entries[index] {
+            Some(entry) if entry.white == white && entry.black == black => weigh(&entry.counts),
+            _ => {
+                let counts = term_counts(white, black);
+                self.entries[index] = Some(PawnEntry { white, black, counts });
+                weigh(&counts)
+            }
+        }
+    }
+}
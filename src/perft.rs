@@ -0,0 +1,86 @@
+use super::{ChessState, Move};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// The number of move sequences `depth` plies long from `state`, to check move generation
+/// against known counts.
+pub fn perft(state: &ChessState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = state.legal_moves();
+
+    //the last ply only needs counting
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    moves.iter()
+        .map(|&action| {
+            let mut child = state.clone();
+            child.apply_move(action);
+            perft(&child, depth - 1)
+        })
+        .sum()
+}
+
+/// Perft under each root move, in move generation order, counted on `threads` threads. The
+/// root moves are handed out one at a time as threads finish their last, since their trees
+/// differ a lot in size.
+pub fn divide(state: &ChessState, depth: u32, threads: usize) -> Vec<(Move, u64)> {
+    let moves = state.legal_moves();
+    let next = Arc::new(AtomicUsize::new(0));
+
+    let workers: Vec<_> = (0..threads.max(1).min(moves.len().max(1))).map(|_| {
+        let (state, moves, next) = (state.clone(), Arc::clone(&moves), Arc::clone(&next));
+
+        thread::spawn(move || {
+            let mut counts = Vec::new();
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let action = match moves.get(i) {
+                    Some(&action) => action,
+                    None => return counts,
+                };
+
+                let mut child = state.clone();
+                child.apply_move(action);
+                counts.push((i, perft(&child, depth.saturating_sub(1))));
+            }
+        })
+    }).collect();
+
+    let mut counts: Vec<(usize, u64)> = workers.into_iter()
+        .flat_map(|worker| worker.join().expect("A perft thread panicked."))
+        .collect();
+    counts.sort_by_key(|&(i, _)| i);
+
+    counts.into_iter().map(|(i, nodes)| (moves[i], nodes)).collect()
+}
+
+//prints the count under each root move and the total with its speed, returning the total
+pub fn report(state: &ChessState, depth: u32, threads: usize) -> u64 {
+    let started = Instant::now();
+
+    //depth 0 has no root moves to divide
+    let total = if depth == 0 {
+        1
+    } else {
+        let counts = divide(state, depth, threads);
+        for &(action, nodes) in &counts {
+            println!("{}: {}", state.coordinates(action), nodes);
+        }
+        counts.iter().map(|&(_, nodes)| nodes).sum()
+    };
+
+    let seconds = started.elapsed().as_secs_f64();
+    println!();
+    println!("Nodes: {}", total);
+    println!("Time: {:.3}s, {:.0} nodes per second", seconds, total as f64 / seconds.max(1e-9));
+
+    total
+}
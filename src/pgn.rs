@@ -0,0 +1,741 @@
+use super::chess960::Chess960;
+use super::{ChessState, Color, FenError, GameResult, Move, Piece};
+
+use std::fmt;
+
+//a game as played from some starting position, kept as moves so it can be written as PGN
+pub struct Game {
+    //tags in the order they are written, starting with the Seven Tag Roster
+    tags: Vec<(String, String)>,
+    start: ChessState,
+    moves: Vec<Move>,
+    //where the duck was placed after each move, in duck chess
+    ducks: Vec<Option<u32>>,
+    //annotations of each move
+    notes: Vec<Annotation>,
+    state: ChessState,
+    //hash of the starting position and of the position after each move
+    hashes: Vec<u64>,
+    //set when the game ended without an outcome on the board, e.g. by resignation
+    result: Option<&'static str>,
+}
+
+//comments, numeric annotation glyphs and alternative lines attached to a move
+#[derive(Debug, Clone, Default)]
+pub struct Annotation {
+    pub nags: Vec<u32>,
+    pub comment: Option<String>,
+    //lines played instead of the move, from the position before it
+    pub variations: Vec<Vec<Move>>,
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+//a comment cannot contain the brace that closes it, and readers trim the space inside braces
+fn comment_token(comment: &str) -> String {
+    format!("{{{}}}", comment.trim().replace('}', ")"))
+}
+
+//movetext of a variation, which always opens with a move number
+fn variation_tokens(start: &ChessState, moves: &[Move]) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut state = start.clone();
+
+    for (i, &action) in moves.iter().enumerate() {
+        match state.active {
+            Color::White => tokens.push(format!("{}.", state.fullmove)),
+            Color::Black if i == 0 => tokens.push(format!("{}...", state.fullmove)),
+            Color::Black => {}
+        }
+
+        tokens.push(state.san(action));
+        state.apply_move(action);
+    }
+
+    if let Some(first) = tokens.first_mut() {
+        first.insert(0, '(');
+    }
+    if let Some(last) = tokens.last_mut() {
+        last.push(')');
+    }
+    tokens
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self::from_position(ChessState::default())
+    }
+
+    pub fn from_position(start: ChessState) -> Self {
+        let mut tags: Vec<(String, String)> = [
+            ("Event", "?"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "?"),
+            ("White", "?"), ("Black", "?"), ("Result", "*"),
+        ].iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect();
+
+        if start.chess960 {
+            tags.push(("Variant".to_string(), "Chess960".to_string()));
+
+            //the number of the start position, which its FEN leaves readers to work out
+            if let Some(number) = Chess960::number(&start) {
+                tags.push(("StartPosition".to_string(), number.to_string()));
+            }
+        } else if start.duck.is_some() {
+            tags.push(("Variant".to_string(), "Duck".to_string()));
+        } else if start.pockets.is_some() {
+            tags.push(("Variant".to_string(), "Crazyhouse".to_string()));
+        } else if start.checks.is_some() {
+            tags.push(("Variant".to_string(), "Three-check".to_string()));
+        }
+
+        //games not starting from the variant's standard position carry it along
+        let mut standard = ChessState::default();
+        if start.pockets.is_some() {
+            standard.start_crazyhouse();
+        }
+        if start.checks.is_some() {
+            standard.start_three_check();
+        }
+
+        let fen = start.to_fen();
+        if fen != standard.to_fen() {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+
+        Self {
+            tags,
+            state: start.clone(),
+            hashes: vec![start.hash()],
+            start,
+            moves: Vec::new(),
+            ducks: Vec::new(),
+            notes: Vec::new(),
+            result: None,
+        }
+    }
+
+    //duck chess from the standard position, which starts without the duck
+    pub fn duck_chess() -> Self {
+        let mut game = Self::new();
+        game.set_tag("Variant", "Duck");
+        game
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn state(&self) -> &ChessState {
+        &self.state
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Position hashes from the start of the game, the current position last.
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    /// Position hashes since the last capture or pawn move, the only ones that can repeat.
+    pub fn reversible_hashes(&self) -> &[u64] {
+        let count = (self.state.move_rule as usize + 1).min(self.hashes.len());
+        &self.hashes[self.hashes.len() - count..]
+    }
+
+    pub fn push(&mut self, action: Move) {
+        self.state.apply_move(action);
+        self.moves.push(action);
+        self.ducks.push(None);
+        self.notes.push(Annotation::default());
+        self.hashes.push(self.state.hash());
+    }
+
+    pub fn push_duck(&mut self, action: Move, duck: u32) {
+        self.state.apply_duck_move(action, duck);
+        self.moves.push(action);
+        self.ducks.push(Some(duck));
+        self.notes.push(Annotation::default());
+        self.hashes.push(self.state.hash());
+    }
+
+    /// The pieces each side has taken so far, indexed by the capturing color, in the order
+    /// they were taken.
+    pub fn captured_pieces(&self) -> [Vec<Piece>; 2] {
+        let mut captured = [Vec::new(), Vec::new()];
+        let mut state = self.start.clone();
+
+        for (&action, &duck) in self.moves.iter().zip(&self.ducks) {
+            if let Some(piece) = state.captured(action) {
+                captured[state.active as usize].push(piece);
+            }
+
+            match duck {
+                Some(duck) => state.apply_duck_move(action, duck),
+                None => state.apply_move(action),
+            }
+        }
+
+        captured
+    }
+
+    //keeps the first `plies` moves, for takebacks
+    pub fn truncate(&mut self, plies: usize) {
+        self.moves.truncate(plies);
+        self.ducks.truncate(plies);
+        self.notes.truncate(plies);
+        self.hashes.truncate(plies + 1);
+        self.state = self.start.clone();
+        for (&action, &duck) in self.moves.iter().zip(&self.ducks) {
+            match duck {
+                Some(duck) => self.state.apply_duck_move(action, duck),
+                None => self.state.apply_move(action),
+            }
+        }
+    }
+
+    /// The annotation of the move at `ply`, counting from 0, to attach comments, NAGs and
+    /// variations to, e.g. an engine's "+0.35/18" evaluation.
+    pub fn annotation(&mut self, ply: usize) -> Option<&mut Annotation> {
+        self.notes.get_mut(ply)
+    }
+
+    /// Adds a line played instead of the move at `ply`, if every move of it is legal.
+    pub fn add_variation(&mut self, ply: usize, moves: Vec<Move>) -> bool {
+        if moves.is_empty() || ply >= self.moves.len() || self.ducks[ply].is_some() {
+            return false;
+        }
+
+        let mut state = self.start.clone();
+        for (&action, &duck) in self.moves[..ply].iter().zip(&self.ducks) {
+            match duck {
+                Some(duck) => state.apply_duck_move(action, duck),
+                None => state.apply_move(action),
+            }
+        }
+
+        for &action in &moves {
+            if !state.legal_moves().contains(&action) {
+                return false;
+            }
+            state.apply_move(action);
+        }
+
+        self.notes[ply].variations.push(moves);
+        true
+    }
+
+    pub fn resign(&mut self, color: Color) {
+        self.result = Some(match color {
+            Color::White => "0-1",
+            Color::Black => "1-0",
+        });
+    }
+
+    /// Ends the game with `winner`, or drawn for None, though the board has no result yet.
+    pub fn adjudicate(&mut self, winner: Option<Color>) {
+        self.result = Some(match winner {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        });
+    }
+
+    pub fn result(&self) -> &'static str {
+        if let Some(result) = self.result {
+            return result;
+        }
+
+        if let Some(winner) = self.state.three_check_winner() {
+            return match winner {
+                Color::White => "1-0",
+                Color::Black => "0-1",
+            };
+        }
+
+        if self.state.duck.is_some() {
+            return match self.state.duck_winner() {
+                Some(Color::White) => "1-0",
+                Some(Color::Black) => "0-1",
+                None => "*",
+            };
+        }
+
+        match self.state.outcome() {
+            Some(GameResult::Checkmate { winner: Color::White }) => "1-0",
+            Some(GameResult::Checkmate { winner: Color::Black }) => "0-1",
+            Some(_) => "1/2-1/2",
+            None => "*",
+        }
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        for (name, value) in &self.tags {
+            let value = if name == "Result" { self.result() } else { value };
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut state = self.start.clone();
+
+        //black's moves get their number again after a comment or variation
+        let mut interrupted = true;
+
+        for ((&action, &duck), note) in self.moves.iter().zip(&self.ducks).zip(&self.notes) {
+            match state.active {
+                Color::White => tokens.push(format!("{}.", state.fullmove)),
+                Color::Black if interrupted => tokens.push(format!("{}...", state.fullmove)),
+                Color::Black => {}
+            }
+
+            let before = state.clone();
+
+            match duck {
+                Some(duck) => {
+                    tokens.push(state.duck_san(action, duck));
+                    state.apply_duck_move(action, duck);
+                }
+                None => {
+                    tokens.push(state.san(action));
+                    state.apply_move(action);
+                }
+            }
+
+            tokens.extend(note.nags.iter().map(|nag| format!("${}", nag)));
+            if let Some(comment) = &note.comment {
+                tokens.push(comment_token(comment));
+            }
+            for variation in &note.variations {
+                tokens.extend(variation_tokens(&before, variation));
+            }
+
+            interrupted = note.comment.is_some() || !note.variations.is_empty();
+        }
+        tokens.push(self.result().to_string());
+
+        //movetext lines stay below 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() >= 80 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+//a move of an imported game, with the alternatives to it given as variations
+pub struct Node {
+    pub action: Move,
+    pub san: String,
+    //the move as the file wrote it, and the line it is on
+    pub source: String,
+    pub line: usize,
+    //the position after the move
+    pub state: ChessState,
+    pub nags: Vec<u32>,
+    //where the duck was placed after the move, in duck chess
+    pub duck: Option<u32>,
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    pub variations: Vec<Vec<Node>>,
+}
+
+pub struct GameTree {
+    pub tags: Vec<(String, String)>,
+    pub start: ChessState,
+    pub moves: Vec<Node>,
+    pub result: String,
+}
+
+impl GameTree {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn mainline(&self) -> Vec<Move> {
+        self.moves.iter().map(|node| node.action).collect()
+    }
+
+    /// Moves, variations included, whose SAN written again differs from the file's or does
+    /// not read back as the same move.
+    pub fn san_mismatches(&self) -> Vec<&Node> {
+        let mut mismatches = Vec::new();
+        collect_mismatches(&self.start, &self.moves, &mut mismatches);
+        mismatches
+    }
+}
+
+fn collect_mismatches<'a>(start: &ChessState, line: &'a [Node], mismatches: &mut Vec<&'a Node>) {
+    let mut state = start;
+
+    for node in line {
+        let reread = match node.duck {
+            Some(_) => state.parse_duck_san(&node.san).map(|(action, duck)| (action, Some(duck))),
+            None => state.parse_san(&node.san).map(|action| (action, None)),
+        };
+        if node.san != node.source || reread.ok() != Some((node.action, node.duck)) {
+            mismatches.push(node);
+        }
+
+        for variation in &node.variations {
+            collect_mismatches(state, variation, mismatches);
+        }
+        state = &node.state;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    BadTag { line: usize },
+    UnterminatedComment { line: usize },
+    IllegalMove { line: usize, san: String },
+    UnbalancedVariation { line: usize },
+    Fen(FenError),
+    UnsupportedVariant(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::BadTag { line } => write!(f, "Line {}: malformed tag pair.", line),
+            PgnError::UnterminatedComment { line } => write!(f, "Line {}: comment is never closed.", line),
+            PgnError::IllegalMove { line, san } => write!(f, "Line {}: {} is not a legal move.", line, san),
+            PgnError::UnbalancedVariation { line } => write!(f, "Line {}: unbalanced parentheses.", line),
+            PgnError::Fen(error) => write!(f, "FEN tag: {}", error),
+            PgnError::UnsupportedVariant(variant) => write!(f, "The {} variant is not supported.", variant),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u32),
+    Open,
+    Close,
+    San(String),
+    Result(String),
+}
+
+//tokens with the line they start on
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        let start = line;
+
+        match c {
+            '\n' => {
+                line += 1;
+                line_start = true;
+                continue;
+            }
+
+            //escaped lines are skipped
+            '%' if line_start => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+
+            c if c.is_whitespace() => {}
+
+            '[' => {
+                let mut tag = String::new();
+                let mut quoted = false;
+
+                loop {
+                    match chars.next() {
+                        Some('\\') if quoted => tag.extend(chars.next()),
+                        Some('"') => {
+                            quoted = !quoted;
+                            tag.push('"');
+                        }
+                        Some(']') if !quoted => break,
+                        Some('\n') | None => return Err(PgnError::BadTag { line: start }),
+                        Some(c) => tag.push(c),
+                    }
+                }
+
+                let mut parts = tag.splitn(2, '"');
+                let name = parts.next().unwrap_or("").trim().to_string();
+                let value = parts.next()
+                    .and_then(|value| value.strip_suffix('"'))
+                    .ok_or(PgnError::BadTag { line: start })?;
+
+                if name.is_empty() {
+                    return Err(PgnError::BadTag { line: start });
+                }
+                tokens.push((start, Token::Tag(name, value.to_string())));
+            }
+
+            '{' => {
+                let mut comment = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            comment.push(c);
+                        }
+                        None => return Err(PgnError::UnterminatedComment { line: start }),
+                    }
+                }
+
+                tokens.push((start, Token::Comment(comment.trim().to_string())));
+            }
+
+            ';' => {
+                let mut comment = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    comment.push(c);
+                    chars.next();
+                }
+
+                tokens.push((start, Token::Comment(comment.trim().to_string())));
+            }
+
+            '(' => tokens.push((start, Token::Open)),
+            ')' => tokens.push((start, Token::Close)),
+
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();$".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+
+                if let Some(nag) = word.strip_prefix('$') {
+                    let nag = nag.parse().map_err(|_| PgnError::IllegalMove { line: start, san: word.clone() })?;
+                    tokens.push((start, Token::Nag(nag)));
+                } else {
+                    tokens.extend(word_tokens(&word).into_iter().map(|token| (start, token)));
+                }
+            }
+        }
+
+        line_start = false;
+    }
+
+    Ok(tokens)
+}
+
+//splits a movetext word into a move and its annotation, skipping move numbers
+fn word_tokens(word: &str) -> Vec<Token> {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word) {
+        return vec![Token::Result(word.to_string())];
+    }
+
+    //move numbers may be glued to the move, as in "12.e4" or "12...Nf6"
+    let word = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if word.is_empty() {
+        return Vec::new();
+    }
+
+    let san = word.trim_end_matches(|c| c == '!' || c == '?');
+    let nag = match &word[san.len()..] {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    };
+
+    let mut tokens = vec![Token::San(san.to_string())];
+    tokens.extend(nag.map(Token::Nag));
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    //whether the game being read is duck chess, where every move names the duck's square
+    duck: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(usize, Token)> {
+        self.tokens.get(self.index)
+    }
+
+    //a line of moves from start, ending at a closing parenthesis, a result or the next game
+    fn line(&mut self, start: &ChessState, nested: bool) -> Result<Vec<Node>, PgnError> {
+        let mut line: Vec<Node> = Vec::new();
+        let mut pending_comment = None;
+
+        while let Some((number, token)) = self.peek().cloned() {
+            match token {
+                Token::Tag(..) | Token::Result(_) => break,
+                Token::Close => {
+                    if !nested {
+                        return Err(PgnError::UnbalancedVariation { line: number });
+                    }
+                    break;
+                }
+                _ => {}
+            }
+            self.index += 1;
+
+            match token {
+                Token::San(san) => {
+                    let state = line.last().map_or(start, |node| &node.state);
+                    let illegal = |_| PgnError::IllegalMove { line: number, san: san.clone() };
+
+                    let mut next = state.clone();
+                    let (action, duck, written) = if self.duck {
+                        let (action, duck) = state.parse_duck_san(&san).map_err(illegal)?;
+                        next.apply_duck_move(action, duck);
+                        (action, Some(duck), state.duck_san(action, duck))
+                    } else {
+                        let action = state.parse_san(&san).map_err(illegal)?;
+                        next.apply_move(action);
+                        (action, None, state.san(action))
+                    };
+
+                    line.push(Node {
+                        action,
+                        san: written,
+                        source: san,
+                        line: number,
+                        state: next,
+                        nags: Vec::new(),
+                        duck,
+                        comment_before: pending_comment.take(),
+                        comment: None,
+                        variations: Vec::new(),
+                    });
+                }
+
+                Token::Nag(nag) => {
+                    if let Some(node) = line.last_mut() {
+                        node.nags.push(nag);
+                    }
+                }
+
+                Token::Comment(comment) => match line.last_mut() {
+                    Some(node) if pending_comment.is_none() => match &mut node.comment {
+                        Some(existing) => {
+                            existing.push(' ');
+                            existing.push_str(&comment);
+                        }
+                        None => node.comment = Some(comment),
+                    },
+                    _ => pending_comment = Some(comment),
+                },
+
+                //a variation replaces the last move, so it starts from the position before it
+                Token::Open => {
+                    let before = match line.len() {
+                        0 => return Err(PgnError::UnbalancedVariation { line: number }),
+                        1 => start.clone(),
+                        n => line[n - 2].state.clone(),
+                    };
+
+                    let variation = self.line(&before, true)?;
+                    match self.peek() {
+                        Some((_, Token::Close)) => self.index += 1,
+                        _ => return Err(PgnError::UnbalancedVariation { line: number }),
+                    }
+
+                    line.last_mut().unwrap().variations.push(variation);
+                }
+
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(line)
+    }
+
+    fn game(&mut self) -> Result<Option<GameTree>, PgnError> {
+        let mut tags = Vec::new();
+        while let Some((_, Token::Tag(name, value))) = self.peek().cloned() {
+            tags.push((name, value));
+            self.index += 1;
+        }
+
+        if tags.is_empty() && self.peek().is_none() {
+            return Ok(None);
+        }
+
+        let mut start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => ChessState::parse_fen(fen).map_err(PgnError::Fen)?,
+            None => ChessState::default(),
+        };
+
+        //crazyhouse games are read up to their first drop, which is not a move here
+        self.duck = false;
+        if let Some((_, variant)) = tags.iter().find(|(name, _)| name == "Variant") {
+            match variant.to_lowercase().as_str() {
+                "" | "standard" | "from position" => {}
+                "chess960" | "chess 960" | "fischerandom" => start.chess960 = true,
+                "duck" | "duck chess" => self.duck = true,
+                "crazyhouse" => start.start_crazyhouse(),
+                "three-check" | "threecheck" | "three check" | "3-check" => start.start_three_check(),
+                _ => return Err(PgnError::UnsupportedVariant(variant.clone())),
+            }
+        }
+
+        let moves = self.line(&start, false)?;
+
+        let result = match self.peek() {
+            Some((_, Token::Result(result))) => {
+                let result = result.clone();
+                self.index += 1;
+                result
+            }
+            _ => "*".to_string(),
+        };
+
+        Ok(Some(GameTree { tags, start, moves, result }))
+    }
+}
+
+/// Reads every game in a PGN file, such as a Lichess export.
+pub fn parse_pgn(text: &str) -> Result<Vec<GameTree>, PgnError> {
+    let mut parser = Parser { tokens: tokenize(text)?, index: 0, duck: false };
+    let mut games = Vec::new();
+
+    while let Some(game) = parser.game()? {
+        games.push(game);
+    }
+
+    Ok(games)
+}
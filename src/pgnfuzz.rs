@@ -0,0 +1,136 @@
+use super::chess960::Chess960;
+use super::pgn::{self, Game, GameTree};
+use super::{ChessState, Color, Move};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+//characters that need care somewhere in PGN: quotes and backslashes in tags,
+//braces in comments, parentheses, semicolons and dollar signs in movetext
+const TEXT_CHARS: &[u8] = b"abcxyz +-=/.0123456789{}();\"\\$%";
+
+//the random games written and read back, the same ones every run
+const SEED: u64 = 1;
+const GAMES: usize = 300;
+
+fn random_text(rng: &mut StdRng, max: usize) -> String {
+    let length = rng.gen_range(0, max);
+    let text: String = (0..length)
+        .map(|_| TEXT_CHARS[rng.gen_range(0, TEXT_CHARS.len())] as char)
+        .collect();
+
+    //a comment starting with a letter cannot be empty once trimmed
+    format!("a{}", text)
+}
+
+fn random_line(rng: &mut StdRng, start: &ChessState, plies: usize) -> Vec<Move> {
+    let mut state = start.clone();
+    let mut line = Vec::new();
+
+    for _ in 0..plies {
+        let moves = state.legal_moves();
+        if moves.is_empty() {
+            break;
+        }
+
+        let action = moves[rng.gen_range(0, moves.len())];
+        state.apply_move(action);
+        line.push(action);
+    }
+
+    line
+}
+
+//a random legal game, sometimes from a Chess960 start, with tricky tags and random annotations
+fn random_game(rng: &mut StdRng) -> Game {
+    let start = if rng.gen_range(0, 4) == 0 {
+        Chess960::position(rng.gen_range(0, Chess960::COUNT))
+    } else {
+        ChessState::default()
+    };
+
+    let mut game = Game::from_position(start);
+    game.set_tag("White", &random_text(rng, 12));
+    game.set_tag("Black", &random_text(rng, 12));
+
+    for ply in 0..rng.gen_range(0, 160) {
+        let state = game.state().clone();
+        let moves = state.legal_moves();
+        if moves.is_empty() || state.outcome().is_some() {
+            break;
+        }
+
+        game.push(moves[rng.gen_range(0, moves.len())]);
+
+        if rng.gen_range(0, 8) == 0 {
+            let nag = rng.gen_range(1, 140);
+            game.annotation(ply).unwrap().nags.push(nag);
+        }
+
+        if rng.gen_range(0, 8) == 0 {
+            let comment = random_text(rng, 30);
+            game.annotation(ply).unwrap().comment = Some(comment);
+        }
+
+        if rng.gen_range(0, 10) == 0 {
+            let plies = rng.gen_range(1, 6);
+            let line = random_line(rng, &state, plies);
+            game.add_variation(ply, line);
+        }
+    }
+
+    if game.result() == "*" && rng.gen_range(0, 3) == 0 {
+        game.resign(if rng.gen() { Color::White } else { Color::Black });
+    }
+
+    game
+}
+
+//the game record an imported game would have been written from
+fn rebuild(tree: &GameTree) -> Game {
+    let mut game = Game::from_position(tree.start.clone());
+    for (name, value) in &tree.tags {
+        game.set_tag(name, value);
+    }
+
+    for (ply, node) in tree.moves.iter().enumerate() {
+        match node.duck {
+            Some(duck) => game.push_duck(node.action, duck),
+            None => game.push(node.action),
+        }
+
+        let annotation = game.annotation(ply).unwrap();
+        annotation.nags = node.nags.clone();
+        annotation.comment = node.comment.clone();
+
+        for variation in &node.variations {
+            game.add_variation(ply, variation.iter().map(|node| node.action).collect());
+        }
+    }
+
+    if game.result() != tree.result {
+        match tree.result.as_str() {
+            "1-0" => game.resign(Color::Black),
+            "0-1" => game.resign(Color::White),
+            _ => {}
+        }
+    }
+
+    game
+}
+
+//PGN must come back unchanged after importing it and writing it out again
+fn assert_round_trips(text: &str) {
+    let trees = pgn::parse_pgn(text).unwrap_or_else(|error| panic!("{}\n{}", text, error));
+    assert_eq!(trees.len(), 1, "{}", text);
+    assert_eq!(rebuild(&trees[0]).to_pgn(), text);
+}
+
+#[test]
+fn random_games_round_trip() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+
+    for _ in 0..GAMES {
+        assert_round_trips(&random_game(&mut rng).to_pgn());
+    }
+}
@@ -0,0 +1,44 @@
+//a shared set of positions for benchmarks, tuning and self-checks, balanced across game phases.
+//openings and middlegames come from main lines of common openings, the endgames are composed
+//for this set and the rest are the usual perft test positions, so all of it is free to use.
+//every FEN is written the way to_fen writes it.
+
+pub const OPENINGS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqk2r/1pppbppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 w kq - 4 6",
+    "rnbq1rk1/ppp1bppp/4pn2/3p2B1/2PP4/2N1P3/PP3PPP/R2QKBNR w KQ - 1 6",
+    "rnbqkb1r/1p2pppp/p2p1n2/8/3NP3/2N5/PPP2PPP/R1BQKB1R w KQkq - 0 6",
+    "rnbq1rk1/ppp1ppbp/3p1np1/8/2PPP3/2N2N2/PP3PPP/R1BQKB1R w KQ - 2 6",
+    "rnbqk1nr/pp3ppp/4p3/2ppP3/3P4/P1P5/2P2PPP/R1BQKBNR b KQkq - 0 6",
+    "r1bqkb1r/ppp2ppp/2n5/3np3/8/2N2NP1/PP1PPP1P/R1BQKB1R w KQkq - 0 6",
+    "rn1qkbnr/pp2pppp/2p3b1/8/3P4/6N1/PPP2PPP/R1BQKBNR w KQkq - 3 6",
+    "r2qkb1r/pp1npppp/2p2n2/3p4/6b1/3P1NP1/PPP1PPBP/RNBQ1RK1 w kq - 1 6",
+];
+
+pub const MIDDLEGAMES: &[&str] = &[
+    "r1bq1rk1/pp2nppp/2p5/3n4/2BP4/1Q3N2/PP1N1PPP/R3R1K1 w - - 0 13",
+    "r2q1rk1/pp1n1ppp/2p1p3/3nP2b/Pb1P4/2NB1N2/1P2QPPP/R1B2RK1 w - - 1 13",
+    "r1bq1rk1/5ppp/p1np4/1p1Np1b1/4P3/2P5/PPN2PPP/R2QKB1R w KQ - 3 13",
+    "r2q1rk1/pb1n1ppp/1p2pn2/2p3B1/Q2P4/P3PN2/1P2BPPP/R3K2R w KQ c6 0 13",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+];
+
+pub const ENDGAMES: &[&str] = &[
+    "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1",
+    "8/5pk1/6p1/7p/7P/6P1/5PK1/8 w - - 0 1",
+    "8/8/4k3/8/2R5/8/4K3/6r1 w - - 0 1",
+    "8/8/1p3k2/p1p5/P1P5/1P3K2/8/8 w - - 0 1",
+    "6k1/5pp1/7p/8/8/7P/5PP1/3R2K1 w - - 0 1",
+    "8/8/8/3k4/8/3B4/3NK3/8 w - - 0 1",
+    "8/4kp2/8/3K4/8/5P2/8/8 b - - 0 1",
+    "4r1k1/5ppp/8/8/8/8/5PPP/2R3K1 w - - 0 1",
+    "8/6k1/8/8/8/3Q4/1q6/6K1 w - - 0 1",
+    "2b3k1/5pp1/7p/8/8/6PP/5PK1/2B5 w - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+];
+
+pub fn all() -> impl Iterator<Item = &'static str> {
+    OPENINGS.iter().chain(MIDDLEGAMES).chain(ENDGAMES).copied()
+}
@@ -0,0 +1,201 @@
+use super::eval;
+use super::pgn::{self, GameTree};
+use super::search::{Engine, Score};
+use super::{ChessState, Color};
+
+//moves up to this one are the opening, unless the board is already an endgame
+const OPENING_MOVES: u32 = 12;
+//eval::phase at or below which the position is an endgame, about a rook and a minor each
+const ENDGAME_PHASE: i32 = 6;
+//scores past a decided position all count as that, so one slip in a won game does not swamp
+//a player's average
+const LOSS_CAP: i32 = 1000;
+//centipawns lost for a ?!, a ? and a ??
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+
+//in the order a game goes through them, which indexes a player's tallies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::Opening, Phase::Middlegame, Phase::Endgame];
+
+    /// The phase a move played in `state` counts toward.
+    pub fn of(state: &ChessState) -> Self {
+        if eval::phase(state) <= ENDGAME_PHASE {
+            Phase::Endgame
+        } else if state.fullmove <= OPENING_MOVES {
+            Phase::Opening
+        } else {
+            Phase::Middlegame
+        }
+    }
+}
+
+/// How often one player's moves matched the engine's choice and how many centipawns they
+/// lost against it on average.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub moves: u32,
+    pub matches: u32,
+    pub loss: u64,
+}
+
+impl Tally {
+    fn add(&mut self, other: Tally) {
+        self.moves += other.moves;
+        self.matches += other.matches;
+        self.loss += other.loss;
+    }
+
+    /// The percentage of moves that were the engine's, 0 without moves.
+    pub fn match_rate(&self) -> f64 {
+        100.0 * self.matches as f64 / self.moves.max(1) as f64
+    }
+
+    pub fn average_loss(&self) -> f64 {
+        self.loss as f64 / self.moves.max(1) as f64
+    }
+}
+
+/// A game gone over by the engine: annotated with its evaluation of every move, with the
+/// better line after the inaccurate ones, and tallied by player and phase.
+pub struct Review {
+    pub game: pgn::Game,
+    //by color, then by phase
+    pub tallies: [[Tally; 3]; 2],
+    players: [String; 2],
+}
+
+//a score from the mover's point of view as a comment from white's, e.g. "+0.35/8" or "#-3/8"
+fn evaluation(mover: Color, score: i32, depth: u32) -> String {
+    let sign = if mover == Color::White { 1 } else { -1 };
+    match Score::new(score) {
+        Score::Centipawns(centipawns) => format!("{:+.2}/{}", (sign * centipawns) as f64 / 100.0, depth),
+        Score::Mate(moves) => format!("#{}/{}", sign * moves, depth),
+    }
+}
+
+/// Goes over the main line of `tree`, searching every position `depth` plies and every move
+/// played that was not the engine's a ply less. None for duck chess, crazyhouse and
+/// three-check, which the engine cannot search.
+pub fn review(engine: &mut Engine, tree: &GameTree, depth: u32) -> Option<Review> {
+    let start = &tree.start;
+    if tree.moves.iter().any(|node| node.duck.is_some()) || start.pockets.is_some() || start.checks.is_some() {
+        return None;
+    }
+
+    let mut game = pgn::Game::from_position(tree.start.clone());
+    for (name, value) in &tree.tags {
+        game.set_tag(name, value);
+    }
+
+    let mut tallies = [[Tally::default(); 3]; 2];
+    let mut state = tree.start.clone();
+    let mut hashes = Vec::new();
+    engine.clear();
+
+    for (ply, node) in tree.moves.iter().enumerate() {
+        engine.set_game(hashes.clone());
+        let best = engine.search(&state, depth);
+        let matched = best.best == Some(node.action);
+
+        let played = if matched {
+            best.score
+        } else {
+            let mut after = hashes.clone();
+            after.push(state.hash());
+            engine.set_game(after);
+            let score = -engine.search(&node.state, depth.saturating_sub(1).max(1)).score;
+
+            //a mate is a ply further from the position before the move
+            match Score::new(score) {
+                Score::Mate(_) => score - score.signum(),
+                Score::Centipawns(_) => score,
+            }
+        };
+
+        let loss = (best.score.clamp(-LOSS_CAP, LOSS_CAP) - played.clamp(-LOSS_CAP, LOSS_CAP)).max(0);
+        tallies[state.active as usize][Phase::of(&state) as usize].add(Tally { moves: 1, matches: matched as u32, loss: loss as u64 });
+
+        game.push(node.action);
+        let annotation = game.annotation(ply).unwrap();
+        annotation.comment = Some(evaluation(state.active, played, depth));
+        annotation.nags.extend(match loss {
+            loss if loss >= BLUNDER => Some(4),
+            loss if loss >= MISTAKE => Some(2),
+            loss if loss >= INACCURACY => Some(6),
+            _ => None,
+        });
+        if loss >= INACCURACY {
+            game.add_variation(ply, best.pv);
+        }
+
+        hashes.push(state.hash());
+        state = node.state.clone();
+    }
+
+    match tree.result.as_str() {
+        "1-0" => game.adjudicate(Some(Color::White)),
+        "0-1" => game.adjudicate(Some(Color::Black)),
+        "1/2-1/2" => game.adjudicate(None),
+        _ => {}
+    }
+
+    let player = |tag| tree.tag(tag).unwrap_or("?").to_string();
+    Some(Review { game, tallies, players: [player("White"), player("Black")] })
+}
+
+impl Review {
+    /// Each player's engine match rate and average centipawn loss in every phase they moved
+    /// in, then over the whole game.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        for (color, name) in [Color::White, Color::Black].iter().zip(&self.players) {
+            report += &format!("{:?} ({})\n", color, name);
+
+            let mut total = Tally::default();
+            for (phase, tally) in Phase::ALL.iter().zip(&self.tallies[*color as usize]) {
+                if tally.moves > 0 {
+                    report += &format!("  {:<11} {:>3} moves  {:>5.1}% engine match  {:>6.1} average loss\n",
+                        format!("{:?}", phase), tally.moves, tally.match_rate(), tally.average_loss());
+                }
+                total.add(*tally);
+            }
+            report += &format!("  {:<11} {:>3} moves  {:>5.1}% engine match  {:>6.1} average loss\n",
+                "Game", total.moves, total.match_rate(), total.average_loss());
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_blunder_into_mate_loses_the_most() {
+        let games = pgn::parse_pgn("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0").unwrap();
+        let review = review(&mut Engine::new(), &games[0], 3).unwrap();
+
+        let black = review.tallies[Color::Black as usize][Phase::Opening as usize];
+        assert_eq!(black.moves, 3);
+        assert!(black.loss >= LOSS_CAP as u64);
+
+        let white = review.tallies[Color::White as usize][Phase::Opening as usize];
+        assert_eq!(white.moves, 4);
+
+        let pgn = review.game.to_pgn();
+        assert!(pgn.contains("Nf6 $4"));
+        assert!(pgn.contains("Qxf7#"));
+        assert!(pgn.ends_with("1-0\n"));
+    }
+}
@@ -0,0 +1,336 @@
+use super::search::Engine;
+use super::skill::{Skill, MAX_LEVEL};
+use super::time::TimeManager;
+use super::{metrics, seeded_rng, ChessState, Color, Move, WebGame};
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::{Outcome, State};
+use serde::Deserialize;
+
+//each room's engine gets a small table of its own, as a server may hold many rooms
+const ENGINE_HASH: usize = 4;
+//deepest a search goes, which the move time ends long before
+const MAX_DEPTH: u32 = 64;
+//the engine's time for each move in milliseconds, unless the creator asks for another
+const DEFAULT_MOVETIME: u64 = 1000;
+const MAX_MOVETIME: u64 = 10_000;
+
+//players authenticate with the token they got when creating or joining a room,
+//sent as "Authorization: Bearer <token>"
+pub struct Token(String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for Token {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let header = request.headers().get_one("Authorization");
+
+        match header.and_then(|header| header.strip_prefix("Bearer ")) {
+            Some(token) => Outcome::Success(Token(token.to_string())),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+struct Room {
+    game: WebGame,
+    //player tokens, by color, the room's creator plays white and configures the engine
+    seats: [Option<String>; 2],
+    engine: Option<Color>,
+    //set when the engine crashed, which ends the game as its resignation
+    resigned: Option<Color>,
+    //the engine's random choices, seeded per room
+    rng: StdRng,
+    settings: Settings,
+    //made once the engine first moves, and away on the thinking thread while it does
+    searcher: Option<Engine>,
+}
+
+//how the engine plays, chosen by the creator along with its side. the move time is in
+//milliseconds, a skill below the top level plays weaker, see Skill
+#[derive(Deserialize, Clone, Copy, Default)]
+struct Settings {
+    depth: Option<u32>,
+    movetime: Option<u64>,
+    skill: Option<u32>,
+}
+
+//the engine searches for as long as the room's settings allow. fog of war is not something the
+//search understands, so there it plays random moves like the cli opponent
+fn engine_move(engine: &mut Engine, state: &ChessState, fog: bool, settings: Settings, rng: &mut StdRng) -> Move {
+    if fog {
+        let moves = state.unchecked_moves();
+        return moves[rng.gen_range(0, moves.len())];
+    }
+
+    let skill = settings.skill.map(Skill::new).filter(|skill| skill.level() < MAX_LEVEL);
+    let depth = settings.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+    let movetime = Duration::from_millis(settings.movetime.unwrap_or(DEFAULT_MOVETIME).min(MAX_MOVETIME));
+
+    //the position has a legal move, as it has no outcome
+    match skill {
+        Some(skill) => skill.choose(engine, state, rng),
+        None => engine.search_timed(state, &mut TimeManager::fixed(movetime), depth).best,
+    }.expect("A position without an outcome has a move.")
+}
+
+impl Room {
+    fn finished(&self) -> bool {
+        self.resigned.is_some() || self.game.finished()
+    }
+
+    fn seat(&self, token: &Token) -> Option<Color> {
+        [Color::White, Color::Black].iter()
+            .copied()
+            .find(|&color| self.seats[color as usize].as_deref() == Some(token.0.as_str()))
+    }
+
+    fn engine_to_move(&self) -> bool {
+        self.engine == Some(self.game.state.active) && !self.finished()
+    }
+}
+
+//the engine thinks on its own thread, so a panic only costs this game, and without holding
+//the rooms, so the other rooms are not kept waiting meanwhile
+fn engine_reply(rooms: &Mutex<Rooms>, name: &str) {
+    let (state, fog, settings, searcher, mut rng, game) = {
+        let mut rooms = lock(rooms);
+        let room = match rooms.rooms.get_mut(name) {
+            Some(room) if room.engine_to_move() => room,
+            _ => return,
+        };
+
+        let game: Vec<u64> = room.game.history.iter().map(ChessState::hash).collect();
+        (room.game.state.clone(), room.game.fog, room.settings, room.searcher.take(), room.rng.clone(), game)
+    };
+
+    let thinking = state.clone();
+    let reply = thread::spawn(move || {
+        let mut searcher = searcher.unwrap_or_else(|| Engine::with_hash(ENGINE_HASH, false));
+        let stats = metrics::SearchStats::default();
+        searcher.set_listener(Some(stats.listener()));
+        searcher.set_game(game);
+
+        let action = engine_move(&mut searcher, &thinking, fog, settings, &mut rng);
+        stats.count_search();
+        (action, searcher, rng)
+    }).join();
+
+    let mut rooms = lock(rooms);
+    let room = match rooms.rooms.get_mut(name) {
+        Some(room) => room,
+        None => return,
+    };
+
+    match reply {
+        //the creator may have taken the engine off the game while it thought
+        Ok((_, searcher, _)) if !room.engine_to_move() || room.game.state.hash() != state.hash() => {
+            room.searcher = Some(searcher);
+        }
+        Ok((action, searcher, rng)) => {
+            room.searcher = Some(searcher);
+            room.rng = rng;
+            room.game.history.push(state.clone());
+            room.game.state.apply_move(action);
+            metrics::count_move();
+        }
+        Err(_) => {
+            eprintln!("Engine failed in position {}, resigning for {:?}.", state.to_fen(), state.active);
+            room.resigned = Some(state.active);
+        }
+    }
+}
+
+pub struct Rooms {
+    rooms: HashMap<String, Room>,
+}
+
+impl Rooms {
+    pub fn new() -> Self {
+        Self { rooms: HashMap::new() }
+    }
+
+    pub fn active_games(&self) -> usize {
+        self.rooms.values().filter(|room| !room.finished()).count()
+    }
+}
+
+//a panic elsewhere while holding the lock must not take every room down with it
+pub fn lock(rooms: &Mutex<Rooms>) -> MutexGuard<Rooms> {
+    rooms.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn new_token() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+#[get("/rooms")]
+pub fn list(rooms: State<Mutex<Rooms>>) -> String {
+    let rooms = lock(&rooms);
+
+    let mut names: Vec<&str> = rooms.rooms.keys().map(String::as_str).collect();
+    names.sort();
+    names.join("\n")
+}
+
+fn open_room(rooms: &Mutex<Rooms>, name: String, fog: bool) -> String {
+    let mut rooms = lock(&rooms);
+
+    if rooms.rooms.contains_key(&name) {
+        return "taken".to_string();
+    }
+
+    let mut game = WebGame::new();
+    game.fog = fog;
+
+    let token = new_token();
+    rooms.rooms.insert(name, Room {
+        game,
+        seats: [Some(token.clone()), None],
+        engine: None,
+        resigned: None,
+        rng: seeded_rng(None).1,
+        settings: Settings::default(),
+        searcher: None,
+    });
+
+    token
+}
+
+//responds with the creator's token
+#[post("/rooms/<name>")]
+pub fn create(name: String, rooms: State<Mutex<Rooms>>) -> String {
+    open_room(&rooms, name, false)
+}
+
+//a room playing fog of war, where players only see what their pieces can reach
+#[post("/rooms/<name>/fog")]
+pub fn create_fog(name: String, rooms: State<Mutex<Rooms>>) -> String {
+    open_room(&rooms, name, true)
+}
+
+//takes the black seat, responding with the player's token
+#[post("/rooms/<name>/join")]
+pub fn join(name: String, rooms: State<Mutex<Rooms>>) -> String {
+    let mut rooms = lock(&rooms);
+
+    let room = match rooms.rooms.get_mut(&name) {
+        Some(room) => room,
+        None => return "invalid".to_string(),
+    };
+
+    if room.seats[Color::Black as usize].is_some() || room.engine == Some(Color::Black) {
+        return "full".to_string();
+    }
+
+    let token = new_token();
+    room.seats[Color::Black as usize] = Some(token.clone());
+    token
+}
+
+//spectators need no token, they poll the position as FEN.
+//fog of war games stay hidden until they are over, so players cannot peek
+#[get("/rooms/<name>")]
+pub fn spectate(name: String, rooms: State<Mutex<Rooms>>) -> Option<String> {
+    let rooms = lock(&rooms);
+    rooms.rooms.get(&name).map(|room| match room.resigned {
+        Some(color) => format!("{}\n{:?} resigned.", room.game.state.to_fen(), color),
+        None if room.game.fog && !room.finished() => "hidden".to_string(),
+        None => room.game.state.to_fen(),
+    })
+}
+
+//a player's own view of the board, see ChessState::fog_view, or the FEN outside of fog of war
+#[get("/rooms/<name>/view")]
+pub fn view(name: String, token: Token, rooms: State<Mutex<Rooms>>) -> Option<String> {
+    let rooms = lock(&rooms);
+    let room = rooms.rooms.get(&name)?;
+    let color = room.seat(&token)?;
+
+    if room.game.fog && !room.finished() {
+        Some(room.game.state.fog_view(color))
+    } else {
+        Some(room.game.state.to_fen())
+    }
+}
+
+fn room_apply(rooms: &Mutex<Rooms>, name: &str, token: &Token, text: &str) -> &'static str {
+    {
+        let mut rooms = lock(rooms);
+
+        let room = match rooms.rooms.get_mut(name) {
+            Some(room) => room,
+            None => return "invalid",
+        };
+
+        let active = room.game.state.active;
+        if room.resigned.is_some() || room.engine == Some(active) || room.seat(token) != Some(active) {
+            return "invalid";
+        }
+
+        if !room.game.play(text) {
+            return "invalid";
+        }
+    }
+
+    engine_reply(rooms, name);
+    "valid"
+}
+
+#[post("/rooms/<name>/move/<origin>/<dest>")]
+pub fn room_move(name: String, origin: String, dest: String, token: Token, rooms: State<Mutex<Rooms>>) -> &'static str {
+    room_apply(&rooms, &name, &token, &format!("{}{}", origin, dest))
+}
+
+#[post("/rooms/<name>/move/<origin>/<dest>/<promotion>")]
+pub fn room_promote(name: String, origin: String, dest: String, promotion: String, token: Token, rooms: State<Mutex<Rooms>>) -> &'static str {
+    room_apply(&rooms, &name, &token, &format!("{}{}{}", origin, dest, promotion))
+}
+
+//the creator chooses which free side the engine plays, by FEN letter, or "none". the body may
+//set how it plays as JSON, e.g. {"depth": 8}, {"movetime": 2000} or {"skill": 5}, by default it
+//thinks for a second a move
+#[post("/rooms/<name>/engine/<color>", data = "<body>")]
+pub fn configure_engine(name: String, color: String, body: String, token: Token, rooms: State<Mutex<Rooms>>) -> &'static str {
+    let settings = match body.trim() {
+        "" => Settings::default(),
+        body => match serde_json::from_str(body) {
+            Ok(settings) => settings,
+            Err(_) => return "invalid",
+        },
+    };
+
+    {
+        let mut rooms = lock(&rooms);
+
+        let room = match rooms.rooms.get_mut(&name) {
+            Some(room) => room,
+            None => return "invalid",
+        };
+
+        if room.seat(&token) != Some(Color::White) {
+            return "invalid";
+        }
+
+        room.engine = match color.as_str() {
+            "none" => None,
+            _ => match color.chars().next().and_then(Color::from_letter) {
+                //the creator's own seat is taken, so the engine can only play a side nobody joined
+                Some(color) if room.seats[color as usize].is_none() => Some(color),
+                _ => return "invalid",
+            },
+        };
+        room.settings = settings;
+    }
+
+    engine_reply(&rooms, &name);
+    "configured"
+}
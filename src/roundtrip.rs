@@ -0,0 +1,105 @@
+use super::ChessState;
+use super::chess960::Chess960;
+use super::positions;
+use super::symmetry::random_position;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+//FENs already in the form to_fen writes, so they must come back unchanged
+const FENS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 13 41",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+    "2r1kr2/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1",
+    "rr2k3/8/8/8/8/8/8/R3K1RR w Gb - 0 1",
+];
+
+//the same in Shredder-FEN, where every castling rook is named by its file
+const SHREDDER_FENS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1",
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    "rr2k3/8/8/8/8/8/8/R3K1RR w Gb - 0 1",
+];
+
+//random games played out to check positions of every kind, with a fixed seed so a failure repeats
+const SEED: u64 = 1;
+const RANDOM_POSITIONS: usize = 500;
+
+fn assert_same_position(a: &ChessState, b: &ChessState) {
+    assert_eq!(a.active, b.active, "{}", a.to_fen());
+    assert_eq!(a.piece_bb, b.piece_bb, "{}", a.to_fen());
+    assert_eq!(a.player_bb, b.player_bb, "{}", a.to_fen());
+    assert_eq!(a.castle_ks, b.castle_ks, "{}", a.to_fen());
+    assert_eq!(a.castle_qs, b.castle_qs, "{}", a.to_fen());
+    assert_eq!(a.en_passant, b.en_passant, "{}", a.to_fen());
+    assert_eq!(a.move_rule, b.move_rule, "{}", a.to_fen());
+    assert_eq!(a.fullmove, b.fullmove, "{}", a.to_fen());
+}
+
+fn assert_survives_encoding(state: &ChessState) {
+    let bytes = state.encode().unwrap_or_else(|| panic!("{} cannot be encoded", state.to_fen()));
+    let decoded = ChessState::decode(&bytes).unwrap_or_else(|| panic!("{} does not decode", state.to_fen()));
+    assert_same_position(state, &decoded);
+    assert_eq!(decoded.chess960, state.chess960, "{}", state.to_fen());
+}
+
+fn random_positions() -> impl Iterator<Item = ChessState> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    (0..RANDOM_POSITIONS).map(move |_| {
+        let plies = rng.gen_range(0, 120);
+        random_position(&mut rng, plies)
+    })
+}
+
+#[test]
+fn fens_are_exported_as_written() {
+    for fen in FENS.iter().copied().chain(positions::all()) {
+        assert_eq!(ChessState::from_fen(fen).to_fen(), fen);
+    }
+
+    for &fen in SHREDDER_FENS {
+        assert_eq!(ChessState::from_fen(fen).to_shredder_fen(), fen);
+    }
+}
+
+#[test]
+fn chess960_positions_parse_back_from_both_notations() {
+    for number in 0..Chess960::COUNT {
+        let state = Chess960::position(number);
+
+        for fen in &[state.to_fen(), state.to_shredder_fen()] {
+            assert_same_position(&state, &ChessState::from_fen(fen));
+        }
+    }
+}
+
+#[test]
+fn random_positions_parse_back_from_both_notations() {
+    for state in random_positions() {
+        for fen in &[state.to_fen(), state.to_shredder_fen()] {
+            assert_same_position(&state, &ChessState::from_fen(fen));
+        }
+    }
+}
+
+#[test]
+fn positions_survive_binary_encoding() {
+    let fixed = FENS.iter().copied().chain(positions::all()).map(ChessState::from_fen);
+    let chess960 = (0..Chess960::COUNT).map(Chess960::position);
+
+    for state in fixed.chain(chess960).chain(random_positions()) {
+        assert_survives_encoding(&state);
+    }
+}
+
+#[test]
+fn incremental_hash_matches_the_recomputed_one() {
+    //the hash is updated move by move on the way to each random position
+    for state in random_positions() {
+        assert_eq!(state.hash(), state.compute_hash(), "{}", state.to_fen());
+    }
+}
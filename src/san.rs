@@ -0,0 +1,178 @@
+use super::{parse_pos, pos_to_algebra, ChessState, Color, Error, Move, Piece};
+
+fn file_char(pos: u32) -> char {
+    (b'a' + (pos % 8) as u8) as char
+}
+
+fn rank_char(pos: u32) -> char {
+    (b'1' + (pos / 8) as u8) as char
+}
+
+impl ChessState {
+    /// Standard algebraic notation of a legal move in this position, as used in PGN,
+    /// e.g. "Nbd2", "exd6", "O-O" or "Qxf7#".
+    pub fn san (&self, action: Move) -> String {
+        let mut san = if self.is_castle(action) {
+            if action.dest > action.origin { "O-O" } else { "O-O-O" }.to_string()
+        } else {
+            self.san_body(action, &self.legal_moves())
+        };
+
+        let mut after = self.clone();
+        after.apply_move(action);
+
+        if after.in_check() {
+            san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+
+        san
+    }
+
+    /// SAN of a duck chess move followed by the duck's new square, e.g. "Nf3@f6".
+    /// Duck chess has no checks, so there is no check suffix.
+    pub fn duck_san (&self, action: Move, duck: u32) -> String {
+        let san = if self.is_castle(action) {
+            if action.dest > action.origin { "O-O" } else { "O-O-O" }.to_string()
+        } else {
+            self.san_body(action, &self.unchecked_moves())
+        };
+
+        format!("{}@{}", san, pos_to_algebra(duck))
+    }
+
+    //moves are the ones the move has to be told apart from
+    fn san_body (&self, action: Move, moves: &[Move]) -> String {
+        let (file, rank) = (file_char(action.origin), rank_char(action.origin));
+        let en_passant = action.piece == Piece::Pawn && action.origin % 8 != action.dest % 8;
+        let capture = en_passant || self.color_at(action.dest) == Some(self.active.opposite());
+
+        let mut san = String::new();
+
+        if action.piece == Piece::Pawn {
+            if capture {
+                san.push(file);
+            }
+        } else {
+            san.push(action.piece.letter(Color::White));
+
+            //other pieces of the same kind that could also reach dest
+            let rivals: Vec<u32> = moves.iter()
+                .filter(|other| other.piece == action.piece && other.dest == action.dest && other.origin != action.origin)
+                .map(|other| other.origin)
+                .collect();
+
+            if !rivals.is_empty() {
+                if rivals.iter().all(|&other| other % 8 != action.origin % 8) {
+                    san.push(file);
+                } else if rivals.iter().all(|&other| other / 8 != action.origin / 8) {
+                    san.push(rank);
+                } else {
+                    san.push(file);
+                    san.push(rank);
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+
+        san.push_str(&pos_to_algebra(action.dest));
+
+        if let Some(piece) = action.promotion {
+            san.push('=');
+            san.push(piece.letter(Color::White));
+        }
+
+        san
+    }
+
+    /// Finds the legal move written in SAN. Check and annotation suffixes, castling with
+    /// zeros, a missing "=" before the promotion piece and extra disambiguation are accepted.
+    pub fn parse_san (&self, text: &str) -> Result<Move, Error> {
+        self.san_move(text, &self.legal_moves()).ok_or_else(|| Error::San(text.to_string()))
+    }
+
+    /// Reads a duck chess move as `duck_san` writes it, e.g. "Nf3@f6", into the move and the
+    /// duck's new square.
+    pub fn parse_duck_san (&self, text: &str) -> Result<(Move, u32), Error> {
+        let error = || Error::San(text.to_string());
+        let (san, square) = text.split_once('@').ok_or_else(error)?;
+
+        let action = self.san_move(san, &self.unchecked_moves()).ok_or_else(error)?;
+        let duck = parse_pos(square).ok_or_else(error)?;
+
+        if self.duck_squares(action).empty_at(duck) {
+            Err(error())
+        } else {
+            Ok((action, duck))
+        }
+    }
+
+    //the one move of `moves` that text names
+    fn san_move (&self, text: &str, moves: &[Move]) -> Option<Move> {
+        let text = text.trim_end_matches(|c| c == '+' || c == '#' || c == '!' || c == '?');
+
+        let castle = match text {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+
+        if let Some(king_side) = castle {
+            return moves.iter()
+                .copied()
+                .find(|&action| self.is_castle(action) && (action.dest > action.origin) == king_side);
+        }
+
+        let mut chars: Vec<char> = text.chars().filter(|&c| c != 'x' && c != '-' && c != '=').collect();
+
+        let piece = match chars.first() {
+            Some(&c) if c.is_ascii_uppercase() => {
+                chars.remove(0);
+                Piece::from_letter(c.to_ascii_lowercase())?
+            }
+            _ => Piece::Pawn,
+        };
+
+        let promotion = match chars.last() {
+            Some(&c) if c.is_ascii_alphabetic() && !('a'..='h').contains(&c) => {
+                chars.pop();
+                Some(Piece::from_letter(c.to_ascii_lowercase())?)
+            }
+            _ => None,
+        };
+
+        if chars.len() < 2 {
+            return None;
+        }
+
+        let dest = parse_pos(&chars.split_off(chars.len() - 2).into_iter().collect::<String>())?;
+
+        //whatever is left narrows down the origin
+        let mut file = None;
+        let mut rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' => file = Some(c as u32 - 'a' as u32),
+                '1'..='8' => rank = Some(c as u32 - '1' as u32),
+                _ => return None,
+            }
+        }
+
+        let mut candidates = moves.iter().copied().filter(|&action| {
+            action.piece == piece
+                && action.dest == dest
+                && !self.is_castle(action)
+                && action.promotion == promotion
+                && file.is_none_or(|file| action.origin % 8 == file)
+                && rank.is_none_or(|rank| action.origin / 8 == rank)
+        });
+
+        let action = candidates.next()?;
+        match candidates.next() {
+            Some(_) => None,
+            None => Some(action),
+        }
+    }
+}
@@ -0,0 +1,1044 @@
+use super::{cache, ChessState, Color, Move, Piece, Promotions};
+use super::eval::{self, piece_value};
+use super::nnue::Network;
+use super::pawns::PawnTable;
+use super::time::TimeManager;
+use super::tt::{Bound, Entry, TranspositionTable};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+//scores are in centipawns from the side to move's point of view, mates count down from MATE
+pub const MATE: i32 = 100_000;
+const INFINITY: i32 = MATE + 1;
+//scores beyond this are mates, which the table stores relative to the position
+const MATE_BOUND: i32 = MATE - 1000;
+
+//mate scores count plies from the root while searching, but from the position in the table
+fn to_table(score: i32, ply: i32) -> i32 {
+    if score > MATE_BOUND {
+        score + ply
+    } else if score < -MATE_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: i32) -> i32 {
+    if score > MATE_BOUND {
+        score - ply
+    } else if score < -MATE_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
+
+/// A search score as it is reported: centipawns, or moves to a forced mate, negative when
+/// the side to move is the one mated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32),
+}
+
+impl Score {
+    /// The reported form of a score the search returned.
+    pub fn new(score: i32) -> Self {
+        if score > MATE_BOUND {
+            //plies to the mate, rounded up to our moves
+            Score::Mate((MATE - score + 1) / 2)
+        } else if score < -MATE_BOUND {
+            Score::Mate(-(MATE + score) / 2)
+        } else {
+            Score::Centipawns(score)
+        }
+    }
+}
+
+//as in the UCI protocol, e.g. "cp 34" or "mate -2"
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Centipawns(centipawns) => write!(f, "cp {}", centipawns),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+//pawn structures the engine remembers, a few hundred kilobytes
+const PAWN_ENTRIES: usize = 16384;
+
+//how far ahead at the root, in centipawns, the engine has to stand to count as better for the
+//shuffle penalty
+const SHUFFLE_MARGIN: i32 = 50;
+
+//nodes between looks at the clock and the stop flag, and between helpers counting their nodes
+const CLOCK_INTERVAL: u64 = 2048;
+
+//quiet moves are scored by origin and destination, halved once any score passes the limit
+type History = [[i32; 64]; 64];
+const HISTORY_LIMIT: i32 = 1 << 20;
+
+//the table's move goes first, it is the most likely to cut off, then captures with the most
+//valuable victim and cheapest attacker first, then the killers, then the quiet moves by history
+fn order_moves(state: &ChessState, moves: &mut Vec<Move>, first: Option<Move>, killers: &[Option<Move>; 2], history: &History) {
+    moves.sort_by_key(|&action| {
+        if Some(action) == first {
+            return i32::MIN;
+        }
+
+        match state.captured(action) {
+            Some(victim) => piece_value(action.piece) - piece_value(victim) * 10,
+            None => match killers.iter().position(|&killer| killer == Some(action)) {
+                Some(index) => 1 + index as i32,
+                None => killers.len() as i32 + 1 + HISTORY_LIMIT - history[action.origin as usize][action.dest as usize],
+            },
+        }
+    });
+}
+
+//plies without a pawn move or capture after which locked pawns count as a fortress
+const FORTRESS_PLIES: u32 = 20;
+
+/// Whether the position looks like a fortress: every pawn stands blocked by an enemy pawn with
+/// nothing to capture, and neither side has moved a pawn or captured for a while. The side
+/// ahead is then likely unable to make progress, e.g. for adjudicating a game as drawn.
+pub fn likely_fortress(state: &ChessState) -> bool {
+    let pawns = state.piece_bb[Piece::Pawn as usize];
+    if state.move_rule < FORTRESS_PLIES || pawns.is_empty() {
+        return false;
+    }
+
+    [Color::White, Color::Black].iter().all(|&color| {
+        let enemy = state.player_bb[color.opposite() as usize];
+        state.pieces_of(color, Piece::Pawn).get_indices().all(|pos| {
+            let ahead = if color == Color::White { pos + 8 } else { pos.wrapping_sub(8) };
+            ahead < 64 && !(pawns & enemy).empty_at(ahead) && (cache.pawn_attacks(color, pos) & enemy).is_empty()
+        })
+    })
+}
+
+//the static evaluation, by the network when the position carries one, except that without
+//pawn moves or captures the score fades toward a draw as the fifty move rule nears, and
+//quickly once the pawns are locked
+fn evaluate(state: &ChessState, pawns: &mut PawnTable) -> i32 {
+    let score = state.network_score().unwrap_or_else(|| eval::evaluate_cached(state, pawns));
+    let score = score * (200 - state.move_rule as i32) / 200;
+
+    if likely_fortress(state) {
+        score / 4
+    } else {
+        score
+    }
+}
+
+/// Late move reductions: quiet moves ordered late are searched shallower, by
+/// `1 + ln(depth) * ln(move number) / divisor` plies, and again at full depth if they beat alpha.
+#[derive(Debug, Clone, Copy)]
+pub struct Reductions {
+    //nodes closer to the horizon than this are searched in full
+    pub min_depth: u32,
+    //how many moves at a node are searched in full before reducing
+    pub full_moves: usize,
+    pub divisor: f64,
+}
+
+impl Default for Reductions {
+    fn default() -> Self {
+        Self { min_depth: 3, full_moves: 3, divisor: 2.0 }
+    }
+}
+
+impl Reductions {
+    /// No reductions at all, searching every move to the full depth.
+    pub fn none() -> Self {
+        Self { min_depth: u32::MAX, ..Self::default() }
+    }
+
+    //plies to take off the index'th move of a node at depth, leaving at least one to search
+    fn reduction(&self, depth: u32, index: usize) -> u32 {
+        if depth < self.min_depth || index < self.full_moves {
+            return 0;
+        }
+
+        let plies = 1.0 + (depth as f64).ln() * ((index + 1) as f64).ln() / self.divisor;
+        (plies as u32).min(depth.saturating_sub(2))
+    }
+}
+
+/// Futility pruning at nodes up to `max_depth` plies from the horizon and not in check. A node
+/// whose static score beats beta by `reverse_margin` per ply returns at once, and quiet moves
+/// are skipped when the static score falls short of alpha by `margin` per ply.
+#[derive(Debug, Clone, Copy)]
+pub struct Futility {
+    pub max_depth: u32,
+    pub margin: i32,
+    pub reverse_margin: i32,
+}
+
+impl Default for Futility {
+    fn default() -> Self {
+        Self { max_depth: 3, margin: 150, reverse_margin: 120 }
+    }
+}
+
+impl Futility {
+    /// No futility pruning at all.
+    pub fn none() -> Self {
+        Self { max_depth: 0, ..Self::default() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    //None when the side to move has no legal move
+    pub best: Option<Move>,
+    //centipawns, or a mate score counting plies down from MATE (see Score)
+    pub score: i32,
+    //of the deepest search that finished
+    pub depth: u32,
+    pub nodes: u64,
+    //the expected line starting with best, cut short where the table ended the search early
+    pub pv: Vec<Move>,
+}
+
+impl SearchResult {
+    /// The result as a progress line, e.g. "depth 4 score cp 34 nodes 1234 pv e2e4 e7e5",
+    /// or "score mate 2" once the search found a forced mate.
+    pub fn info(&self, state: &ChessState) -> String {
+        let mut position = state.clone();
+        let mut line = Vec::new();
+        for &action in &self.pv {
+            line.push(position.coordinates(action));
+            position.apply_move(action);
+        }
+
+        format!("depth {} score {} nodes {} pv {}", self.depth, Score::new(self.score), self.nodes, line.join(" "))
+    }
+}
+
+/// Progress of a search as it goes, for UIs and protocols to report. The search itself
+/// never prints.
+#[derive(Debug, Clone)]
+pub enum SearchEvent {
+    /// A depth finished. Nodes are those of all threads so far, with the helpers' counted in
+    /// steps of a few thousand.
+    Depth { depth: u32, score: Score, pv: Vec<Move>, nodes: u64, nps: u64, hashfull: u32 },
+    /// The root found a new best move before the depth finished.
+    BestMove { depth: u32, best: Move, score: Score },
+}
+
+/// Receives the events of every search, e.g. sending them down a channel to another thread.
+pub type Listener = Box<dyn FnMut(&SearchEvent) + Send>;
+
+/// The search, with its transposition table and what it learned about move ordering. With
+/// more than one thread (see `set_threads`) it searches lazy SMP: helper threads search the same
+/// position at once, each with its own killers and history, and only share the table.
+pub struct Engine {
+    tt: Arc<TranspositionTable>,
+    nodes: u64,
+    //nodes searched under each root move of the last search, in the order they were searched
+    root_nodes: Vec<(Move, u64)>,
+    //by ply, the last two quiet moves that caused a beta cutoff there
+    killers: Vec<[Option<Move>; 2]>,
+    //triangular table of principal variations, by ply the best line found from that node
+    pv: Vec<Vec<Move>>,
+    //how far from the root extensions may take the search, twice the depth searched
+    max_ply: i32,
+    history: Box<History>,
+    pawns: PawnTable,
+    promotions: Promotions,
+    reductions: Reductions,
+    futility: Futility,
+    network: Option<Arc<Network>>,
+    //centipawns a draw is worth less than nothing to the side to move at the root
+    contempt: i32,
+    //centipawns a repetition costs the root side on top of contempt when it stands better, and
+    //whether it does in the search running now
+    shuffle: i32,
+    ahead: bool,
+    //the root moves searched, all of them for None
+    root_moves: Option<Vec<Move>>,
+    //hashes of the positions played before the root, oldest first
+    game: Vec<u64>,
+    //the game's hashes followed by those of the root and the nodes above the current one
+    line: Vec<u64>,
+    //when a timed search has to stop, and whether it did, which leaves its scores unusable
+    deadline: Option<Instant>,
+    stopped: bool,
+    //nodes a single depth or line may search before it stops, to weaken the engine
+    node_limit: Option<u64>,
+    //raised when the helpers, or any search sharing it, should stop
+    stop: Arc<AtomicBool>,
+    threads: usize,
+    //engines of the helper threads, kept between searches for their history
+    helpers: Vec<Engine>,
+    //nodes the helpers searched so far, which they add to as they go
+    helper_nodes: Arc<AtomicU64>,
+    helper: bool,
+    listener: Option<Listener>,
+    started: Instant,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_hash(16, false)
+    }
+
+    /// An engine with a transposition table of `megabytes`, optionally on huge pages
+    /// (see TranspositionTable).
+    pub fn with_hash(megabytes: usize, large_pages: bool) -> Self {
+        Self {
+            tt: Arc::new(TranspositionTable::new(megabytes, large_pages)),
+            nodes: 0,
+            root_nodes: Vec::new(),
+            killers: Vec::new(),
+            pv: Vec::new(),
+            max_ply: 0,
+            history: Box::new([[0; 64]; 64]),
+            pawns: PawnTable::new(PAWN_ENTRIES),
+            promotions: Promotions::All,
+            reductions: Reductions::default(),
+            futility: Futility::default(),
+            network: None,
+            contempt: 0,
+            shuffle: 0,
+            ahead: false,
+            root_moves: None,
+            game: Vec::new(),
+            line: Vec::new(),
+            deadline: None,
+            stopped: false,
+            node_limit: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: 1,
+            helpers: Vec::new(),
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            helper: false,
+            listener: None,
+            started: Instant::now(),
+        }
+    }
+
+    //a helper thread's engine, sharing the table and the stop flag but nothing it learns
+    fn helper(&self) -> Self {
+        Self {
+            tt: Arc::clone(&self.tt),
+            nodes: 0,
+            root_nodes: Vec::new(),
+            killers: Vec::new(),
+            pv: Vec::new(),
+            max_ply: 0,
+            history: Box::new([[0; 64]; 64]),
+            pawns: PawnTable::new(PAWN_ENTRIES),
+            promotions: self.promotions,
+            reductions: self.reductions,
+            futility: self.futility,
+            network: self.network.clone(),
+            contempt: self.contempt,
+            shuffle: self.shuffle,
+            ahead: false,
+            root_moves: self.root_moves.clone(),
+            game: self.game.clone(),
+            line: Vec::new(),
+            deadline: None,
+            stopped: false,
+            node_limit: None,
+            stop: Arc::clone(&self.stop),
+            threads: 1,
+            helpers: Vec::new(),
+            helper_nodes: Arc::clone(&self.helper_nodes),
+            helper: true,
+            listener: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// Forgets everything learned in earlier searches, e.g. for a new game.
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.history = Box::new([[0; 64]; 64]);
+        self.pawns.clear();
+        self.helpers.clear();
+    }
+
+    /// How many threads search at once, 1 for no helpers.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+        self.helpers.truncate(self.threads - 1);
+    }
+
+    /// Which promotions the search considers, queening only makes it faster but blind to the
+    /// rare underpromotion that matters.
+    pub fn set_promotions(&mut self, promotions: Promotions) {
+        self.promotions = promotions;
+    }
+
+    pub fn set_reductions(&mut self, reductions: Reductions) {
+        self.reductions = reductions;
+    }
+
+    pub fn set_futility(&mut self, futility: Futility) {
+        self.futility = futility;
+    }
+
+    /// Evaluates with `network` instead of the handcrafted evaluation, or with the handcrafted
+    /// one again for None. The table is cleared, as its scores came from the other evaluation.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+        self.tt.clear();
+    }
+
+    /// How much worse than an equal position the engine thinks a draw is, in centipawns. A
+    /// positive contempt plays on against a weaker opponent, a negative one settles for a draw
+    /// against a stronger one. Repetitions, stalemates, the fifty move rule and insufficient
+    /// material all score this way.
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
+        self.tt.clear();
+    }
+
+    /// How much worse than contempt alone the engine thinks repeating a position is when it
+    /// stands better at the root, in centipawns, so it keeps trying to make progress rather
+    /// than shuffle its pieces back and forth. 0 scores repetitions like any other draw.
+    pub fn set_shuffle(&mut self, penalty: i32) {
+        self.shuffle = penalty;
+        self.tt.clear();
+    }
+
+    /// Searches only `moves` at the root from now on, or every legal move again for None, e.g.
+    /// for UCI's "go searchmoves" or to see how a move the user is considering scores. Moves
+    /// that are not legal in the searched position are ignored.
+    pub fn set_root_moves(&mut self, moves: Option<Vec<Move>>) {
+        self.root_moves = moves;
+    }
+
+    /// The hashes of the positions the game went through before the searched one, oldest
+    /// first, so that going back to any of them scores as a draw. Only those since the last
+    /// capture or pawn move can repeat, older ones may be left out.
+    pub fn set_game(&mut self, hashes: Vec<u64>) {
+        self.game = hashes;
+    }
+
+    /// Stops each depth, or each line of `search_multi`, after `limit` nodes, keeping
+    /// the best move found until then. Meant for playing weaker (see `Skill`), as it leaves
+    /// the scores of the search unreliable.
+    pub fn set_node_limit(&mut self, limit: Option<u64>) {
+        self.node_limit = limit;
+    }
+
+    /// A flag that stops the running search when raised from another thread, which then
+    /// returns what it found so far. A flag raised between searches stops the next one at
+    /// once, every search lowers it when it returns.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Calls `listener` with the progress of every search from now on, or stops for None.
+    pub fn set_listener(&mut self, listener: Option<Listener>) {
+        self.listener = listener;
+    }
+
+    /// How many nodes the last search spent under each root move, for progress reports
+    /// and showing where the effort went.
+    pub fn root_nodes(&self) -> &[(Move, u64)] {
+        &self.root_nodes
+    }
+
+    /// Searches `depth` plies with fail-soft alpha-beta, returning the best move and its score.
+    pub fn search(&mut self, state: &ChessState, depth: u32) -> SearchResult {
+        let helpers = self.start_helpers(state, depth);
+
+        self.prepare(depth);
+        let mut result = self.search_root(state, depth, &[]);
+        self.report(&result, result.nodes);
+
+        result.nodes += self.stop_helpers(helpers);
+        result
+    }
+
+    /// Iterative deepening from depth 1 up to `max_depth`, for as long as `time` allows. The
+    /// result is that of the deepest search that finished, with the nodes of all of them.
+    pub fn search_timed(&mut self, state: &ChessState, time: &mut TimeManager, max_depth: u32) -> SearchResult {
+        let helpers = self.start_helpers(state, max_depth);
+        self.deadline = Some(time.deadline());
+
+        let mut result: Option<SearchResult> = None;
+        let mut nodes = 0;
+
+        for depth in 1..=max_depth.max(1) {
+            self.prepare(depth);
+            let searched = self.search_root(state, depth, &[]);
+            nodes += searched.nodes;
+
+            //a search cut short has a best move only when it finished the first root move, and
+            //that is only worth more than nothing
+            if self.stopped {
+                if result.is_none() {
+                    result = Some(searched);
+                }
+                break;
+            }
+
+            self.report(&searched, nodes);
+            let best = searched.best;
+            result = Some(searched);
+            if !time.next_depth(best) {
+                break;
+            }
+        }
+
+        self.deadline = None;
+        self.stopped = false;
+        nodes += self.stop_helpers(helpers);
+
+        //stopped before depth 1 finished a root move, any legal move beats none
+        let mut result = result.expect("Depth 1 is always searched.");
+        if result.best.is_none() {
+            result.best = self.first_move(state);
+        }
+        result.nodes = nodes;
+        result
+    }
+
+    /// The best `lines` root moves, each with its own exact score and principal variation,
+    /// best first. Every line is a search of its own that leaves out the moves found before it.
+    /// Always single threaded.
+    pub fn search_multi(&mut self, state: &ChessState, depth: u32, lines: usize) -> Vec<SearchResult> {
+        self.prepare(depth);
+
+        let mut results: Vec<SearchResult> = Vec::new();
+        let mut excluded = Vec::new();
+
+        while results.len() < lines {
+            //a line the node limit cut short still ranks its moves, the next starts afresh
+            self.stopped = false;
+            let result = self.search_root(state, depth, &excluded);
+            match result.best {
+                Some(best) => excluded.push(best),
+                None => break,
+            }
+            results.push(result);
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        results
+    }
+
+    /// Whether `action` scores at least `threshold` for the side to move in `state` when searched
+    /// `depth` plies, or None when the search was stopped before it knew. A zero window search
+    /// only proves that bound, which is much cheaper than the move's exact score, e.g. to ask
+    /// whether a played move was nearly as good as the best one. Always single threaded.
+    pub fn verify(&mut self, state: &ChessState, action: Move, depth: u32, threshold: i32) -> Option<bool> {
+        self.prepare(depth);
+        self.stopped = false;
+        self.nodes = 0;
+
+        let with_network;
+        let state = match &self.network {
+            Some(network) => {
+                with_network = state.with_network(network);
+                &with_network
+            }
+            None => state,
+        };
+
+        self.start_line(state);
+
+        let mut child = state.clone();
+        child.apply_move(action);
+
+        //searched like the root searches it, so the answer agrees with search at that depth
+        let child_depth = depth.saturating_sub(1) + self.extension(state, action, 0, depth);
+        let score = -self.negamax(&child, child_depth, 1, -threshold, -threshold + 1);
+
+        let stopped = self.stopped;
+        self.stopped = false;
+        self.stop.store(false, Ordering::Relaxed);
+
+        if stopped {
+            None
+        } else {
+            Some(score >= threshold)
+        }
+    }
+
+    //a finished depth to the listener, with nodes the main thread searched in the whole search
+    fn report(&mut self, result: &SearchResult, nodes: u64) {
+        if self.listener.is_none() {
+            return;
+        }
+
+        let nodes = nodes + self.helper_nodes.load(Ordering::Relaxed);
+        let seconds = self.started.elapsed().as_secs_f64();
+        let event = SearchEvent::Depth {
+            depth: result.depth,
+            score: Score::new(result.score),
+            pv: result.pv.clone(),
+            nodes,
+            nps: if seconds > 0.0 { (nodes as f64 / seconds) as u64 } else { 0 },
+            hashfull: self.tt.hashfull(),
+        };
+
+        self.emit(&event);
+    }
+
+    fn emit(&mut self, event: &SearchEvent) {
+        if let Some(listener) = self.listener.as_mut() {
+            listener(event);
+        }
+    }
+
+    //starts the helpers on state, each deepening up to max_depth until it is done or stopped
+    fn start_helpers(&mut self, state: &ChessState, max_depth: u32) -> Vec<JoinHandle<(Engine, u64)>> {
+        self.stopped = false;
+        self.started = Instant::now();
+        self.helper_nodes.store(0, Ordering::Relaxed);
+
+        while self.helpers.len() < self.threads - 1 {
+            let helper = self.helper();
+            self.helpers.push(helper);
+        }
+
+        let (promotions, reductions, futility, contempt) = (self.promotions, self.reductions, self.futility, self.contempt);
+        let shuffle = self.shuffle;
+        let network = self.network.clone();
+        let root_moves = self.root_moves.clone();
+        let game = self.game.clone();
+
+        self.helpers.drain(..).enumerate().map(|(i, mut helper)| {
+            let state = state.clone();
+            helper.promotions = promotions;
+            helper.reductions = reductions;
+            helper.futility = futility;
+            helper.contempt = contempt;
+            helper.shuffle = shuffle;
+            helper.root_moves = root_moves.clone();
+            helper.game = game.clone();
+            helper.network = network.clone();
+
+            thread::spawn(move || {
+                helper.stopped = false;
+                let mut nodes = 0;
+
+                //every other helper starts a ply deeper, so the threads spread over more depths
+                let first = (1 + i as u32 % 2).min(max_depth.max(1));
+                for depth in first..=max_depth.max(1) {
+                    helper.prepare(depth);
+                    helper.search_root(&state, depth, &[]);
+                    nodes += helper.nodes;
+
+                    if helper.stopped {
+                        break;
+                    }
+                }
+
+                (helper, nodes)
+            })
+        }).collect()
+    }
+
+    //stops the helpers and takes their engines back, returning the nodes they searched. the
+    //stop flag is lowered again for the next search
+    fn stop_helpers(&mut self, helpers: Vec<JoinHandle<(Engine, u64)>>) -> u64 {
+        self.stop.store(true, Ordering::Relaxed);
+
+        let mut nodes = 0;
+        for helper in helpers {
+            //a helper that panicked is replaced by a new one at the next search
+            if let Ok((helper, searched)) = helper.join() {
+                self.helpers.push(helper);
+                nodes += searched;
+            }
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        nodes
+    }
+
+    fn prepare(&mut self, depth: u32) {
+        self.max_ply = 2 * depth as i32;
+        self.killers = vec![[None; 2]; self.max_ply as usize + 1];
+        self.pv = vec![Vec::new(); self.max_ply as usize + 2];
+
+        //history from earlier searches still helps, but should give way to what this one finds
+        self.age_history();
+    }
+
+    //searches the root moves allowed by root_moves other than `excluded`
+    fn search_root(&mut self, state: &ChessState, depth: u32, excluded: &[Move]) -> SearchResult {
+        self.nodes = 0;
+        self.root_nodes.clear();
+
+        //the accumulator then follows every move of the search
+        let with_network;
+        let state = match &self.network {
+            Some(network) => {
+                with_network = state.with_network(network);
+                &with_network
+            }
+            None => state,
+        };
+
+        let key = state.hash();
+        self.start_line(state);
+
+        let mut moves = state.legal_moves_with(self.promotions);
+        let allowed = self.root_moves.as_ref();
+        moves.retain(|action| !excluded.contains(action) && allowed.is_none_or(|allowed| allowed.contains(action)));
+        order_moves(state, &mut moves, self.tt.probe(key).and_then(|entry| entry.best), &[None; 2], &self.history);
+
+        let mut best = None;
+        let mut alpha = -INFINITY;
+
+        for action in moves {
+            let mut child = state.clone();
+            child.apply_move(action);
+
+            let before = self.nodes;
+            let child_depth = depth.saturating_sub(1) + self.extension(state, action, 0, depth);
+            let score = -self.negamax(&child, child_depth, 1, -INFINITY, -alpha);
+            self.root_nodes.push((action, self.nodes - before));
+
+            if self.stopped {
+                break;
+            }
+
+            if score > alpha {
+                if best.is_some() {
+                    self.emit(&SearchEvent::BestMove { depth, best: action, score: Score::new(score) });
+                }
+
+                best = Some(action);
+                alpha = score;
+                self.update_pv(0, action);
+            }
+        }
+
+        let score = match best {
+            Some(_) => alpha,
+            None => self.negamax(state, 0, 0, -INFINITY, INFINITY),
+        };
+
+        //with moves left out the best one found is not the position's best move
+        if best.is_some() && excluded.is_empty() && self.root_moves.is_none() && !self.stopped {
+            let depth = depth.min(u8::MAX as u32) as u8;
+            self.tt.store(Entry { key, best, score, depth, bound: Bound::Exact });
+        }
+
+        SearchResult { best, score, depth, nodes: self.nodes, pv: self.pv[0].clone() }
+    }
+
+    //the first legal move the root moves allow, None when there is none
+    fn first_move(&self, state: &ChessState) -> Option<Move> {
+        let allowed = self.root_moves.as_ref();
+        state.legal_moves().iter()
+            .find(|action| allowed.is_none_or(|allowed| allowed.contains(action)))
+            .copied()
+    }
+
+    //starts the line from the root, after the game's positions, and sees whether the root side
+    //stands better there for the shuffle penalty
+    fn start_line(&mut self, root: &ChessState) {
+        self.line.clear();
+        self.line.extend_from_slice(&self.game);
+        self.line.push(root.hash());
+        self.ahead = self.shuffle != 0 && evaluate(root, &mut self.pawns) >= SHUFFLE_MARGIN;
+    }
+
+    //a draw from the point of view of the side to move at ply, the root side's at even plies
+    fn draw_score(&self, ply: i32) -> i32 {
+        if ply % 2 == 0 { -self.contempt } else { self.contempt }
+    }
+
+    //a repetition, which costs a root side standing better the shuffle penalty as well
+    fn repetition_score(&self, ply: i32) -> i32 {
+        let penalty = if self.ahead { self.shuffle } else { 0 };
+        if ply % 2 == 0 { -self.contempt - penalty } else { self.contempt + penalty }
+    }
+
+    //whether state already came up in the game or the line leading to it, with the same side to
+    //move and no capture or pawn move since
+    fn repeats(&self, state: &ChessState) -> bool {
+        let key = state.hash();
+        self.line.iter().rev().take(state.move_rule as usize).skip(1).step_by(2).any(|&earlier| earlier == key)
+    }
+
+    fn negamax(&mut self, state: &ChessState, depth: u32, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        self.pv[ply as usize].clear();
+
+        if self.out_of_time() {
+            return 0;
+        }
+
+        let mut moves = state.legal_moves_with(self.promotions);
+        if moves.is_empty() {
+            return if state.in_check() { -MATE + ply } else { self.draw_score(ply) };
+        }
+
+        if self.repeats(state) {
+            return self.repetition_score(ply);
+        }
+
+        if state.fifty_move_draw() || state.insufficient_material() {
+            return self.draw_score(ply);
+        }
+
+        if depth == 0 {
+            return self.quiescence(state, alpha, beta);
+        }
+
+        let key = state.hash();
+        let entry = self.tt.probe(key);
+
+        if let Some(entry) = entry.filter(|entry| entry.depth as u32 >= depth) {
+            let score = from_table(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        //near the horizon, out of check and away from mate scores, the static score decides
+        //whether the node or its quiet moves are worth searching
+        let in_check = state.in_check();
+        let shallow = depth <= self.futility.max_depth && !in_check
+            && alpha.abs() < MATE_BOUND && beta.abs() < MATE_BOUND;
+        let static_score = if shallow { evaluate(state, &mut self.pawns) } else { 0 };
+
+        if shallow && static_score - self.futility.reverse_margin * depth as i32 >= beta {
+            return static_score;
+        }
+
+        let futile = shallow && static_score + self.futility.margin * depth as i32 <= alpha;
+
+        let killers = self.killers[ply as usize];
+        order_moves(state, &mut moves, entry.and_then(|entry| entry.best), &killers, &self.history);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        self.line.push(key);
+
+        for (index, action) in moves.into_iter().enumerate() {
+            let mut child = state.clone();
+            child.apply_move(action);
+
+            //only quiet moves that neither escape nor give check are reduced or pruned
+            let quiet = state.captured(action).is_none() && action.promotion.is_none();
+            let check = child.in_check();
+            if futile && quiet && index > 0 && !check {
+                continue;
+            }
+
+            let reduction = if quiet && !in_check && !check {
+                self.reductions.reduction(depth, index)
+            } else {
+                0
+            };
+
+            let depth = depth - 1 + self.extension(state, action, ply, depth);
+
+            //a reduced move is only tested against alpha, and searched in full if it beats it
+            let mut score = alpha;
+            if reduction > 0 {
+                score = -self.negamax(&child, depth - reduction, ply + 1, -alpha - 1, -alpha);
+            }
+            if reduction == 0 || score > alpha {
+                score = -self.negamax(&child, depth, ply + 1, -beta, -alpha);
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(action);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply as usize, action);
+                    if alpha >= beta {
+                        if state.captured(action).is_none() {
+                            self.store_killer(ply, action);
+                            self.reward_history(action, depth);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.line.pop();
+        if self.stopped {
+            return 0;
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+
+        let depth = depth.min(u8::MAX as u32) as u8;
+        self.tt.store(Entry { key, best: best_move, score: to_table(best, ply), depth, bound });
+
+        best
+    }
+
+    //past the horizon only captures and queen promotions are searched, until the position is
+    //quiet, so a line never ends halfway through an exchange. the side to move may always
+    //stand on the static score instead
+    fn quiescence(&mut self, state: &ChessState, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+
+        if self.out_of_time() {
+            return 0;
+        }
+
+        let mut best = evaluate(state, &mut self.pawns);
+        if best >= beta {
+            return best;
+        }
+        alpha = alpha.max(best);
+
+        let mut moves = state.capture_moves();
+        moves.retain(|action| action.promotion.is_none_or(|piece| piece == Piece::Queen));
+        order_moves(state, &mut moves, None, &[None; 2], &self.history);
+
+        for action in moves {
+            let mut child = state.clone();
+            child.apply_move(action);
+
+            let score = -self.quiescence(&child, -beta, -alpha);
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    //the stop flag and the node limit are read at every node, the clock at the first and then
+    //every few thousand nodes. once any says so every node returns at once
+    fn out_of_time(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        if self.helper && self.nodes % CLOCK_INTERVAL == 0 {
+            self.helper_nodes.fetch_add(CLOCK_INTERVAL, Ordering::Relaxed);
+        }
+
+        self.stopped = self.stop.load(Ordering::Relaxed) || self.node_limit.is_some_and(|limit| self.nodes >= limit);
+        if self.nodes == 1 || self.nodes % CLOCK_INTERVAL == 0 {
+            self.stopped = self.stopped || self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.stopped
+    }
+
+    //checks are searched a ply deeper, so forcing lines are not cut off at the horizon, as
+    //long as the line stays within max_ply
+    fn extension(&self, state: &ChessState, action: Move, ply: i32, depth: u32) -> u32 {
+        if ply + (depth as i32) < self.max_ply && state.gives_check(action) {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn store_killer(&mut self, ply: i32, action: Move) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(action) {
+            killers[1] = killers[0];
+            killers[0] = Some(action);
+        }
+    }
+
+    fn reward_history(&mut self, action: Move, depth: u32) {
+        let score = &mut self.history[action.origin as usize][action.dest as usize];
+        *score += (depth * depth) as i32;
+
+        if *score > HISTORY_LIMIT {
+            self.age_history();
+        }
+    }
+
+    fn age_history(&mut self) {
+        for score in self.history.iter_mut().flatten() {
+            *score /= 2;
+        }
+    }
+
+    //the line at ply becomes action followed by the line just found for the reply
+    fn update_pv(&mut self, ply: usize, action: Move) {
+        let (lines, replies) = self.pv.split_at_mut(ply + 1);
+        let line = &mut lines[ply];
+        line.clear();
+        line.push(action);
+        line.extend_from_slice(&replies[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_proves_the_bound_search_found() {
+        let state = ChessState::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let result = Engine::new().search(&state, 3);
+        let best = result.best.unwrap();
+
+        let mut engine = Engine::new();
+        assert_eq!(engine.verify(&state, best, 3, result.score), Some(true));
+        assert_eq!(engine.verify(&state, best, 3, result.score + 1), Some(false));
+
+        //no move does better than the best one
+        for &action in state.legal_moves().iter() {
+            assert_eq!(engine.verify(&state, action, 3, result.score + 1), Some(false));
+        }
+    }
+
+    #[test]
+    fn verify_stopped_early_does_not_know() {
+        let state = ChessState::default();
+        let mut engine = Engine::new();
+        engine.set_node_limit(Some(1));
+
+        let action = state.legal_moves()[0];
+        assert_eq!(engine.verify(&state, action, 4, 0), None);
+    }
+
+    #[test]
+    fn repeating_costs_the_better_side_the_shuffle_penalty() {
+        let mut state = ChessState::from_fen("k7/8/8/8/8/8/8/3Q3K w - - 0 1");
+        let mut game = Vec::new();
+        for action in ["d1d2", "a8b8", "d2d1", "b8a8"] {
+            game.push(state.hash());
+            let action = state.find_move(action).unwrap();
+            state.apply_move(action);
+        }
+        let repeat = state.find_move("d1d2").unwrap();
+
+        let mut engine = Engine::new();
+        engine.set_game(game);
+        engine.set_root_moves(Some(vec![repeat]));
+        assert_eq!(engine.search(&state, 1).score, 0);
+
+        engine.set_shuffle(30);
+        assert_eq!(engine.search(&state, 1).score, -30);
+    }
+}
@@ -0,0 +1,68 @@
+use super::{BitBoard, ChessState, Color, Piece};
+
+//indexed by the relative rank of our closest pawn on the file, 0 when there is none
+const SHELTER: [i32; 8] = [-36, 40, 25, 10, 5, 0, 0, 0];
+
+//indexed by the relative rank of the closest enemy pawn on the file, 0 when there is none
+const STORM: [i32; 8] = [0, -50, -50, -30, -15, -5, 0, 0];
+
+pub fn relative_rank(color: Color, pos: u32) -> usize {
+    match color {
+        Color::White => (pos / 8) as usize,
+        Color::Black => (7 - pos / 8) as usize,
+    }
+}
+
+pub fn file_bb(file: u32) -> BitBoard {
+    (0..8).fold(BitBoard::new(), |bb, y| bb.add_pos(y * 8 + file))
+}
+
+//the king's file and its neighbours, shifted inwards on the edge
+fn shelter_files(king: u32) -> impl Iterator<Item = u32> {
+    let file = (king % 8).clamp(1, 6);
+    (file - 1)..=(file + 1)
+}
+
+//relative rank of the pawn closest to the king that is level with or in front of it
+fn closest_pawn(pawns: BitBoard, file: u32, color: Color, king_rank: usize) -> usize {
+    (pawns & file_bb(file)).get_indices()
+        .map(|pos| relative_rank(color, pos))
+        .filter(|&rank| rank >= king_rank)
+        .min()
+        .unwrap_or(0)
+}
+
+impl ChessState {
+    //bonus for our pawns standing in front of our king, in centipawns
+    pub fn shelter_score (&self, color: Color) -> i32 {
+        let king = self.king_pos(color);
+        let king_rank = relative_rank(color, king);
+        let pawns = self.pieces_of(color, Piece::Pawn);
+
+        shelter_files(king)
+            .map(|file| SHELTER[closest_pawn(pawns, file, color, king_rank)])
+            .sum()
+    }
+
+    //penalty (zero or negative) for enemy pawns advancing on our king, in centipawns
+    pub fn storm_score (&self, color: Color) -> i32 {
+        let king = self.king_pos(color);
+        let king_rank = relative_rank(color, king);
+        let ours = self.pieces_of(color, Piece::Pawn);
+        let theirs = self.pieces_of(color.opposite(), Piece::Pawn);
+
+        shelter_files(king)
+            .map(|file| {
+                let storm = closest_pawn(theirs, file, color, king_rank);
+                let shelter = closest_pawn(ours, file, color, king_rank);
+
+                //a storming pawn stuck against our shelter pawn is much less dangerous
+                if shelter != 0 && shelter + 1 == storm {
+                    STORM[storm] / 2
+                } else {
+                    STORM[storm]
+                }
+            })
+            .sum()
+    }
+}
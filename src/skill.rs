@@ -0,0 +1,94 @@
+use super::search::{Engine, SearchResult};
+use super::{ChessState, Move};
+
+use rand::Rng;
+
+pub const MAX_LEVEL: u32 = 20;
+
+//candidate moves searched below the top level, one of which is played
+const LINES: usize = 4;
+//each level below the top widens the window of candidates by this many centipawns
+const MARGIN_STEP: i32 = 15;
+//nodes each line may search at level 0, doubling every two levels
+const BASE_NODES: u64 = 1000;
+
+//a rough guess at the strength of levels 0 and MAX_LEVEL, not measured against rated play
+const MIN_ELO: u32 = 800;
+const MAX_ELO: u32 = 2300;
+
+/// A strength for practice games, from 0 to `MAX_LEVEL`. Lower levels search shallower and
+/// fewer nodes, and pick at random among the moves that scored close to the best one, so the
+/// engine's mistakes are those of a weaker player rather than random blunders. The choice is
+/// drawn from the caller's generator, so a seeded game plays the same way again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Skill {
+    level: u32,
+}
+
+impl Skill {
+    pub fn new(level: u32) -> Self {
+        Self { level: level.min(MAX_LEVEL) }
+    }
+
+    /// The level closest to a rating of `elo`.
+    pub fn from_elo(elo: u32) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let level = ((elo - MIN_ELO) * MAX_LEVEL + (MAX_ELO - MIN_ELO) / 2) / (MAX_ELO - MIN_ELO);
+        Self::new(level)
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn depth(&self) -> u32 {
+        1 + self.level / 3
+    }
+
+    //None at the top level, which plays at full strength for its depth
+    fn nodes(&self) -> Option<u64> {
+        if self.level == MAX_LEVEL {
+            None
+        } else {
+            Some(BASE_NODES << (self.level / 2))
+        }
+    }
+
+    fn margin(&self) -> i32 {
+        (MAX_LEVEL - self.level) as i32 * MARGIN_STEP
+    }
+
+    /// The move to play in `state`, None when there is no legal move.
+    pub fn choose(&self, engine: &mut Engine, state: &ChessState, rng: &mut impl Rng) -> Option<Move> {
+        let lines = if self.level == MAX_LEVEL { 1 } else { LINES };
+
+        engine.set_node_limit(self.nodes());
+        let results = engine.search_multi(state, self.depth(), lines);
+        engine.set_node_limit(None);
+
+        //a node limit can stop even the first line before it finished a move
+        self.pick(&results, rng).or_else(|| state.legal_moves().first().copied())
+    }
+
+    //candidates within the margin of the best score, the better ones more likely
+    fn pick(&self, results: &[SearchResult], rng: &mut impl Rng) -> Option<Move> {
+        let best = results.iter().map(|result| result.score).max()?;
+        let margin = self.margin();
+
+        let candidates: Vec<(Move, i32)> = results.iter()
+            .filter(|result| result.score >= best - margin)
+            .filter_map(|result| result.best.map(|action| (action, margin - (best - result.score) + 1)))
+            .collect();
+
+        let total: i32 = candidates.iter().map(|&(_, weight)| weight).sum();
+        let mut roll = rng.gen_range(0, total);
+        for &(action, weight) in &candidates {
+            if roll < weight {
+                return Some(action);
+            }
+            roll -= weight;
+        }
+
+        candidates.last().map(|&(action, _)| action)
+    }
+}
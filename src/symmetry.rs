@@ -0,0 +1,60 @@
+use super::{eval, positions, ChessState, Color};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+//evaluation terms from white's point of view, each must negate when the board is mirrored
+const TERMS: &[(&str, fn(&ChessState) -> i32)] = &[
+    ("shelter", |state| state.shelter_score(Color::White) - state.shelter_score(Color::Black)),
+    ("storm", |state| state.storm_score(Color::White) - state.storm_score(Color::Black)),
+    //the whole evaluation the search uses is from the side to move, which the mirror swaps,
+    //so it is turned around to white's point of view like the rest
+    ("evaluation", |state| match state.active {
+        Color::White => eval::evaluate(state),
+        Color::Black => -eval::evaluate(state),
+    }),
+];
+
+//positions from random games, the same ones every run
+const SEED: u64 = 1;
+const RANDOM_POSITIONS: usize = 500;
+
+pub fn random_position<R: Rng>(rng: &mut R, plies: usize) -> ChessState {
+    let mut state = ChessState::default();
+
+    for _ in 0..plies {
+        if state.outcome().is_some() {
+            break;
+        }
+
+        let moves = state.legal_moves();
+        state.apply_move(moves[rng.gen_range(0, moves.len())]);
+    }
+
+    state
+}
+
+fn assert_symmetric(state: &ChessState) {
+    let mirror = state.mirror();
+
+    for &(term, score) in TERMS {
+        assert_eq!(score(state), -score(&mirror), "{} is asymmetric in\n{}", term, state);
+    }
+}
+
+#[test]
+fn shared_positions_evaluate_symmetrically() {
+    for fen in positions::all() {
+        assert_symmetric(&ChessState::from_fen(fen));
+    }
+}
+
+#[test]
+fn random_positions_evaluate_symmetrically() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+
+    for _ in 0..RANDOM_POSITIONS {
+        let plies = rng.gen_range(0, 120);
+        assert_symmetric(&random_position(&mut rng, plies));
+    }
+}
@@ -0,0 +1,143 @@
+use super::{piece_attacks, BitBoard, ChessState, Move, Piece};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Motif {
+    Fork { attacker: u32, targets: BitBoard },
+    Pin { pinner: u32, pinned: u32, behind: u32, absolute: bool },
+    Skewer { attacker: u32, front: u32, behind: u32 },
+    DiscoveredAttack { attacker: u32, target: u32 },
+}
+
+impl Motif {
+    //theme names as used for tagging puzzles
+    pub fn theme(&self) -> &'static str {
+        match self {
+            Motif::Fork { .. } => "fork",
+            Motif::Pin { absolute: true, .. } => "absolutePin",
+            Motif::Pin { absolute: false, .. } => "relativePin",
+            Motif::Skewer { .. } => "skewer",
+            Motif::DiscoveredAttack { .. } => "discoveredAttack",
+        }
+    }
+}
+
+pub fn value(piece: Piece) -> u32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight | Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => 100,
+    }
+}
+
+fn is_slider(piece: Piece) -> bool {
+    match piece {
+        Piece::Bishop | Piece::Rook | Piece::Queen => true,
+        _ => false,
+    }
+}
+
+//a piece is worth attacking if it is the king, outvalues the attacker or is undefended
+fn is_target(state: &ChessState, attacker: Piece, pos: u32) -> bool {
+    let piece = state.piece_at(pos).unwrap();
+    let color = state.color_at(pos).unwrap();
+
+    piece == Piece::King
+        || value(piece) > value(attacker)
+        || state.attackers_to(pos, color, state.occupied()).is_empty()
+}
+
+fn fork_from(state: &ChessState, pos: u32) -> Option<Motif> {
+    let piece = state.piece_at(pos)?;
+    let color = state.color_at(pos)?;
+    let enemy = state.occupied_by(color.opposite());
+
+    let mut targets = BitBoard::new();
+    for target in (piece_attacks(piece, color, pos, state.occupied()) & enemy).get_indices() {
+        if is_target(state, piece, target) {
+            targets = targets.add_pos(target);
+        }
+    }
+
+    if targets.count() >= 2 {
+        Some(Motif::Fork { attacker: pos, targets })
+    } else {
+        None
+    }
+}
+
+//pins and skewers along the lines of the slider on pos
+fn line_motifs(state: &ChessState, pos: u32, found: &mut Vec<Motif>) {
+    let (piece, color) = match (state.piece_at(pos), state.color_at(pos)) {
+        (Some(piece), Some(color)) if is_slider(piece) => (piece, color),
+        _ => return,
+    };
+
+    let occupied = state.occupied();
+    let enemy = state.occupied_by(color.opposite());
+    let attacks = piece_attacks(piece, color, pos, occupied);
+
+    for front in (attacks & enemy).get_indices() {
+        //removing the front piece only extends the ray running through it
+        let xray = piece_attacks(piece, color, pos, occupied.clear_pos(front))
+            & attacks.invert() & enemy;
+
+        let front_piece = state.piece_at(front).unwrap();
+
+        for behind in xray.get_indices() {
+            let behind_piece = state.piece_at(behind).unwrap();
+
+            if behind_piece == Piece::King {
+                found.push(Motif::Pin { pinner: pos, pinned: front, behind, absolute: true });
+            } else if front_piece == Piece::King || value(front_piece) > value(behind_piece) {
+                found.push(Motif::Skewer { attacker: pos, front, behind });
+            } else if value(behind_piece) > value(front_piece) {
+                found.push(Motif::Pin { pinner: pos, pinned: front, behind, absolute: false });
+            }
+        }
+    }
+}
+
+//every fork, pin and skewer currently on the board, for both sides
+pub fn motifs(state: &ChessState) -> Vec<Motif> {
+    let mut found = Vec::new();
+
+    for pos in state.occupied().get_indices() {
+        found.extend(fork_from(state, pos));
+        line_motifs(state, pos, &mut found);
+    }
+
+    found
+}
+
+//motifs created by playing action: threats from the moved piece and attacks it uncovers
+pub fn move_motifs(state: &ChessState, action: Move) -> Vec<Motif> {
+    let color = state.active;
+
+    let mut after = state.clone();
+    after.apply_move(action);
+
+    let mut found = Vec::new();
+    found.extend(fork_from(&after, action.dest));
+    line_motifs(&after, action.dest, &mut found);
+
+    let enemy = after.occupied_by(color.opposite());
+    let sliders = after.pieces_of(color, Piece::Bishop)
+        | after.pieces_of(color, Piece::Rook)
+        | after.pieces_of(color, Piece::Queen);
+
+    for pos in sliders.clear_pos(action.dest).get_indices() {
+        let piece = after.piece_at(pos).unwrap();
+        let before = piece_attacks(piece, color, pos, state.occupied());
+        let uncovered = piece_attacks(piece, color, pos, after.occupied()) & before.invert() & enemy;
+
+        for target in uncovered.get_indices() {
+            if is_target(&after, piece, target) {
+                found.push(Motif::DiscoveredAttack { attacker: pos, target });
+            }
+        }
+    }
+
+    found
+}
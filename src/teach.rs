@@ -0,0 +1,107 @@
+use super::{pos_to_algebra, ChessState, Color, Move, Piece, Square};
+use super::tactics::{self, Motif};
+
+//hint levels: 1 warns about pieces left en prise, 2 adds the opponent's checks and captures,
+//3 adds the tactical motifs a move creates and pawns walling in a bishop
+pub const EN_PRISE: u32 = 1;
+pub const THREATS: u32 = 2;
+pub const MOTIFS: u32 = 3;
+
+fn name(piece: Piece) -> String {
+    format!("{:?}", piece).to_lowercase()
+}
+
+//pieces of color that are attacked and either undefended or attacked by something cheaper
+fn en_prise(state: &ChessState, color: Color) -> Vec<String> {
+    let occupied = state.occupied();
+    let mut warnings = Vec::new();
+
+    for (square, piece) in state.pieces(color).filter(|&(_, piece)| piece != Piece::King) {
+        let pos = square.index();
+        let attackers = state.attackers_to(pos, color.opposite(), occupied);
+
+        let cheapest = match attackers.get_indices().min_by_key(|&attacker| tactics::value(state.piece_at(attacker).unwrap())) {
+            Some(attacker) => attacker,
+            None => continue,
+        };
+
+        let attacker = state.piece_at(cheapest).unwrap();
+        let defended = !state.attackers_to(pos, color, occupied).is_empty();
+
+        if !defended || tactics::value(attacker) < tactics::value(piece) {
+            warnings.push(format!("Your {} on {} is en prise to the {} on {}.",
+                name(piece), square, name(attacker), pos_to_algebra(cheapest)));
+        }
+    }
+
+    warnings
+}
+
+fn list(state: &ChessState, moves: &[Move]) -> String {
+    moves.iter().map(|&action| state.san(action)).collect::<Vec<_>>().join(", ")
+}
+
+fn describe(state: &ChessState, motif: Motif) -> String {
+    let at = |pos: u32| format!("{} on {}", name(state.piece_at(pos).unwrap()), pos_to_algebra(pos));
+
+    match motif {
+        Motif::Fork { attacker, targets } => format!("The {} forks {} pieces.", at(attacker), targets.count()),
+        Motif::Pin { pinner, pinned, .. } => format!("The {} pins the {}.", at(pinner), at(pinned)),
+        Motif::Skewer { attacker, front, .. } => format!("The {} skewers the {}.", at(attacker), at(front)),
+        Motif::DiscoveredAttack { attacker, target } => format!("The {} now attacks the {}.", at(attacker), at(target)),
+    }
+}
+
+//a pawn move that leaves most pawns on the color of the player's only bishop, blocking it in
+fn bad_bishop(after: &ChessState, color: Color, action: Move) -> Option<String> {
+    let bishops = after.pieces_of(color, Piece::Bishop);
+    if action.piece != Piece::Pawn || action.promotion.is_some() || bishops.count() != 1 {
+        return None;
+    }
+
+    let bishop_color = Square::new(bishops.solo_pos()).color();
+    let same = after.pawns_on(color, bishop_color);
+    let pawns = after.pieces_of(color, Piece::Pawn).count();
+
+    if Square::new(action.dest).color() == bishop_color && same >= 3 && same * 2 > pawns {
+        Some(format!("{} of your {} pawns now stand on your bishop's color, which hems it in.", same, pawns))
+    } else {
+        None
+    }
+}
+
+/// Hints for a learning player about the move they just played from `before`.
+pub fn hints(before: &ChessState, action: Move, level: u32) -> Vec<String> {
+    let player = before.active;
+    let mut after = before.clone();
+    after.apply_move(action);
+
+    let mut hints = Vec::new();
+
+    if level >= EN_PRISE {
+        hints.extend(en_prise(&after, player));
+    }
+
+    if level >= THREATS && after.outcome().is_none() {
+        let moves = after.legal_moves();
+        let checks: Vec<Move> = moves.iter().copied().filter(|&reply| after.gives_check(reply)).collect();
+        let captures: Vec<Move> = moves.iter().copied()
+            .filter(|reply| after.color_at(reply.dest) == Some(player) && !after.is_castle(*reply))
+            .collect();
+
+        if !checks.is_empty() {
+            hints.push(format!("Your opponent can give check: {}.", list(&after, &checks)));
+        }
+
+        if !captures.is_empty() {
+            hints.push(format!("Your opponent can capture: {}.", list(&after, &captures)));
+        }
+    }
+
+    if level >= MOTIFS {
+        hints.extend(tactics::move_motifs(before, action).into_iter().map(|motif| describe(&after, motif)));
+        hints.extend(bad_bishop(&after, player, action));
+    }
+
+    hints
+}
@@ -0,0 +1,114 @@
+use super::{Color, Move};
+
+use std::time::{Duration, Instant};
+
+//moves the game is assumed to last when the time control does not say
+const EXPECTED_MOVES: u32 = 30;
+//kept back from every budget for the time it takes to send the move
+const OVERHEAD: Duration = Duration::from_millis(50);
+//the hard budget may run this many times over the soft one, but never past a part of the clock
+const HARD_FACTOR: u32 = 4;
+const HARD_CLOCK_DIVISOR: u32 = 3;
+//each change of the best move late in the search extends the soft budget by half its
+//first size, up to this many times that size
+const PANIC_LIMIT: u32 = 3;
+//the first move out of the opening book gets this many times its soft budget, within the hard one
+const BOOK_EXIT_FACTOR: u32 = 2;
+
+/// The clocks of a game, as a GUI sends them with each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    //by color, the time left and what each move adds
+    pub time: [Duration; 2],
+    pub increment: [Duration; 2],
+    //moves until the next time control, None when the clock has to last the game
+    pub moves_to_go: Option<u32>,
+}
+
+impl TimeControl {
+    /// Both sides starting with `time` and gaining `increment` a move, e.g. 5+3 blitz.
+    pub fn new(time: Duration, increment: Duration) -> Self {
+        Self { time: [time; 2], increment: [increment; 2], moves_to_go: None }
+    }
+
+    /// Charges `color` for a move that took `elapsed` and adds its increment. Returns false
+    /// when the clock ran out first.
+    pub fn charge(&mut self, color: Color, elapsed: Duration) -> bool {
+        let time = &mut self.time[color as usize];
+        if elapsed > *time {
+            *time = Duration::from_secs(0);
+            return false;
+        }
+
+        *time = *time - elapsed + self.increment[color as usize];
+        true
+    }
+}
+
+/// How long the engine thinks about one move. Iterative deepening starts no depth after the
+/// soft budget is half spent, since the next would likely not finish in time, and the search
+/// is stopped outright at the hard budget.
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    start: Instant,
+    //the soft budget as first allocated, which panic extensions add to
+    base: Duration,
+    soft: Duration,
+    hard: Duration,
+    best: Option<Move>,
+}
+
+impl TimeManager {
+    /// Budgets the move `color` is about to make, starting now.
+    pub fn new(control: &TimeControl, color: Color) -> Self {
+        let left = control.time[color as usize].checked_sub(OVERHEAD).unwrap_or_default();
+        let increment = control.increment[color as usize];
+        let moves = control.moves_to_go.unwrap_or(EXPECTED_MOVES).max(1);
+
+        //with a single move to go the whole clock may be used, except for the margin
+        let hard_limit = if moves == 1 { left } else { left / HARD_CLOCK_DIVISOR };
+        let soft = (left / moves + increment * 3 / 4).min(hard_limit);
+        let hard = (soft * HARD_FACTOR).min(hard_limit);
+
+        Self { start: Instant::now(), base: soft, soft, hard, best: None }
+    }
+
+    /// A budget of exactly `time` for the move, without extensions.
+    pub fn fixed(time: Duration) -> Self {
+        Self { start: Instant::now(), base: time, soft: time, hard: time, best: None }
+    }
+
+    /// No budget at all, the search goes on until it reaches its depth or is stopped.
+    pub fn infinite() -> Self {
+        //a year, as an Instant cannot be pushed arbitrarily far into the future
+        Self::fixed(Duration::from_secs(365 * 24 * 60 * 60))
+    }
+
+    /// Budgets more for the first move after the opening book, which saved the time on the
+    /// clock and left the engine a position it has searched nothing of.
+    pub fn leave_book(&mut self) {
+        self.base = (self.base * BOOK_EXIT_FACTOR).min(self.hard);
+        self.soft = self.base;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// When the search must stop, however far it got.
+    pub fn deadline(&self) -> Instant {
+        self.start + self.hard
+    }
+
+    /// Called after each completed depth with its best move, whether to search another.
+    /// A best move that changed since the last depth buys more time, as the search has not
+    /// settled on it yet.
+    pub fn next_depth(&mut self, best: Option<Move>) -> bool {
+        if self.best.is_some() && best != self.best {
+            self.soft = (self.soft + self.base / 2).min(self.base * PANIC_LIMIT).min(self.hard);
+        }
+        self.best = best;
+
+        self.elapsed() < self.soft / 2
+    }
+}
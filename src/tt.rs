@@ -0,0 +1,234 @@
+use super::{Move, Piece};
+
+use std::alloc::{self, Layout};
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    //the score is at least this, the search failed high
+    Lower,
+    //the score is at most this, no move raised alpha
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub key: u64,
+    pub best: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+impl Entry {
+    //score in the low 32 bits, then depth, bound, the move and a bit set in every entry,
+    //so that an empty slot reads as 0
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+
+        let action = self.best.map_or(0, |action| {
+            let promotion = action.promotion.map_or(0, |piece| piece as u64 + 1);
+            1 | (action.origin as u64) << 1 | (action.dest as u64) << 7 | (action.piece as u64) << 13 | promotion << 16
+        });
+
+        self.score as u32 as u64 | (self.depth as u64) << 32 | bound << 40 | action << 42 | 1 << 63
+    }
+
+    fn unpack(key: u64, data: u64) -> Self {
+        let action = (data >> 42) & 0x7FFFF;
+        let best = if action & 1 == 0 {
+            None
+        } else {
+            let promotion = (action >> 16) & 0x7;
+            Some(Move {
+                piece: Piece::kinds()[((action >> 13) & 0x7) as usize],
+                origin: ((action >> 1) & 0x3F) as u32,
+                dest: ((action >> 7) & 0x3F) as u32,
+                promotion: if promotion == 0 { None } else { Some(Piece::kinds()[promotion as usize - 1]) },
+            })
+        };
+
+        let bound = match (data >> 40) & 0x3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+
+        Entry { key, best, score: data as u32 as i32, depth: (data >> 32) as u8, bound }
+    }
+}
+
+//an entry stores its key xored with its data, so an entry torn by two threads writing it at
+//once no longer matches its key and is never read back
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn empty() -> Self {
+        Slot { key: AtomicU64::new(0), data: AtomicU64::new(0) }
+    }
+
+    fn load(&self) -> Option<Entry> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data;
+        if data == 0 {
+            None
+        } else {
+            Some(Entry::unpack(key, data))
+        }
+    }
+
+    fn store(&self, entry: &Entry) {
+        let data = entry.pack();
+        self.key.store(entry.key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+//four entries fill one cache line, so a probe touches a single line
+#[repr(align(64))]
+struct Bucket {
+    slots: [Slot; 4],
+}
+
+fn empty() -> Bucket {
+    Bucket { slots: [Slot::empty(), Slot::empty(), Slot::empty(), Slot::empty()] }
+}
+
+//buckets hashfull looks at, a thousand entries
+const HASHFULL_SAMPLE: usize = 250;
+
+//buckets in memory we allocate ourselves, so the alignment can be raised to a huge page
+struct Buckets {
+    ptr: NonNull<Bucket>,
+    len: usize,
+    layout: Layout,
+}
+
+//the buckets are plain data owned by the table
+unsafe impl Send for Buckets {}
+unsafe impl Sync for Buckets {}
+
+impl Buckets {
+    fn allocate(len: usize, large_pages: bool) -> Self {
+        let len = len.max(1);
+        let size = len * mem::size_of::<Bucket>();
+
+        if large_pages {
+            if let Some(buckets) = Self::allocate_huge(len, size) {
+                return buckets;
+            }
+        }
+
+        let layout = Layout::array::<Bucket>(len).expect("Transposition table too large.");
+        Self::allocate_with(len, layout).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+    }
+
+    //huge page aligned memory the kernel is asked to back with huge pages, None if unavailable
+    #[cfg(all(feature = "huge-pages", target_os = "linux"))]
+    fn allocate_huge(len: usize, size: usize) -> Option<Self> {
+        //huge pages are 2MB on x86-64 and most aarch64 systems
+        const HUGE_PAGE: usize = 2 * 1024 * 1024;
+
+        let size = (size + HUGE_PAGE - 1) / HUGE_PAGE * HUGE_PAGE;
+        let buckets = Self::allocate_with(len, Layout::from_size_align(size, HUGE_PAGE).ok()?)?;
+
+        unsafe {
+            libc::madvise(buckets.ptr.as_ptr() as *mut libc::c_void, size, libc::MADV_HUGEPAGE);
+        }
+        Some(buckets)
+    }
+
+    #[cfg(not(all(feature = "huge-pages", target_os = "linux")))]
+    fn allocate_huge(_len: usize, _size: usize) -> Option<Self> {
+        None
+    }
+
+    fn allocate_with(len: usize, layout: Layout) -> Option<Self> {
+        let ptr = NonNull::new(unsafe { alloc::alloc(layout) } as *mut Bucket)?;
+
+        for i in 0..len {
+            unsafe { ptr::write(ptr.as_ptr().add(i), empty()) };
+        }
+
+        Some(Self { ptr, len, layout })
+    }
+
+    fn as_slice(&self) -> &[Bucket] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for Buckets {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, self.layout) };
+    }
+}
+
+/// Search results by position hash, in cache line aligned buckets. With the `huge-pages`
+/// feature on Linux the table can be backed by huge pages to cut TLB misses, falling back
+/// to regular pages when that allocation fails. Threads searching at once share the table
+/// without locking it.
+pub struct TranspositionTable {
+    buckets: Buckets,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize, large_pages: bool) -> Self {
+        let len = megabytes * 1024 * 1024 / mem::size_of::<Bucket>();
+        Self { buckets: Buckets::allocate(len, large_pages) }
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        &self.buckets.as_slice()[(key % self.buckets.len as u64) as usize]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.bucket(key).slots.iter()
+            .filter_map(Slot::load)
+            .find(|entry| entry.key == key)
+    }
+
+    //replaces the same position, an empty slot or the shallowest entry of the bucket
+    pub fn store(&self, entry: Entry) {
+        let slots = &self.bucket(entry.key).slots;
+        let mut entries = [None; 4];
+        for (old, slot) in entries.iter_mut().zip(slots) {
+            *old = slot.load();
+        }
+
+        let slot = entries.iter()
+            .position(|old| old.is_none_or(|old| old.key == entry.key))
+            .unwrap_or_else(|| {
+                (0..slots.len()).min_by_key(|&i| entries[i].map_or(0, |old| old.depth)).unwrap_or(0)
+            });
+
+        slots[slot].store(&entry);
+    }
+
+    /// How full the table is in permille, from a sample of its first buckets.
+    pub fn hashfull(&self) -> u32 {
+        let sample = &self.buckets.as_slice()[..self.buckets.len.min(HASHFULL_SAMPLE)];
+        let used = sample.iter().flat_map(|bucket| bucket.slots.iter()).filter(|slot| slot.load().is_some()).count();
+        (used * 1000 / (4 * sample.len())) as u32
+    }
+
+    pub fn clear(&self) {
+        for bucket in self.buckets.as_slice() {
+            for slot in bucket.slots.iter() {
+                slot.key.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+}
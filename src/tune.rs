@@ -0,0 +1,236 @@
+use super::epd::Epd;
+use super::error::Error;
+use super::eval;
+use super::ChessState;
+
+use std::path::Path;
+
+//texel tuning: the evaluation, turned into an expected score by a sigmoid, should predict
+//how the games of its positions ended. the mean squared difference is minimized first by
+//gradient descent, then by trying each parameter one step up and down
+
+//scales of the first steps of the search for the sigmoid's scale, each ten times finer
+const SCALE_STEPS: [f64; 3] = [0.1, 0.01, 0.001];
+
+//step size of the gradient descent in centipawns, and Adam's decay rates
+const LEARNING_RATE: f64 = 0.5;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+struct Sample {
+    //parameter index and coefficient, stored small as there are millions of samples
+    coefficients: Vec<(u16, f32)>,
+    //1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+impl Sample {
+    fn evaluate(&self, parameters: &[f64]) -> f64 {
+        self.coefficients.iter().map(|&(i, c)| c as f64 * parameters[i as usize]).sum()
+    }
+}
+
+//the expected score for white of a position white evaluates at score
+fn sigmoid(scale: f64, score: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scale * score / 400.0))
+}
+
+fn parse_result(text: &str) -> Option<f64> {
+    match text.trim().trim_matches('"') {
+        "1-0" | "1.0" | "1" => Some(1.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        "0-1" | "0.0" | "0" => Some(0.0),
+        _ => None,
+    }
+}
+
+//either a FEN followed by the result in brackets, e.g. "... w - - 0 1 [0.5]", or an EPD
+//record with the result in its c9 or result opcode, e.g. "... w - - c9 \"1/2-1/2\";".
+//lines without a result are skipped
+fn parse_line(line: &str) -> Result<Option<(ChessState, f64)>, Error> {
+    if let Some(open) = line.find('[') {
+        let close = line[open..].find(']').map_or(line.len(), |close| open + close);
+        let fen = line[..open].trim();
+
+        //the move counters do not matter to the evaluation and are often left out
+        let state = if fen.split_whitespace().count() == 4 {
+            ChessState::parse_fen(&format!("{} 0 1", fen))?
+        } else {
+            ChessState::parse_fen(fen)?
+        };
+
+        return Ok(parse_result(&line[open + 1..close]).map(|result| (state, result)));
+    }
+
+    let epd = Epd::parse(line)?;
+    let result = epd.get("c9").or_else(|| epd.get("result"))
+        .and_then(|operands| operands.first())
+        .and_then(|result| parse_result(result));
+
+    Ok(result.map(|result| (epd.state, result)))
+}
+
+/// Tunes every evaluation parameter against a set of positions labelled with their game's
+/// result (see `parse_line`), in three steps: fitting the scale of the sigmoid, gradient descent
+/// and a local search.
+pub struct Tuner {
+    samples: Vec<Sample>,
+    parameters: Vec<f64>,
+    scale: f64,
+}
+
+impl Tuner {
+    /// Reads the positions of `path`, one per line. Known endings are left out, as the
+    /// parameters do not score them.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)?;
+        let mut samples = Vec::new();
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some((state, result)) = parse_line(line)? {
+                if let Some(coefficients) = eval::tuning_coefficients(&state) {
+                    let coefficients = coefficients.into_iter().map(|(i, c)| (i as u16, c as f32)).collect();
+                    samples.push(Sample { coefficients, result });
+                }
+            }
+        }
+
+        let parameters = eval::tuning_parameters().into_iter().map(f64::from).collect();
+        Ok(Self { samples, parameters, scale: 1.0 })
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The mean squared difference of the expected scores and the results.
+    pub fn error(&self) -> f64 {
+        self.error_with(&self.parameters, self.scale)
+    }
+
+    fn error_with(&self, parameters: &[f64], scale: f64) -> f64 {
+        let total: f64 = self.samples.iter()
+            .map(|sample| (sample.result - sigmoid(scale, sample.evaluate(parameters))).powi(2))
+            .sum();
+
+        total / self.samples.len().max(1) as f64
+    }
+
+    /// Fits the scale of the sigmoid to the current parameters, so that the tuning changes
+    /// what the evaluation thinks of positions rather than the size of its units.
+    pub fn fit_scale(&mut self) -> f64 {
+        let mut best = (self.error(), self.scale);
+
+        for &step in &SCALE_STEPS {
+            let center = best.1;
+            for i in -10..=10 {
+                let scale = center + i as f64 * step;
+                if scale > 0.0 {
+                    let error = self.error_with(&self.parameters, scale);
+                    if error < best.0 {
+                        best = (error, scale);
+                    }
+                }
+            }
+        }
+
+        self.scale = best.1;
+        self.scale
+    }
+
+    /// Runs `iterations` steps of gradient descent with Adam over the whole set, calling
+    /// `progress` with the iteration and the error every so often.
+    pub fn descend(&mut self, iterations: usize, mut progress: impl FnMut(usize, f64)) {
+        let count = self.parameters.len();
+        let (mut momentum, mut velocity) = (vec![0.0; count], vec![0.0; count]);
+        let slope = self.scale * 10f64.ln() / 400.0;
+
+        for iteration in 1..=iterations {
+            let mut gradient = vec![0.0; count];
+
+            for sample in &self.samples {
+                let expected = sigmoid(self.scale, sample.evaluate(&self.parameters));
+                let derivative = 2.0 * (expected - sample.result) * expected * (1.0 - expected) * slope;
+
+                for &(i, c) in &sample.coefficients {
+                    gradient[i as usize] += derivative * c as f64;
+                }
+            }
+
+            for i in 0..count {
+                let g = gradient[i] / self.samples.len().max(1) as f64;
+                momentum[i] = BETA1 * momentum[i] + (1.0 - BETA1) * g;
+                velocity[i] = BETA2 * velocity[i] + (1.0 - BETA2) * g * g;
+
+                let m = momentum[i] / (1.0 - BETA1.powi(iteration as i32));
+                let v = velocity[i] / (1.0 - BETA2.powi(iteration as i32));
+                self.parameters[i] -= LEARNING_RATE * m / (v.sqrt() + 1e-8);
+            }
+
+            if iteration % 100 == 0 || iteration == iterations {
+                progress(iteration, self.error());
+            }
+        }
+    }
+
+    /// Rounds the parameters, then moves each one a centipawn at a time for as long as that
+    /// lowers the error, for up to `passes` passes over all of them. Returns the passes made.
+    pub fn local_search(&mut self, passes: usize, mut progress: impl FnMut(usize, f64)) -> usize {
+        for parameter in self.parameters.iter_mut() {
+            *parameter = parameter.round();
+        }
+
+        //only the samples a parameter appears in change with it
+        let mut uses = vec![Vec::new(); self.parameters.len()];
+        for (j, sample) in self.samples.iter().enumerate() {
+            for &(i, c) in &sample.coefficients {
+                uses[i as usize].push((j, c as f64));
+            }
+        }
+
+        let mut scores: Vec<f64> = self.samples.iter().map(|sample| sample.evaluate(&self.parameters)).collect();
+        let scale = self.scale;
+        let squared = |result: f64, score: f64| (result - sigmoid(scale, score)).powi(2);
+
+        for pass in 1..=passes {
+            let mut improved = false;
+
+            for (i, users) in uses.iter().enumerate() {
+                for &step in &[1.0, -1.0] {
+                    let change: f64 = users.iter()
+                        .map(|&(j, c)| {
+                            let result = self.samples[j].result;
+                            squared(result, scores[j] + c * step) - squared(result, scores[j])
+                        })
+                        .sum();
+
+                    if change < 0.0 {
+                        self.parameters[i] += step;
+                        for &(j, c) in users {
+                            scores[j] += c * step;
+                        }
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+
+            progress(pass, self.error());
+            if !improved {
+                return pass;
+            }
+        }
+
+        passes
+    }
+
+    /// The tuned parameters, rounded to centipawns, in the order of `eval::tuning_parameters`.
+    pub fn parameters(&self) -> Vec<i32> {
+        self.parameters.iter().map(|parameter| parameter.round() as i32).collect()
+    }
+
+    /// The tuned parameters as Rust source, to replace the constants of the evaluation.
+    pub fn source(&self) -> String {
+        eval::format_parameters(&self.parameters())
+    }
+}
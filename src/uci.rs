@@ -0,0 +1,427 @@
+use super::chess960::Chess960;
+use super::error::Error;
+use super::eval;
+use super::nnue::Network;
+use super::search::{Engine, Futility, Listener, Reductions, SearchEvent};
+use super::skill::{Skill, MAX_LEVEL};
+use super::time::{TimeControl, TimeManager};
+use super::{seeded_rng, ChessState, Color, Move};
+
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+//deepest a search goes when go names no depth, which the clock or stop ends long before
+const MAX_DEPTH: u32 = 64;
+
+const DEFAULT_HASH: usize = 16;
+const MAX_HASH: usize = 65536;
+const MAX_THREADS: usize = 256;
+const MAX_CONTEMPT: i32 = 100;
+const MAX_MARGIN: i32 = 1000;
+const MAX_WEIGHT: i32 = 3000;
+
+//everything setoption changes, kept so the engine can be built again with a new table size
+struct Options {
+    hash: usize,
+    //huge pages for the table, only with the huge-pages feature on a platform that has them
+    large_pages: bool,
+    threads: usize,
+    contempt: i32,
+    //what a repetition costs on top of contempt when the engine stands better
+    shuffle: i32,
+    //late move reductions, off to see what they cost in strength or for analysis
+    reductions: Reductions,
+    //futility pruning, with margins a tuner can set
+    futility: Futility,
+    skill: u32,
+    network: Option<Arc<Network>>,
+    chess960: bool,
+}
+
+impl Options {
+    fn engine(&self) -> Engine {
+        let mut engine = Engine::with_hash(self.hash, self.large_pages);
+        engine.set_threads(self.threads);
+        engine.set_contempt(self.contempt);
+        engine.set_shuffle(self.shuffle);
+        engine.set_reductions(self.reductions);
+        engine.set_futility(self.futility);
+        engine.set_network(self.network.clone());
+        engine
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: Option<&&str>) -> Result<T, Error> {
+    value.and_then(|value| value.parse().ok())
+        .ok_or_else(|| Error::Uci(format!("{} needs a number", name)))
+}
+
+//clock times in milliseconds, some GUIs send negative ones when the clock is nearly out
+fn millis(name: &str, value: Option<&&str>) -> Result<Duration, Error> {
+    let millis: i64 = parse(name, value)?;
+    Ok(Duration::from_millis(millis.max(0) as u64))
+}
+
+//moves in coordinates, as UCI writes them, played out from state
+fn line(state: &ChessState, moves: &[Move]) -> String {
+    let mut position = state.clone();
+    let mut line = Vec::new();
+    for &action in moves {
+        line.push(position.coordinates(action));
+        position.apply_move(action);
+    }
+    line.join(" ")
+}
+
+//an info line for every depth finished, searching from state
+fn info(state: &ChessState) -> Listener {
+    let state = state.clone();
+    let started = Instant::now();
+
+    Box::new(move |event| if let SearchEvent::Depth { depth, score, pv, nodes, nps, hashfull } = event {
+        println!("info depth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+            depth, score, nodes, nps, hashfull, started.elapsed().as_millis(), line(&state, pv));
+    })
+}
+
+//the engine and the position the GUI set up, and the search running on its own thread, which
+//has the engine until it returns it
+struct Session {
+    options: Options,
+    engine: Option<Engine>,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<Engine>>,
+    state: ChessState,
+    //hashes of the positions before state, for the search to see repetitions
+    game: Vec<u64>,
+}
+
+impl Session {
+    fn new() -> Self {
+        let options = Options {
+            hash: DEFAULT_HASH,
+            large_pages: false,
+            threads: 1,
+            contempt: 0,
+            shuffle: 0,
+            reductions: Reductions::default(),
+            futility: Futility::default(),
+            skill: MAX_LEVEL,
+            network: None,
+            chess960: false,
+        };
+        let engine = options.engine();
+
+        Self { stop: engine.stop_flag(), engine: Some(engine), options, search: None, state: ChessState::default(), game: Vec::new() }
+    }
+
+    //false once the GUI quits
+    fn command(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+
+        let result = match words.next() {
+            Some("uci") => {
+                self.identify();
+                Ok(())
+            }
+            Some("isready") => {
+                println!("readyok");
+                Ok(())
+            }
+            Some("setoption") => self.set_option(line),
+            Some("ucinewgame") => {
+                self.engine().clear();
+                Ok(())
+            }
+            Some("position") => self.set_position(words.collect()),
+            Some("go") => self.go(words.collect()),
+            Some("stop") => {
+                self.stop_search();
+                Ok(())
+            }
+            Some("quit") => return false,
+            //anything else is ignored, as the protocol asks
+            _ => Ok(()),
+        };
+
+        if let Err(error) = result {
+            println!("info string {}", error);
+        }
+        true
+    }
+
+    fn identify(&self) {
+        println!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        println!("id author {}", env!("CARGO_PKG_AUTHORS"));
+        println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH, MAX_HASH);
+        println!("option name LargePages type check default false");
+        println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+        println!("option name Contempt type spin default 0 min {} max {}", -MAX_CONTEMPT, MAX_CONTEMPT);
+        println!("option name Shuffle Penalty type spin default 0 min 0 max {}", MAX_CONTEMPT);
+        println!("option name Reductions type check default true");
+        let futility = Futility::default();
+        println!("option name Futility type check default true");
+        println!("option name Futility Margin type spin default {} min 0 max {}", futility.margin, MAX_MARGIN);
+        println!("option name Reverse Futility Margin type spin default {} min 0 max {}", futility.reverse_margin, MAX_MARGIN);
+        println!("option name Skill Level type spin default {} min 0 max {}", MAX_LEVEL, MAX_LEVEL);
+        println!("option name EvalFile type string default <empty>");
+        println!("option name UCI_Chess960 type check default false");
+        println!("option name Clear Hash type button");
+        for weight in eval::weights() {
+            println!("option name {} type spin default {} min {} max {}", weight.name, weight.default, -MAX_WEIGHT, MAX_WEIGHT);
+        }
+        println!("uciok");
+    }
+
+    //stops the running search, if any, and takes the engine back once it printed its move
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            match search.join() {
+                Ok(engine) => self.engine = Some(engine),
+                Err(_) => println!("info string the search failed"),
+            }
+        }
+    }
+
+    //the engine, stopping the search that has it, or a new one if the last search lost it
+    fn engine(&mut self) -> &mut Engine {
+        self.stop_search();
+        if self.engine.is_none() {
+            self.rebuild();
+        }
+        self.engine.as_mut().expect("The engine was just built.")
+    }
+
+    fn rebuild(&mut self) {
+        let engine = self.options.engine();
+        self.stop = engine.stop_flag();
+        self.engine = Some(engine);
+    }
+
+    //e.g. "setoption name Skill Level value 5", option names are not case sensitive
+    fn set_option(&mut self, line: &str) -> Result<(), Error> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let name_at = words.iter().position(|&word| word == "name")
+            .ok_or_else(|| Error::Uci("setoption needs a name".to_string()))?;
+        let value_at = words.iter().position(|&word| word == "value").filter(|&i| i > name_at).unwrap_or(words.len());
+
+        let name = words[name_at + 1..value_at].join(" ").to_lowercase();
+        let value = words.get(value_at + 1);
+        self.stop_search();
+
+        match name.as_str() {
+            "hash" => {
+                self.options.hash = parse::<usize>("Hash", value)?.clamp(1, MAX_HASH);
+                self.rebuild();
+            }
+            "largepages" => {
+                self.options.large_pages = value == Some(&"true");
+                self.rebuild();
+            }
+            "threads" => {
+                self.options.threads = parse::<usize>("Threads", value)?.clamp(1, MAX_THREADS);
+                let threads = self.options.threads;
+                self.engine().set_threads(threads);
+            }
+            "contempt" => {
+                self.options.contempt = parse::<i32>("Contempt", value)?.clamp(-MAX_CONTEMPT, MAX_CONTEMPT);
+                let contempt = self.options.contempt;
+                self.engine().set_contempt(contempt);
+            }
+            "shuffle penalty" => {
+                self.options.shuffle = parse::<i32>("Shuffle Penalty", value)?.clamp(0, MAX_CONTEMPT);
+                let shuffle = self.options.shuffle;
+                self.engine().set_shuffle(shuffle);
+            }
+            "reductions" => {
+                self.options.reductions = if value == Some(&"true") { Reductions::default() } else { Reductions::none() };
+                let reductions = self.options.reductions;
+                self.engine().set_reductions(reductions);
+            }
+            //turning pruning off and on again keeps the margins
+            "futility" => {
+                let pruning = if value == Some(&"true") { Futility::default() } else { Futility::none() };
+                let Futility { margin, reverse_margin, .. } = self.options.futility;
+                self.set_futility(Futility { margin, reverse_margin, ..pruning });
+            }
+            "futility margin" => {
+                let margin = parse::<i32>("Futility Margin", value)?.clamp(0, MAX_MARGIN);
+                self.set_futility(Futility { margin, ..self.options.futility });
+            }
+            "reverse futility margin" => {
+                let reverse_margin = parse::<i32>("Reverse Futility Margin", value)?.clamp(0, MAX_MARGIN);
+                self.set_futility(Futility { reverse_margin, ..self.options.futility });
+            }
+            "skill level" => self.options.skill = parse::<u32>("Skill Level", value)?.min(MAX_LEVEL),
+            "evalfile" => {
+                let path = words.get(value_at + 1..).map(|path| path.join(" ")).unwrap_or_default();
+                self.options.network = match path.as_str() {
+                    "" | "<empty>" => None,
+                    path => Some(Arc::new(Network::load(Path::new(path))?)),
+                };
+
+                let network = self.options.network.clone();
+                self.engine().set_network(network);
+            }
+            "uci_chess960" => self.options.chess960 = value == Some(&"true"),
+            "clear hash" => self.engine().clear(),
+            //evaluation weights, e.g. "setoption name KnightValue value 350"
+            name if eval::weights().iter().any(|weight| weight.name.eq_ignore_ascii_case(name)) => {
+                eval::set_weight(name, parse::<i32>(name, value)?.clamp(-MAX_WEIGHT, MAX_WEIGHT));
+            }
+            _ => return Err(Error::Uci(format!("there is no option {}", name))),
+        }
+
+        Ok(())
+    }
+
+    fn set_futility(&mut self, futility: Futility) {
+        self.options.futility = futility;
+        self.engine().set_futility(futility);
+    }
+
+    //"startpos" or "fen <FEN>", optionally followed by "moves" and the moves played since
+    fn set_position(&mut self, words: Vec<&str>) -> Result<(), Error> {
+        let moves_at = words.iter().position(|&word| word == "moves").unwrap_or(words.len());
+
+        let mut state = match words.first() {
+            Some(&"startpos") if self.options.chess960 => Chess960::position(Chess960::STANDARD),
+            Some(&"startpos") => ChessState::default(),
+            Some(&"fen") => ChessState::parse_fen(&words[1..moves_at].join(" "))?,
+            _ => return Err(Error::Uci("position needs startpos or a FEN".to_string())),
+        };
+        state.chess960 |= self.options.chess960;
+
+        let mut game = Vec::new();
+        for text in words.iter().skip(moves_at + 1) {
+            let action = state.find_move(text)?;
+            game.push(state.hash());
+            state.apply_move(action);
+        }
+
+        self.state = state;
+        self.game = game;
+        Ok(())
+    }
+
+    //starts a search of the position on its own thread, which prints the best move when done
+    fn go(&mut self, words: Vec<&str>) -> Result<(), Error> {
+        let mut control = TimeControl::new(Duration::from_secs(0), Duration::from_secs(0));
+        let mut clock = false;
+        let (mut depth, mut nodes, mut movetime, mut root_moves) = (None, None, None, None);
+
+        let mut i = 0;
+        while i < words.len() {
+            let value = words.get(i + 1);
+            match words[i] {
+                "wtime" | "btime" | "winc" | "binc" => {
+                    let color = if words[i].starts_with('w') { Color::White } else { Color::Black } as usize;
+                    if words[i].ends_with("time") {
+                        control.time[color] = millis(words[i], value)?;
+                    } else {
+                        control.increment[color] = millis(words[i], value)?;
+                    }
+                    clock = true;
+                    i += 1;
+                }
+                "movestogo" => {
+                    control.moves_to_go = Some(parse("movestogo", value)?);
+                    i += 1;
+                }
+                "depth" => {
+                    depth = Some(parse("depth", value)?);
+                    i += 1;
+                }
+                "nodes" => {
+                    nodes = Some(parse("nodes", value)?);
+                    i += 1;
+                }
+                "movetime" => {
+                    movetime = Some(millis("movetime", value)?);
+                    i += 1;
+                }
+                //the moves run until the next word that is not one
+                "searchmoves" => {
+                    let mut moves = Vec::new();
+                    while let Some(action) = words.get(i + 1).and_then(|text| self.state.find_move(text).ok()) {
+                        moves.push(action);
+                        i += 1;
+                    }
+                    root_moves = Some(moves);
+                }
+                //infinite, and anything unknown, leave the search unlimited
+                _ => {}
+            }
+            i += 1;
+        }
+
+        let mut time = match movetime {
+            Some(movetime) => TimeManager::fixed(movetime),
+            None if clock => TimeManager::new(&control, self.state.active),
+            None => TimeManager::infinite(),
+        };
+
+        let skill = Some(Skill::new(self.options.skill)).filter(|skill| skill.level() < MAX_LEVEL);
+        let state = self.state.clone();
+
+        self.engine();
+        let mut engine = self.engine.take().expect("The engine is back once no search runs.");
+        engine.set_root_moves(root_moves);
+        engine.set_game(self.game.clone());
+        //the limit holds for each depth, so the whole search may go somewhat over it
+        engine.set_node_limit(nodes);
+        engine.set_listener(Some(info(&state)));
+        self.stop.store(false, Ordering::Relaxed);
+
+        self.search = Some(thread::spawn(move || {
+            let (best, ponder) = match skill {
+                Some(skill) => (skill.choose(&mut engine, &state, &mut seeded_rng(None).1), None),
+                None => {
+                    let result = engine.search_timed(&state, &mut time, depth.unwrap_or(MAX_DEPTH));
+                    (result.best, result.pv.get(1).copied())
+                }
+            };
+
+            //the reply the search expects is offered for pondering. a mated or stalemated
+            //position has no move to give
+            match best {
+                Some(best) => {
+                    let mut after = state.clone();
+                    after.apply_move(best);
+                    match ponder {
+                        Some(ponder) => println!("bestmove {} ponder {}", state.coordinates(best), after.coordinates(ponder)),
+                        None => println!("bestmove {}", state.coordinates(best)),
+                    }
+                }
+                None => println!("bestmove 0000"),
+            }
+
+            engine
+        }));
+
+        Ok(())
+    }
+}
+
+/// Speaks UCI on stdin and stdout until the GUI quits, for playing in GUIs such as Arena or
+/// Cute Chess and as a Lichess bot. Searches run on their own thread, so "stop" and "isready"
+/// are answered while the engine thinks.
+pub fn run() {
+    let mut session = Session::new();
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) if session.command(&line) => {}
+            _ => break,
+        }
+    }
+
+    session.stop_search();
+}
@@ -0,0 +1,199 @@
+use super::{ChessState, Color, FenError, FenField, Move, Piece, PIECE_TYPE_COUNT, PLAYER_COUNT};
+
+//crazyhouse and three-check: their positions and games are read and written with what they
+//carry beyond the board, the pockets of captured pieces and the checks given, though the
+//engine plays neither. drops in particular are not moves here
+
+//pockets are written queens first, as other programs write them
+const POCKET_ORDER: [Piece; 5] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn];
+
+//checks that win a game of three-check
+const WINNING_CHECKS: u32 = 3;
+
+impl ChessState {
+    /// Makes the position a crazyhouse one, with empty pockets unless it has some already.
+    pub fn start_crazyhouse (&mut self) {
+        self.pockets.get_or_insert([[0; PIECE_TYPE_COUNT]; PLAYER_COUNT]);
+    }
+
+    /// Makes the position a three-check one, no checks given unless some were already.
+    pub fn start_three_check (&mut self) {
+        self.checks.get_or_insert([0; PLAYER_COUNT]);
+    }
+
+    //before action is applied: in crazyhouse what it takes goes to the mover's pocket, a
+    //promoted piece as the pawn it was, and promoted pieces are followed to their new square
+    pub fn pocket_capture (&mut self, action: Move) {
+        let taken = self.captured(action);
+        let promoted = self.promoted;
+        let active = self.active as usize;
+
+        if let (Some(pockets), Some(piece)) = (self.pockets.as_mut(), taken) {
+            let piece = if promoted.empty_at(action.dest) { piece } else { Piece::Pawn };
+            pockets[active][piece as usize] += 1;
+        }
+
+        self.promoted = self.promoted.clear_pos(action.dest);
+        if !promoted.empty_at(action.origin) || action.promotion.is_some() {
+            self.promoted = self.promoted.clear_pos(action.origin).add_pos(action.dest);
+        }
+    }
+
+    //after a move is applied: in three-check a check counts for the side that just moved
+    pub fn count_check (&mut self) {
+        let mover = self.active.opposite() as usize;
+        let check = self.in_check();
+
+        if let (Some(checks), true) = (self.checks.as_mut(), check) {
+            checks[mover] += 1;
+        }
+    }
+
+    /// The side that has given three checks in three-check, which wins.
+    pub fn three_check_winner (&self) -> Option<Color> {
+        let checks = self.checks?;
+        [Color::White, Color::Black].iter().copied().find(|&color| checks[color as usize] >= WINNING_CHECKS)
+    }
+
+    /// Reads the pieces between the brackets of "[Qn]", or after the ninth slash, into pockets
+    /// by color and piece. `start` is the text's offset into the FEN.
+    pub fn parse_pockets (text: &str, start: usize) -> Result<[[u32; PIECE_TYPE_COUNT]; PLAYER_COUNT], FenError> {
+        let mut pockets = [[0; PIECE_TYPE_COUNT]; PLAYER_COUNT];
+
+        for (i, c) in text.char_indices().filter(|&(_, c)| c != '-') {
+            let piece = Piece::from_letter(c.to_ascii_lowercase())
+                .filter(|&piece| piece != Piece::King)
+                .ok_or_else(|| FenError::invalid(FenField::Placement, start, text, i))?;
+            let color = if c.is_uppercase() { Color::White } else { Color::Black };
+            pockets[color as usize][piece as usize] += 1;
+        }
+
+        Ok(pockets)
+    }
+
+    //the pockets as FEN writes them after the board, e.g. "[QNpp]"
+    pub fn pockets_fen (&self) -> Option<String> {
+        let pockets = self.pockets?;
+        let mut text = String::from("[");
+
+        for &color in &[Color::White, Color::Black] {
+            for &piece in POCKET_ORDER.iter() {
+                for _ in 0..pockets[color as usize][piece as usize] {
+                    text.push(piece.letter(color));
+                }
+            }
+        }
+
+        text.push(']');
+        Some(text)
+    }
+
+    /// Reads the checks each side has given from a field like "+2+0", as Lichess appends it
+    /// to the FEN of a three-check game. `start` is the field's offset into the FEN.
+    pub fn parse_checks (field: &str, start: usize) -> Result<[u32; PLAYER_COUNT], FenError> {
+        let invalid = |i| FenError::invalid(FenField::Checks, start, field, i);
+
+        let mut parts = field.split('+');
+        if parts.next() != Some("") {
+            return Err(invalid(0));
+        }
+
+        let mut checks = [0; PLAYER_COUNT];
+        let mut offset = 1;
+        for count in checks.iter_mut() {
+            let part = parts.next().ok_or_else(|| invalid(field.len().saturating_sub(1)))?;
+            *count = FenError::counter(FenField::Checks, start + offset, part)?;
+            offset += part.len() + 1;
+        }
+
+        match parts.next() {
+            Some(_) => Err(invalid(offset - 1)),
+            None => Ok(checks),
+        }
+    }
+
+    //the checks as the FEN's last field, e.g. "+2+0"
+    pub fn checks_fen (&self) -> Option<String> {
+        self.checks.map(|checks| format!("+{}+{}", checks[0], checks[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pgn;
+    use super::*;
+
+    #[test]
+    fn crazyhouse_pockets_and_promotions_round_trip() {
+        let fen = "r1bqk2r/ppp2ppp/2n5/3Q~4/8/8/PPP2PPP/RNB1KBNR[QNpp] b KQkq - 0 7";
+        assert_eq!(ChessState::parse_fen(fen).unwrap().to_fen(), fen);
+
+        //a ninth rank reads like brackets
+        let state = ChessState::parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR/Pn w KQkq - 0 1").unwrap();
+        assert_eq!(state.to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Pn] w KQkq - 0 1");
+
+        assert!(ChessState::parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[K] w KQkq - 0 1").is_err());
+        assert!(ChessState::parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Q w KQkq - 0 1").is_err());
+    }
+
+    #[test]
+    fn captures_fill_the_pocket_and_promoted_pieces_go_back_as_pawns() {
+        let mut state = ChessState::parse_fen("r3k3/8/8/8/8/8/8/R3K3[] w - - 0 1").unwrap();
+        state.apply_move(state.find_move("a1a8").unwrap());
+        assert_eq!(state.to_fen(), "R3k3/8/8/8/8/8/8/4K3[R] b - - 0 1");
+
+        let mut state = ChessState::parse_fen("4k3/1P6/8/8/8/8/1r6/4K3[] w - - 0 1").unwrap();
+        state.apply_move(state.find_move("b7b8q").unwrap());
+        assert_eq!(state.to_fen(), "1Q~2k3/8/8/8/8/8/1r6/4K3[] b - - 0 1");
+
+        state.apply_move(state.find_move("b2b8").unwrap());
+        assert_eq!(state.to_fen(), "1r2k3/8/8/8/8/8/8/4K3[p] w - - 0 2");
+    }
+
+    #[test]
+    fn three_check_counts_round_trip_and_win() {
+        let fen = "rnbqkbnr/ppp2ppp/8/3pp3/4P3/5Q2/PPPP1PPP/RNB1KBNR w KQkq - 0 3 +1+0";
+        assert_eq!(ChessState::parse_fen(fen).unwrap().to_fen(), fen);
+
+        let mut state = ChessState::parse_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0").unwrap();
+        let check = state.find_move("a1a8").unwrap();
+        state.apply_move(check);
+        assert_eq!(state.checks, Some([3, 0]));
+        assert_eq!(state.three_check_winner(), Some(Color::White));
+
+        assert!(ChessState::parse_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2").is_err());
+        assert!(ChessState::parse_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 2+0").is_err());
+    }
+
+    #[test]
+    fn variant_games_round_trip_through_pgn() {
+        let text = "[Variant \"Three-check\"]\n\n1. e4 e5 2. Bc4 Nc6 3. Bxf7+ Kxf7 4. Qh5+ g6 5. Qxg6+ *\n";
+        let tree = pgn::parse_pgn(text).unwrap().remove(0);
+        assert_eq!(tree.moves.last().unwrap().state.checks, Some([3, 0]));
+
+        let mut game = pgn::Game::from_position(tree.start.clone());
+        for &action in &tree.mainline() {
+            game.push(action);
+        }
+        let written = game.to_pgn();
+        assert!(written.contains("[Variant \"Three-check\"]"));
+        assert!(!written.contains("[FEN"));
+        assert!(written.ends_with("1-0\n"));
+
+        let again = pgn::parse_pgn(&written).unwrap().remove(0);
+        assert_eq!(again.mainline(), tree.mainline());
+        assert_eq!(again.start.to_fen(), tree.start.to_fen());
+
+        let text = "[Variant \"Crazyhouse\"]\n\n1. e4 d5 2. exd5 Qxd5 *\n";
+        let tree = pgn::parse_pgn(text).unwrap().remove(0);
+        let last = &tree.moves.last().unwrap().state;
+        assert_eq!(last.to_fen(), "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w KQkq - 0 3");
+
+        let mut game = pgn::Game::from_position(tree.start.clone());
+        for &action in &tree.mainline() {
+            game.push(action);
+        }
+        let again = pgn::parse_pgn(&game.to_pgn()).unwrap().remove(0);
+        assert_eq!(again.moves.last().unwrap().state.to_fen(), last.to_fen());
+    }
+}
@@ -0,0 +1,262 @@
+use super::error::Error;
+use super::pgn;
+use super::search::Engine;
+use super::skill::{Skill, MAX_LEVEL};
+use super::time::TimeManager;
+use super::{adjudicate_kpk, seeded_rng, ChessState, Color};
+
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+//deepest a search goes, which the move time ends long before
+const MAX_DEPTH: u32 = 64;
+//the engine's time for each move in milliseconds, unless the client asks for another
+const DEFAULT_MOVETIME: u64 = 1000;
+//an engine move holds the connection's thread, so clients cannot ask for long searches
+const MAX_MOVETIME: u64 = 10_000;
+
+fn websocket_error(error: impl ToString) -> Error {
+    Error::WebSocket(error.to_string())
+}
+
+//what clients send, as JSON text messages. a new game starts from the FEN if given, with the
+//client playing white unless it asks for black, and is only adjudicated by the bitbase once it
+//reaches king and pawn against king when the client asks for it
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Request {
+    New {
+        fen: Option<String>,
+        color: Option<String>,
+        skill: Option<u32>,
+        movetime: Option<u64>,
+        #[serde(default)]
+        adjudicate: bool,
+    },
+    //in coordinates, e.g. "e2e4" or "e7e8q"
+    Move {
+        #[serde(rename = "move")]
+        text: String,
+    },
+    Resign,
+}
+
+//what the server sends back: the position after every move with the moves legal in it, the
+//engine's moves, the end of the game with its PGN, and requests that were refused
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Update {
+    State {
+        fen: String,
+        active: String,
+        check: bool,
+        legal: Vec<String>,
+    },
+    Reply {
+        #[serde(rename = "move")]
+        text: String,
+        san: String,
+    },
+    Over {
+        result: String,
+        reason: String,
+        pgn: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+fn color_name(color: Color) -> String {
+    format!("{:?}", color).to_lowercase()
+}
+
+//a game between the client and the engine
+struct Game {
+    game: pgn::Game,
+    player: Color,
+    skill: Option<Skill>,
+    movetime: Duration,
+    adjudicate: bool,
+    //set once the game ended, so no more moves are taken
+    over: bool,
+}
+
+//one client's connection, which plays one game at a time against an engine of its own
+struct Connection {
+    socket: WebSocket<TcpStream>,
+    engine: Engine,
+    rng: StdRng,
+    game: Option<Game>,
+}
+
+impl Connection {
+    fn send(&mut self, update: &Update) -> Result<(), Error> {
+        let text = serde_json::to_string(update).expect("Updates always serialize.");
+        self.socket.write_message(Message::Text(text)).map_err(websocket_error)
+    }
+
+    fn refuse(&mut self, message: impl ToString) -> Result<(), Error> {
+        self.send(&Update::Error { message: message.to_string() })
+    }
+
+    //answers each request until the client closes the connection
+    fn serve(&mut self) -> Result<(), Error> {
+        loop {
+            let text = match self.socket.read_message() {
+                Ok(Message::Text(text)) => text,
+                //pings are answered by tungstenite itself
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Ok(_) => continue,
+                Err(error) => return Err(websocket_error(error)),
+            };
+
+            match serde_json::from_str(&text) {
+                Ok(Request::New { fen, color, skill, movetime, adjudicate }) => self.start(fen, color, skill, movetime, adjudicate)?,
+                Ok(Request::Move { text }) => self.play(&text)?,
+                Ok(Request::Resign) => self.resign()?,
+                Err(error) => self.refuse(error)?,
+            }
+        }
+    }
+
+    fn start(&mut self, fen: Option<String>, color: Option<String>, skill: Option<u32>, movetime: Option<u64>,
+             adjudicate: bool) -> Result<(), Error> {
+        let start = match fen.map(|fen| ChessState::parse_fen(&fen)) {
+            Some(Ok(state)) => state,
+            Some(Err(error)) => return self.refuse(error),
+            None => ChessState::default(),
+        };
+
+        let player = match color.as_deref() {
+            None | Some("white") => Color::White,
+            Some("black") => Color::Black,
+            Some(other) => return self.refuse(format!("{} is not a color, white or black", other)),
+        };
+
+        let mut game = pgn::Game::from_position(start);
+        game.set_tag("White", if player == Color::White { "Player" } else { "Engine" });
+        game.set_tag("Black", if player == Color::Black { "Player" } else { "Engine" });
+
+        self.engine.clear();
+        self.game = Some(Game {
+            game,
+            player,
+            skill: skill.map(Skill::new).filter(|skill| skill.level() < MAX_LEVEL),
+            movetime: Duration::from_millis(movetime.unwrap_or(DEFAULT_MOVETIME).min(MAX_MOVETIME)),
+            adjudicate,
+            over: false,
+        });
+
+        self.advance()
+    }
+
+    fn play(&mut self, text: &str) -> Result<(), Error> {
+        let game = match self.game.as_mut() {
+            Some(game) if !game.over => game,
+            Some(_) => return self.refuse("the game is over"),
+            None => return self.refuse("there is no game, start one first"),
+        };
+
+        let state = game.game.state();
+        if state.active != game.player {
+            return self.refuse("it is the engine's turn");
+        }
+
+        match state.find_move(text) {
+            Ok(action) => game.game.push(action),
+            Err(error) => return self.refuse(error),
+        }
+
+        self.advance()
+    }
+
+    fn resign(&mut self) -> Result<(), Error> {
+        match self.game.as_mut() {
+            Some(game) if !game.over => {
+                game.game.resign(game.player);
+                let reason = format!("{:?} resigned.", game.player);
+                self.finish(reason)
+            }
+            _ => self.refuse("there is no game to resign"),
+        }
+    }
+
+    //sends the position, then lets the engine move while it is its turn, until it is the
+    //client's turn or the game is over
+    fn advance(&mut self) -> Result<(), Error> {
+        loop {
+            let state = self.game.as_ref().expect("Only games in progress advance.").game.state().clone();
+
+            self.send(&Update::State {
+                fen: state.to_fen(),
+                active: color_name(state.active),
+                check: !state.checkers().is_empty(),
+                legal: state.legal_moves().iter().map(|&action| state.coordinates(action)).collect(),
+            })?;
+
+            if let Some(outcome) = state.outcome() {
+                return self.finish(outcome.to_string());
+            }
+
+            let game = self.game.as_mut().expect("Only games in progress advance.");
+            if game.adjudicate {
+                if let Some(reason) = adjudicate_kpk(&state, &mut game.game) {
+                    return self.finish(reason);
+                }
+            }
+
+            if state.active == game.player {
+                return Ok(());
+            }
+
+            let hashes = game.game.hashes();
+            self.engine.set_game(hashes[..hashes.len() - 1].to_vec());
+
+            //the position has a legal move, as it has no outcome
+            let action = match game.skill {
+                Some(skill) => skill.choose(&mut self.engine, &state, &mut self.rng),
+                None => self.engine.search_timed(&state, &mut TimeManager::fixed(game.movetime), MAX_DEPTH).best,
+            }.expect("A position without an outcome has a move.");
+
+            game.game.push(action);
+            self.send(&Update::Reply { text: state.coordinates(action), san: state.san(action) })?;
+        }
+    }
+
+    fn finish(&mut self, reason: String) -> Result<(), Error> {
+        let game = self.game.as_mut().expect("Only games in progress finish.");
+        game.over = true;
+
+        let update = Update::Over { result: game.game.result().to_string(), reason, pgn: game.game.to_pgn() };
+        self.send(&update)
+    }
+}
+
+fn accept(stream: TcpStream) -> Result<(), Error> {
+    let socket = tungstenite::accept(stream).map_err(websocket_error)?;
+    let mut connection = Connection { socket, engine: Engine::new(), rng: seeded_rng(None).1, game: None };
+    connection.serve()
+}
+
+/// Serves games against the engine over WebSockets on `port`, for browser frontends. Clients
+/// send JSON messages to start a game, to move in coordinates and to resign, and are sent the
+/// position after every move with its legal moves, the engine's replies and the result once
+/// the game is over. Each connection has its own thread and engine.
+pub fn run(port: u16) -> Result<(), Error> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Listening on port {}.", port);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || if let Err(error) = accept(stream) {
+            eprintln!("{}", error);
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use super::{cache, ChessState, Color, Piece, PIECE_TYPE_COUNT, PLAYER_COUNT};
+
+use lazy_static::lazy_static;
+
+pub struct ZobristKeys {
+    pub pieces: [[[u64; 64]; PIECE_TYPE_COUNT]; PLAYER_COUNT],
+    //by the file of the rook castling is still allowed with
+    pub castling: [[u64; 8]; PLAYER_COUNT],
+    pub en_passant: [u64; 8],
+    pub black_to_move: u64,
+    pub duck: [u64; 64],
+}
+
+//splitmix64, so the keys are the same on every build and platform
+fn next_key(seed: &mut u64) -> u64 {
+    *seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl ZobristKeys {
+    fn new() -> Self {
+        let mut seed = 0;
+        let mut keys = Self {
+            pieces: [[[0; 64]; PIECE_TYPE_COUNT]; PLAYER_COUNT],
+            castling: [[0; 8]; PLAYER_COUNT],
+            en_passant: [0; 8],
+            black_to_move: 0,
+            duck: [0; 64],
+        };
+
+        for key in keys.pieces.iter_mut().flatten().flatten() {
+            *key = next_key(&mut seed);
+        }
+
+        for key in keys.castling.iter_mut().flatten().chain(keys.en_passant.iter_mut()) {
+            *key = next_key(&mut seed);
+        }
+
+        keys.black_to_move = next_key(&mut seed);
+
+        //drawn last so the keys of regular chess stay the same
+        for key in keys.duck.iter_mut() {
+            *key = next_key(&mut seed);
+        }
+
+        keys
+    }
+}
+
+lazy_static! {
+    pub static ref zobrist_keys: ZobristKeys = ZobristKeys::new();
+}
+
+pub fn piece_key(color: Color, piece: Piece, pos: u32) -> u64 {
+    zobrist_keys.pieces[color as usize][piece as usize][pos as usize]
+}
+
+impl ChessState {
+    /// Zobrist hash of the position: pieces, side to move, castling rights, a capturable
+    /// en passant square and the duck in duck chess. Moves update it as they are made.
+    pub fn hash (&self) -> u64 {
+        self.key
+    }
+
+    //castling rights, en passant and the side to move, the part of the hash a move
+    //replaces as a whole rather than square by square
+    pub fn rules_key (&self) -> u64 {
+        let mut hash = 0;
+
+        for &color in &[Color::White, Color::Black] {
+            for file in self.castle_ks[color as usize].iter().chain(self.castle_qs[color as usize].iter()) {
+                hash ^= zobrist_keys.castling[color as usize][*file as usize];
+            }
+        }
+
+        //only a capturable en passant square makes the position different, so that
+        //transpositions with and without a double pawn push hash the same
+        if let Some(en_passant) = self.en_passant {
+            let dest = en_passant.solo_pos();
+            let capturers = cache.pawn_attacks(self.active.opposite(), dest) & self.pieces_of(self.active, Piece::Pawn);
+
+            if !capturers.is_empty() {
+                hash ^= zobrist_keys.en_passant[(dest % 8) as usize];
+            }
+        }
+
+        if self.active == Color::Black {
+            hash ^= zobrist_keys.black_to_move;
+        }
+
+        hash
+    }
+
+    /// The hash computed from scratch, which the incrementally updated one must always equal.
+    pub fn compute_hash (&self) -> u64 {
+        let mut hash = self.rules_key();
+
+        for &color in &[Color::White, Color::Black] {
+            for &piece in Piece::kinds() {
+                for pos in self.pieces_of(color, piece).get_indices() {
+                    hash ^= piece_key(color, piece, pos);
+                }
+            }
+        }
+
+        if let Some(duck) = self.duck {
+            hash ^= zobrist_keys.duck[duck as usize];
+        }
+
+        hash
+    }
+}